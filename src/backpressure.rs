@@ -0,0 +1,92 @@
+//! How full a producer's queue is, so ingestion can slow down before sends start failing with
+//! [`QueueFull`](rdkafka::types::RDKafkaErrorCode::QueueFull).
+
+use std::time::Duration;
+
+use rdkafka::{ClientConfig, ClientContext};
+
+use crate::TypedProducer;
+
+/// librdkafka's defaults for `queue.buffering.max.messages` and `queue.buffering.max.kbytes`.
+const DEFAULT_MAX_RECORDS: usize = 100_000;
+const DEFAULT_MAX_KBYTES: u64 = 1_048_576;
+/// How long [`TypedProducer::await_capacity`] waits for a notification before checking anyway.
+const RECHECK_INTERVAL: Duration = Duration::from_millis(250);
+
+/// The queue limits a producer was configured with.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct QueueLimits {
+    max_records: usize,
+    max_bytes: u64,
+}
+impl QueueLimits {
+    pub(crate) fn new(config: &ClientConfig) -> Self {
+        let max_records = config.get("queue.buffering.max.messages");
+        let max_kbytes = config.get("queue.buffering.max.kbytes");
+
+        Self {
+            max_records: max_records
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(DEFAULT_MAX_RECORDS),
+            max_bytes: max_kbytes
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(DEFAULT_MAX_KBYTES)
+                * 1024,
+        }
+    }
+}
+
+/// A snapshot of how much of a producer's queue is in use.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QueueCapacity {
+    /// Records enqueued and not yet delivered or failed.
+    pub records: usize,
+    /// `queue.buffering.max.messages`.
+    pub max_records: usize,
+    /// Bytes enqueued, as of the latest statistics report. `None` unless
+    /// `statistics.interval.ms` is set.
+    pub bytes: Option<u64>,
+    /// `queue.buffering.max.kbytes`, in bytes.
+    pub max_bytes: u64,
+}
+impl QueueCapacity {
+    /// How full the queue is, from 0 to 1, by whichever of the two limits is closer.
+    pub fn fullness(&self) -> f64 {
+        let records = self.records as f64 / self.max_records.max(1) as f64;
+        let bytes = self
+            .bytes
+            .map_or(0.0, |bytes| bytes as f64 / self.max_bytes.max(1) as f64);
+        records.max(bytes).min(1.0)
+    }
+}
+
+impl<C: ClientContext + 'static> TypedProducer<C> {
+    pub fn queue_capacity(&self) -> QueueCapacity {
+        QueueCapacity {
+            records: self.in_flight_count(),
+            max_records: self.queue_limits.max_records,
+            bytes: self.stats().map(|stats| stats.queued_bytes),
+            max_bytes: self.queue_limits.max_bytes,
+        }
+    }
+    /// Waits until the queue is at most `max_fullness` full, as measured by
+    /// [`QueueCapacity::fullness`]. Await it before each send to slow down instead of failing
+    /// when brokers can't keep up.
+    ///
+    /// It wakes up to check again whenever a send through this producer completes or a
+    /// statistics report arrives. Byte usage is only updated with each report, so with
+    /// statistics enabled this can wait up to `statistics.interval.ms` longer than needed.
+    pub async fn await_capacity(&self, max_fullness: f64) {
+        loop {
+            let drained = self.drained.notified();
+            tokio::pin!(drained);
+            drained.as_mut().enable();
+            if self.queue_capacity().fullness() <= max_fullness {
+                return;
+            }
+            // Records whose delivery futures were dropped leave the queue without a
+            // notification, so check again now and then regardless.
+            let _ = tokio::time::timeout(RECHECK_INTERVAL, drained).await;
+        }
+    }
+}
@@ -0,0 +1,322 @@
+//! The typed API for applications without an async runtime. The producer and base consumer
+//! are built on librdkafka's threaded clients; the other wrappers drive the async clients on a
+//! private single-threaded runtime, blocking the calling thread until each call completes.
+
+use std::{
+    error::Error,
+    sync::mpsc::{self, Sender},
+    thread,
+    time::{Duration, Instant},
+};
+
+use tokio::runtime::{Builder, Runtime};
+
+use rdkafka::{
+    admin::TopicReplication,
+    consumer::{BaseConsumer, CommitMode, Consumer},
+    error::KafkaError,
+    message::{DeliveryResult, Header, OwnedHeaders, OwnedMessage},
+    producer::{BaseRecord, Producer, ProducerContext, ThreadedProducer},
+    types::RDKafkaErrorCode,
+    util::Timeout,
+    ClientConfig, ClientContext, Message, Timestamp,
+};
+
+use crate::{
+    error::{DeliveryError, RdkafkaExtError, SendError},
+    CreateTopicOutcome, Delivery, EncodedRecord, Topic, TypedAdmin, TypedConsumer, TypedMessage,
+};
+
+type DeliveryOutcome = Result<(i32, i64), (KafkaError, OwnedMessage)>;
+type BlockingRecord<'a> = BaseRecord<'a, [u8], [u8], Box<Sender<DeliveryOutcome>>>;
+
+/// Hands each delivery report to the thread waiting for it.
+struct BlockingContext;
+impl ClientContext for BlockingContext {}
+impl ProducerContext for BlockingContext {
+    type DeliveryOpaque = Box<Sender<DeliveryOutcome>>;
+
+    fn delivery(&self, result: &DeliveryResult<'_>, sender: Self::DeliveryOpaque) {
+        let outcome = match result {
+            Ok(message) => Ok((message.partition(), message.offset())),
+            Err((error, message)) => Err((error.clone(), message.detach())),
+        };
+        // The sender gave up waiting, so nobody needs to hear about it.
+        let _ = sender.send(outcome);
+    }
+}
+
+/// A [`TypedProducer`](crate::TypedProducer) for synchronous code. Sends block the calling
+/// thread until the record is delivered; librdkafka is polled on a background thread.
+pub struct TypedBlockingProducer {
+    inner: ThreadedProducer<BlockingContext>,
+    default_timeout: Timeout,
+}
+impl TypedBlockingProducer {
+    pub fn new(config: ClientConfig) -> Result<Self, RdkafkaExtError> {
+        Ok(Self {
+            inner: config.create_with_context(BlockingContext)?,
+            default_timeout: Timeout::Never,
+        })
+    }
+    /// How long [`send`](Self::send) waits for room in the producer queue. Defaults to waiting
+    /// forever.
+    pub fn with_default_timeout(mut self, timeout: impl Into<Timeout>) -> Self {
+        self.default_timeout = timeout.into();
+        self
+    }
+    pub fn default_timeout(&self) -> Timeout {
+        self.default_timeout
+    }
+    /// Blocks until every enqueued record has been delivered or failed, or `timeout` runs out.
+    pub fn flush(&self, timeout: impl Into<Timeout>) -> Result<(), RdkafkaExtError> {
+        Ok(self.inner.flush(timeout)?)
+    }
+    /// How many records have been enqueued but not yet delivered or failed.
+    pub fn in_flight_count(&self) -> usize {
+        self.inner.in_flight_count().max(0) as usize
+    }
+    /// Sends with the producer's [`default_timeout`](Self::default_timeout).
+    pub fn send<T: Topic>(
+        &self,
+        topic: &T,
+        payload: &T::Payload,
+        key: Option<&T::Key>,
+    ) -> Result<Delivery, SendError> {
+        self.send_with_timeout(topic, payload, key, self.default_timeout)
+    }
+    /// Like [`send`](Self::send), but waits up to `timeout` for room in the producer queue
+    /// instead of the default.
+    pub fn send_with_timeout<T: Topic>(
+        &self,
+        topic: &T,
+        payload: &T::Payload,
+        key: Option<&T::Key>,
+        timeout: impl Into<Timeout>,
+    ) -> Result<Delivery, SendError> {
+        let encoded = EncodedRecord::new(topic, payload, key)?;
+        let deadline = match timeout.into() {
+            Timeout::After(timeout) => Some(Instant::now() + timeout),
+            Timeout::Never => None,
+        };
+        let timestamp = Timestamp::now();
+
+        let (sender, receiver) = mpsc::channel();
+        let mut record = BaseRecord::with_opaque_to(&encoded.topic, Box::new(sender))
+            .payload(encoded.payload.as_slice())
+            .timestamp(timestamp.to_millis().unwrap_or_default());
+        if let Some(key) = &encoded.key {
+            record = record.key(key.as_slice());
+        }
+        if !encoded.payload_headers.is_empty() {
+            let headers = encoded.payload_headers.iter().fold(
+                OwnedHeaders::new_with_capacity(encoded.payload_headers.len()),
+                |headers, (name, value)| {
+                    headers.insert(Header {
+                        key: name,
+                        value: Some(value),
+                    })
+                },
+            );
+            record = record.headers(headers);
+        }
+        loop {
+            match self.inner.send(record) {
+                Ok(()) => break,
+                Err((KafkaError::MessageProduction(RDKafkaErrorCode::QueueFull), unsent))
+                    if deadline.is_none_or(|deadline| Instant::now() < deadline) =>
+                {
+                    record = unsent;
+                    thread::sleep(Duration::from_millis(10));
+                }
+                Err((error, unsent)) => {
+                    return Err(DeliveryError {
+                        error: error.into(),
+                        message: owned_message(&unsent, timestamp),
+                    }
+                    .into())
+                }
+            }
+        }
+
+        let outcome = match receiver.recv() {
+            Ok(outcome) => outcome,
+            // The producer was dropped with the record still queued.
+            Err(_) => Err((
+                KafkaError::Canceled,
+                OwnedMessage::new(None, None, encoded.topic.clone(), timestamp, -1, -1, None),
+            )),
+        };
+        let (partition, offset) = outcome.map_err(|(error, message)| DeliveryError {
+            error: error.into(),
+            message: Box::new(message),
+        })?;
+        Ok(Delivery {
+            partition,
+            offset,
+            timestamp,
+        })
+    }
+}
+
+/// A [`TypedConsumer`](crate::TypedConsumer) for synchronous code, built on librdkafka's
+/// `BaseConsumer`: nothing happens in the background, so [`poll`](Self::poll) must be called
+/// regularly, which also serves rebalances and commit callbacks.
+pub struct TypedBaseConsumer<T> {
+    inner: BaseConsumer,
+    topic: T,
+}
+impl<T: Topic> TypedBaseConsumer<T> {
+    pub fn new(client_config: ClientConfig, topic: T) -> Result<Self, RdkafkaExtError> {
+        let inner: BaseConsumer = client_config.create()?;
+        inner.subscribe(&[&topic.topic_string()])?;
+
+        Ok(Self { inner, topic })
+    }
+    pub fn topic(&self) -> &T {
+        &self.topic
+    }
+    /// Blocks for up to `timeout` waiting for a message, returning `None` if none arrived.
+    pub fn poll(
+        &self,
+        timeout: impl Into<Timeout>,
+    ) -> Result<Option<TypedMessage<'_, T>>, RdkafkaExtError> {
+        let message = self.inner.poll(timeout).transpose()?;
+        Ok(message.map(|message| TypedMessage {
+            message,
+            topic: self.topic.clone(),
+        }))
+    }
+    /// Receives messages forever, blocking between them.
+    pub fn iter(&self) -> impl Iterator<Item = Result<TypedMessage<'_, T>, RdkafkaExtError>> {
+        std::iter::from_fn(|| loop {
+            if let Some(message) = self.poll(Timeout::Never).transpose() {
+                return Some(message);
+            }
+        })
+    }
+    /// Commits the offset after `message`, so it isn't received again after a restart.
+    ///
+    /// Only needed with `enable.auto.commit` off.
+    pub fn commit_message(
+        &self,
+        message: &TypedMessage<'_, T>,
+        mode: CommitMode,
+    ) -> Result<(), RdkafkaExtError> {
+        Ok(self.inner.commit_message(&message.message, mode)?)
+    }
+    /// Commits the offsets stored for every assigned partition.
+    pub fn commit_consumer_state(&self, mode: CommitMode) -> Result<(), RdkafkaExtError> {
+        Ok(self.inner.commit_consumer_state(mode)?)
+    }
+}
+
+/// The runtime a blocking wrapper drives its client on.
+fn runtime() -> Result<Runtime, RdkafkaExtError> {
+    Builder::new_current_thread()
+        .enable_time()
+        .build()
+        .map_err(RdkafkaExtError::Runtime)
+}
+
+/// A [`TypedConsumer`] for synchronous code, with the same retries, poison policy and
+/// interceptors. Blocking calls must not be made from inside an async runtime.
+pub struct TypedBlockingConsumer<T> {
+    inner: TypedConsumer<T>,
+    runtime: Runtime,
+}
+impl<T: Topic> TypedBlockingConsumer<T> {
+    pub fn new(client_config: ClientConfig, topic: T) -> Result<Self, RdkafkaExtError> {
+        Self::from_consumer(TypedConsumer::new(client_config, topic)?)
+    }
+    /// Drives a consumer made elsewhere, e.g. with a poison policy or interceptors.
+    pub fn from_consumer(consumer: TypedConsumer<T>) -> Result<Self, RdkafkaExtError> {
+        Ok(Self {
+            inner: consumer,
+            runtime: runtime()?,
+        })
+    }
+    pub fn consumer(&self) -> &TypedConsumer<T> {
+        &self.inner
+    }
+    /// Blocks until a message arrives.
+    pub fn recv(&self) -> Result<TypedMessage<'_, T>, RdkafkaExtError> {
+        self.runtime.block_on(self.inner.recv())
+    }
+    /// Blocks for up to `timeout` waiting for a message, returning `None` if none arrived.
+    pub fn recv_timeout(
+        &self,
+        timeout: Duration,
+    ) -> Result<Option<TypedMessage<'_, T>>, RdkafkaExtError> {
+        self.runtime.block_on(self.inner.recv_timeout(timeout))
+    }
+    /// Like [`recv`](Self::recv), but only blocks for up to `max_messages` messages or
+    /// `max_wait`, whichever comes first.
+    pub fn recv_batch(
+        &self,
+        max_messages: usize,
+        max_wait: Duration,
+    ) -> Result<Vec<TypedMessage<'_, T>>, RdkafkaExtError> {
+        self.runtime
+            .block_on(self.inner.recv_batch(max_messages, max_wait))
+    }
+    pub fn commit_message(
+        &self,
+        message: &TypedMessage<'_, T>,
+        mode: CommitMode,
+    ) -> Result<(), RdkafkaExtError> {
+        self.inner.commit_message(message, mode)
+    }
+    /// Receives messages forever, passing each payload to `handler` as in
+    /// [`TypedConsumer::run`].
+    pub fn run<F, E>(&self, mut handler: F) -> Result<(), RdkafkaExtError>
+    where
+        F: FnMut(T::Payload) -> Result<(), E>,
+        E: Into<Box<dyn Error + Send + Sync>>,
+    {
+        self.runtime.block_on(
+            self.inner
+                .run(|payload| std::future::ready(handler(payload))),
+        )
+    }
+    /// Commits the stored offsets and leaves the group, as in [`TypedConsumer::close`].
+    pub fn close(self) -> Result<(), RdkafkaExtError> {
+        self.inner.close()
+    }
+}
+
+/// A [`TypedAdmin`] for synchronous code.
+pub struct TypedBlockingAdmin {
+    inner: TypedAdmin,
+    runtime: Runtime,
+}
+impl TypedBlockingAdmin {
+    pub fn new(client_config: ClientConfig) -> Result<Self, RdkafkaExtError> {
+        Ok(Self {
+            inner: TypedAdmin::new(client_config)?,
+            runtime: runtime()?,
+        })
+    }
+    pub fn create_topic(
+        &self,
+        topic: impl Topic,
+        num_partitions: i32,
+        replication: TopicReplication<'_>,
+    ) -> Result<CreateTopicOutcome, RdkafkaExtError> {
+        self.runtime
+            .block_on(self.inner.create_topic(topic, num_partitions, replication))
+    }
+}
+
+/// Copies a record that never made it into the producer's queue, to hand back to the caller.
+fn owned_message(record: &BlockingRecord<'_>, timestamp: Timestamp) -> Box<OwnedMessage> {
+    Box::new(OwnedMessage::new(
+        record.payload.map(<[u8]>::to_vec),
+        record.key.map(<[u8]>::to_vec),
+        record.topic.to_string(),
+        timestamp,
+        record.partition.unwrap_or(-1),
+        -1,
+        record.headers.clone(),
+    ))
+}
@@ -5,12 +5,14 @@ use std::time::Duration;
 
 use rdkafka::ClientConfig;
 
+use crate::error::ConfigValidationError;
+
 use self::{
     traits::{
         ApiTimeoutConfigBuilder, KafkaConfigBuilder, RetriesConfigBuilder, SaslConfigBuilder, Set,
         SslConfigBuilder,
     },
-    types::Reset,
+    types::{IsolationLevel, Partitioner, Reset},
 };
 
 #[derive(Default)]
@@ -26,6 +28,12 @@ impl ProducerConfigBuilder {
     pub fn build(self) -> ClientConfig {
         self.config
     }
+    /// Like [`build`](Self::build), but first checks that the required properties are present
+    /// and that no properties conflict with each other.
+    pub fn try_build(self) -> Result<ClientConfig, ConfigValidationError> {
+        validate(&self.config, &["bootstrap.servers"])?;
+        Ok(self.config)
+    }
 }
 impl Set for ProducerConfigBuilder {
     fn set(&mut self, key: &str, value: impl ToString) {
@@ -38,6 +46,16 @@ impl KafkaConfigBuilder for ProducerConfigBuilder {}
 impl RetriesConfigBuilder for ProducerConfigBuilder {}
 impl ProducerConfigBuilder {
     // producer specific ones
+
+    /// Partitioner used to map records without an explicit partition to one. `consistent_random`
+    /// hashes the key with CRC32 and picks a random partition for unkeyed records; pick
+    /// `murmur2_random` to place keys the same way the Java client does.
+    ///
+    /// Default: consistent_random
+    pub fn partitioner(mut self, partitioner: Partitioner) -> Self {
+        self.set("partitioner", partitioner);
+        self
+    }
 }
 
 #[derive(Default)]
@@ -53,6 +71,12 @@ impl ConsumerConfigBuilder {
     pub fn build(self) -> ClientConfig {
         self.config
     }
+    /// Like [`build`](Self::build), but first checks that the required properties are present
+    /// and that no properties conflict with each other.
+    pub fn try_build(self) -> Result<ClientConfig, ConfigValidationError> {
+        validate(&self.config, &["bootstrap.servers", "group.id"])?;
+        Ok(self.config)
+    }
 }
 impl Set for ConsumerConfigBuilder {
     fn set(&mut self, key: &str, value: impl ToString) {
@@ -172,7 +196,13 @@ impl ConsumerConfigBuilder {
         self.set("group.instance.id", id);
         self
     }
-    /* TODO: isolation.level */
+    /// Controls how to read messages written transactionally. If set to `read_committed`, the consumer will only return transactional messages which have been committed. If set to `read_uncommitted`, the consumer will return all messages, even transactional messages which have been aborted. Non-transactional messages will be returned unconditionally in either mode.
+    ///
+    /// Default: read_committed
+    pub fn isolation_level(mut self, level: IsolationLevel) -> Self {
+        self.set("isolation.level", level);
+        self
+    }
     /// The maximum delay between invocations of poll() when using consumer group management. This places an upper bound on the amount of time that the consumer can be idle before fetching more records. If poll() is not called before expiration of this timeout, then the consumer is considered failed and the group will rebalance in order to reassign the partitions to another member. For consumers using a non-null `group.instance.id` which reach this timeout, partitions will not be immediately reassigned. Instead, the consumer will stop sending heartbeats and partitions will be reassigned after expiration of `session.timeout.ms`. This mirrors the behavior of a static consumer which has shutdown.
     ///
     /// Default: 300000 (5 minutes)
@@ -187,6 +217,34 @@ impl ConsumerConfigBuilder {
         self.set("max.poll.records", count);
         self
     }
+    /// Maximum time the broker may wait to fill the fetch response with `fetch.min.bytes` of messages. Lower values reduce latency when topics are quiet, at the cost of more fetch requests.
+    ///
+    /// Default: 500
+    pub fn fetch_wait_max(mut self, wait: Duration) -> Self {
+        self.set("fetch.wait.max.ms", wait.as_millis());
+        self
+    }
+    /// How long to postpone the next fetch request for a topic+partition in case of a fetch error.
+    ///
+    /// Default: 500
+    pub fn fetch_error_backoff(mut self, backoff: Duration) -> Self {
+        self.set("fetch.error.backoff.ms", backoff.as_millis());
+        self
+    }
+    /// Minimum number of messages per topic+partition librdkafka tries to maintain in the local consumer queue. Higher values prefetch more, trading memory for throughput.
+    ///
+    /// Default: 100000
+    pub fn queued_min_messages(mut self, count: usize) -> Self {
+        self.set("queued.min.messages", count);
+        self
+    }
+    /// Maximum number of kilobytes of queued pre-fetched messages in the local consumer queue. If using the high-level consumer this setting applies to the single consumer queue, regardless of the number of partitions. This value may be overshot by `fetch.max.bytes`. This property has higher priority than `queued.min.messages`.
+    ///
+    /// Default: 65536 (64 mebibytes)
+    pub fn queued_max_messages_kbytes(mut self, kbytes: usize) -> Self {
+        self.set("queued.max.messages.kbytes", kbytes);
+        self
+    }
     /* TODO: more after here */
 }
 
@@ -203,6 +261,12 @@ impl AdminConfigBuilder {
     pub fn build(self) -> ClientConfig {
         self.config
     }
+    /// Like [`build`](Self::build), but first checks that the required properties are present
+    /// and that no properties conflict with each other.
+    pub fn try_build(self) -> Result<ClientConfig, ConfigValidationError> {
+        validate(&self.config, &["bootstrap.servers"])?;
+        Ok(self.config)
+    }
 }
 impl Set for AdminConfigBuilder {
     fn set(&mut self, key: &str, value: impl ToString) {
@@ -218,3 +282,139 @@ impl AdminConfigBuilder {
     // admin specific configs
     // host resolver?
 }
+
+const SASL_PROPERTIES: &[&str] = &[
+    "sasl.mechanism",
+    "sasl.mechanisms",
+    "sasl.username",
+    "sasl.password",
+    "sasl.jaas.config",
+    "sasl.kerberos.service.name",
+    "sasl.kerberos.principal",
+    "sasl.kerberos.keytab",
+    "sasl.oauthbearer.config",
+];
+const SSL_PROPERTIES: &[&str] = &[
+    "ssl.key.password",
+    "ssl.key.location",
+    "ssl.keystore.key",
+    "ssl.keystore.location",
+    "ssl.keystore.certificate.chain",
+    "ssl.certificate.location",
+    "ssl.ca.location",
+];
+
+fn validate(config: &ClientConfig, required: &[&str]) -> Result<(), ConfigValidationError> {
+    let mut problems = Vec::new();
+
+    for key in required {
+        if config
+            .get(key)
+            .is_none_or(|val| val.trim_matches(',').is_empty())
+        {
+            problems.push(format!("`{key}` is required"));
+        }
+    }
+
+    let protocol = config
+        .get("security.protocol")
+        .unwrap_or("PLAINTEXT")
+        .to_ascii_uppercase();
+    let uses_sasl = protocol.starts_with("SASL_");
+    let uses_ssl = protocol.ends_with("SSL");
+    for key in SASL_PROPERTIES {
+        if !uses_sasl && config.get(key).is_some() {
+            problems.push(format!(
+                "`{key}` is set but `security.protocol` is {protocol}, not SASL_PLAINTEXT or SASL_SSL"
+            ));
+        }
+    }
+    for key in SSL_PROPERTIES {
+        if !uses_ssl && config.get(key).is_some() {
+            problems.push(format!(
+                "`{key}` is set but `security.protocol` is {protocol}, not SSL or SASL_SSL"
+            ));
+        }
+    }
+
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(ConfigValidationError::new(problems))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(properties: &[(&str, &str)]) -> ClientConfig {
+        let mut config = ClientConfig::new();
+        for (key, value) in properties {
+            config.set(*key, *value);
+        }
+        config
+    }
+
+    #[test]
+    fn requires_properties() {
+        let required = ["bootstrap.servers", "group.id"];
+        let err = validate(&config(&[("bootstrap.servers", ",")]), &required).unwrap_err();
+        assert_eq!(
+            err.problems(),
+            ["`bootstrap.servers` is required", "`group.id` is required"]
+        );
+
+        let config = config(&[("bootstrap.servers", "localhost:9092"), ("group.id", "g")]);
+        assert!(validate(&config, &required).is_ok());
+    }
+
+    #[test]
+    fn rejects_properties_for_another_security_protocol() {
+        let sasl = config(&[("bootstrap.servers", "b"), ("sasl.username", "u")]);
+        assert_eq!(
+            validate(&sasl, &["bootstrap.servers"])
+                .unwrap_err()
+                .problems()
+                .len(),
+            1
+        );
+
+        let ssl = config(&[
+            ("bootstrap.servers", "b"),
+            ("security.protocol", "SASL_PLAINTEXT"),
+            ("sasl.username", "u"),
+            ("ssl.ca.location", "ca.pem"),
+        ]);
+        let err = validate(&ssl, &["bootstrap.servers"]).unwrap_err();
+        assert_eq!(err.problems().len(), 1);
+        assert!(err.problems()[0].starts_with("`ssl.ca.location`"));
+
+        let both = config(&[
+            ("bootstrap.servers", "b"),
+            ("security.protocol", "SASL_SSL"),
+            ("sasl.username", "u"),
+            ("ssl.ca.location", "ca.pem"),
+        ]);
+        assert!(validate(&both, &["bootstrap.servers"]).is_ok());
+    }
+
+    #[test]
+    fn reads_the_security_protocol_in_any_case() {
+        let ssl = config(&[
+            ("bootstrap.servers", "b"),
+            ("security.protocol", "ssl"),
+            ("ssl.ca.location", "ca.pem"),
+        ]);
+        assert!(validate(&ssl, &["bootstrap.servers"]).is_ok());
+
+        let sasl = config(&[
+            ("bootstrap.servers", "b"),
+            ("security.protocol", "sasl_plaintext"),
+            ("sasl.username", "u"),
+            ("ssl.ca.location", "ca.pem"),
+        ]);
+        let err = validate(&sasl, &["bootstrap.servers"]).unwrap_err();
+        assert_eq!(err.problems().len(), 1);
+    }
+}
@@ -161,6 +161,15 @@ pub trait KafkaConfigBuilder: Set + Sized {
         self.set("socket.connection.setup.timeout.ms", val.as_millis());
         self
     }
+    /// librdkafka statistics emit interval. The application also needs to register a stats
+    /// callback for statistics to be read; [`TypedProducer`](crate::TypedProducer) does so
+    /// itself. A value of 0 disables statistics. The granularity is 1000ms.
+    ///
+    /// Default: 0 (disabled)
+    fn statistics_interval(mut self, interval: Duration) -> Self {
+        self.set("statistics.interval.ms", interval.as_millis());
+        self
+    }
 }
 
 pub trait SslConfigBuilder: Set + Sized {
@@ -1,16 +1,32 @@
+use std::fmt;
+
 pub enum Reset {
     Latest,
     Earliest,
     None,
 }
-impl ToString for Reset {
-    fn to_string(&self) -> String {
-        match self {
+impl fmt::Display for Reset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let value = match self {
             Reset::Latest => "latest",
             Reset::Earliest => "earliest",
             Reset::None => "none",
-        }
-        .to_string()
+        };
+        f.write_str(value)
+    }
+}
+
+pub enum IsolationLevel {
+    ReadUncommitted,
+    ReadCommitted,
+}
+impl fmt::Display for IsolationLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let value = match self {
+            IsolationLevel::ReadUncommitted => "read_uncommitted",
+            IsolationLevel::ReadCommitted => "read_committed",
+        };
+        f.write_str(value)
     }
 }
 
@@ -18,15 +34,15 @@ pub enum DnsLookup {
     UseAllDnsIps,
     ResolveCanonicalBootstrapServersOnly,
 }
-impl ToString for DnsLookup {
-    fn to_string(&self) -> String {
-        match self {
+impl fmt::Display for DnsLookup {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let value = match self {
             DnsLookup::UseAllDnsIps => "use_all_dns_ips",
             DnsLookup::ResolveCanonicalBootstrapServersOnly => {
                 "resolve_canonical_bootstrap_servers_only"
             }
-        }
-        .to_string()
+        };
+        f.write_str(value)
     }
 }
 
@@ -35,14 +51,14 @@ pub enum RecordingLevel {
     Debug,
     Info,
 }
-impl ToString for RecordingLevel {
-    fn to_string(&self) -> String {
-        match self {
+impl fmt::Display for RecordingLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let value = match self {
             RecordingLevel::Trace => "TRACE",
             RecordingLevel::Debug => "DEBUG",
             RecordingLevel::Info => "INFO",
-        }
-        .to_string()
+        };
+        f.write_str(value)
     }
 }
 
@@ -52,14 +68,48 @@ pub enum SecurityProtocol {
     SaslPlaintext,
     SaslSsl,
 }
-impl ToString for SecurityProtocol {
-    fn to_string(&self) -> String {
-        match self {
+impl fmt::Display for SecurityProtocol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let value = match self {
             SecurityProtocol::Plaintext => "PLAINTEXT",
             SecurityProtocol::Ssl => "SSL",
             SecurityProtocol::SaslPlaintext => "SASL_PLAINTEXT",
             SecurityProtocol::SaslSsl => "SASL_SSL",
-        }
-        .to_string()
+        };
+        f.write_str(value)
+    }
+}
+
+/// How librdkafka picks a partition for records sent without one.
+pub enum Partitioner {
+    /// A random partition for every record.
+    Random,
+    /// CRC32 of the key. Records with empty or missing keys all go to the same partition.
+    Consistent,
+    /// CRC32 of the key, or a random partition for records without one.
+    ConsistentRandom,
+    /// Murmur2 of the key, matching the Java client. Records with missing keys all go to the same
+    /// partition.
+    Murmur2,
+    /// Murmur2 of the key, or a random partition for records without one. This is the Java
+    /// client's default placement.
+    Murmur2Random,
+    /// FNV-1a of the key. Records with missing keys all go to the same partition.
+    Fnv1a,
+    /// FNV-1a of the key, or a random partition for records without one.
+    Fnv1aRandom,
+}
+impl fmt::Display for Partitioner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let value = match self {
+            Partitioner::Random => "random",
+            Partitioner::Consistent => "consistent",
+            Partitioner::ConsistentRandom => "consistent_random",
+            Partitioner::Murmur2 => "murmur2",
+            Partitioner::Murmur2Random => "murmur2_random",
+            Partitioner::Fnv1a => "fnv1a",
+            Partitioner::Fnv1aRandom => "fnv1a_random",
+        };
+        f.write_str(value)
     }
 }
@@ -0,0 +1,188 @@
+//! Reading a topic up to where it ended when reading started, e.g. to load a snapshot or build
+//! state before serving, without guessing how long to wait for more.
+
+use std::{
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
+
+use futures::{stream, Stream};
+use rdkafka::{
+    consumer::{Consumer, ConsumerContext},
+    util::Timeout,
+    Message, Offset, TopicPartitionList,
+};
+
+use crate::{error::RdkafkaExtError, Topic, TypedConsumer, TypedMessage};
+
+/// How often to check on partitions while no messages are arriving.
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+type Partition = (String, i32);
+
+struct CatchUp {
+    timeout: Timeout,
+    /// The high watermark of every partition not yet read to the end, once assigned.
+    ends: Option<HashMap<Partition, i64>>,
+    /// Partitions paused until the read is done, because they're finished or were assigned
+    /// after it started.
+    held: HashSet<Partition>,
+    done: bool,
+}
+
+impl<T: Topic, C: ConsumerContext + 'static> TypedConsumer<T, C> {
+    /// Streams messages until every assigned partition has been read up to the high watermark
+    /// it had when the consumer was first assigned partitions, then ends.
+    ///
+    /// Partitions are read from wherever the consumer would otherwise start, so for a full load
+    /// use `auto.offset.reset=earliest` without committed offsets, or seek to the beginning.
+    /// Partitions that finish early, or are assigned partway through, are paused until the rest
+    /// are done, then resumed where they left off, so consumption can carry on past the end
+    /// without missing anything. Watermarks are fetched from the broker, blocking for up to
+    /// `timeout`.
+    pub fn read_to_end(
+        &self,
+        timeout: impl Into<Timeout>,
+    ) -> impl Stream<Item = Result<TypedMessage<'_, T>, RdkafkaExtError>> + '_ {
+        let state = CatchUp {
+            timeout: timeout.into(),
+            ends: None,
+            held: HashSet::new(),
+            done: false,
+        };
+        Box::pin(stream::unfold(state, move |mut state| async move {
+            let next = self.next_before_end(&mut state).await;
+            if let Some(Err(_)) = next {
+                state.done = true;
+            }
+            next.map(|next| (next, state))
+        }))
+    }
+    async fn next_before_end(
+        &self,
+        state: &mut CatchUp,
+    ) -> Option<Result<TypedMessage<'_, T>, RdkafkaExtError>> {
+        if state.done {
+            return None;
+        }
+        loop {
+            if let Err(err) = self.check_partitions(state) {
+                return Some(Err(err));
+            }
+            if state.ends.as_ref().is_some_and(HashMap::is_empty) {
+                state.done = true;
+                let mut held = TopicPartitionList::new();
+                for (topic, partition) in &state.held {
+                    held.add_partition(topic, *partition);
+                }
+                return match held.count() > 0 {
+                    true => self.inner.resume(&held).err().map(|err| Err(err.into())),
+                    false => None,
+                };
+            }
+
+            let message = match tokio::time::timeout(IDLE_CHECK_INTERVAL, self.next_message()).await
+            {
+                Ok(Ok(message)) => message,
+                Ok(Err(err)) => return Some(Err(err.into())),
+                Err(_) => continue,
+            };
+            let partition = (message.topic().to_string(), message.partition());
+            // Fetched before the partition was held back; it's fetched again once resumed.
+            if state.held.contains(&partition) {
+                continue;
+            }
+            // The first assignment may have happened while waiting for this message.
+            if state.ends.is_none() {
+                if let Err(err) = self.check_partitions(state) {
+                    return Some(Err(err));
+                }
+            }
+            let offset = message.offset();
+            let ends = state.ends.get_or_insert_default();
+            match ends.get(&partition).copied() {
+                Some(end) if offset < end => {
+                    if offset + 1 >= end {
+                        ends.remove(&partition);
+                        if let Err(err) = self.hold(state, partition, end) {
+                            return Some(Err(err));
+                        }
+                    }
+                    return Some(self.typed(message));
+                }
+                Some(end) => {
+                    ends.remove(&partition);
+                    if let Err(err) = self.hold(state, partition, end) {
+                        return Some(Err(err));
+                    }
+                }
+                None => {
+                    if let Err(err) = self.hold(state, partition, offset) {
+                        return Some(Err(err));
+                    }
+                }
+            }
+        }
+    }
+    /// Fetches the end of every assigned partition once there are some, and drops partitions
+    /// that have been revoked or are already at their end.
+    fn check_partitions(&self, state: &mut CatchUp) -> Result<(), RdkafkaExtError> {
+        let assignment = self.inner.assignment()?;
+        if state.ends.is_none() {
+            if assignment.count() == 0 {
+                return Ok(());
+            }
+            let mut ends = HashMap::new();
+            for element in assignment.elements() {
+                let (low, high) = self.inner.fetch_watermarks(
+                    element.topic(),
+                    element.partition(),
+                    state.timeout,
+                )?;
+                if high > low {
+                    ends.insert((element.topic().to_string(), element.partition()), high);
+                }
+            }
+            let committed = self
+                .inner
+                .committed_offsets(assignment.clone(), state.timeout)?;
+            for element in committed.elements() {
+                let partition = (element.topic().to_string(), element.partition());
+                if let (Offset::Offset(offset), Some(&end)) =
+                    (element.offset(), ends.get(&partition))
+                {
+                    if offset >= end {
+                        ends.remove(&partition);
+                    }
+                }
+            }
+            state.ends = Some(ends);
+        }
+
+        let position = self.inner.position()?;
+        let ends = state.ends.as_mut().expect("just fetched");
+        ends.retain(|(topic, partition), end| {
+            let assigned = assignment.find_partition(topic, *partition).is_some();
+            let reached = position.find_partition(topic, *partition).is_some_and(
+                |element| matches!(element.offset(), Offset::Offset(at) if at >= *end),
+            );
+            assigned && !reached
+        });
+        Ok(())
+    }
+    /// Pauses a partition until the read is done, rewinding it to `offset`.
+    fn hold(
+        &self,
+        state: &mut CatchUp,
+        (topic, partition): Partition,
+        offset: i64,
+    ) -> Result<(), RdkafkaExtError> {
+        let mut list = TopicPartitionList::new();
+        list.add_partition(&topic, partition);
+        self.inner.pause(&list)?;
+        self.inner
+            .seek(&topic, partition, Offset::Offset(offset), Duration::ZERO)?;
+        state.held.insert((topic, partition));
+        Ok(())
+    }
+}
@@ -0,0 +1,287 @@
+//! Splitting payloads too big for `message.max.bytes` across several records, and putting them
+//! back together on the consumer side.
+//!
+//! Each chunk carries `x-chunk-id`, `x-chunk-index` and `x-chunk-count` headers. Chunks are
+//! always sent with the message key so they land on the same partition, in order.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::atomic::{AtomicU64, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use rdkafka::{
+    message::{Header, Headers, OwnedHeaders},
+    util::Timeout,
+    ClientContext, Message,
+};
+
+use crate::{
+    codec::CodecError,
+    error::{DecodeError, RecordField, SendError},
+    Delivery, GenericTypedMessage, RawRecord, Topic, TypedProducer,
+};
+
+const CHUNK_ID: &str = "x-chunk-id";
+const CHUNK_INDEX: &str = "x-chunk-index";
+const CHUNK_COUNT: &str = "x-chunk-count";
+
+static NEXT_CHUNK_ID: AtomicU64 = AtomicU64::new(0);
+
+fn chunk_id() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_nanos());
+    let sequence = NEXT_CHUNK_ID.fetch_add(1, Ordering::Relaxed);
+
+    format!("{:x}-{nanos:x}-{sequence:x}", std::process::id())
+}
+
+impl<C: ClientContext + 'static> TypedProducer<C> {
+    /// Sends `payload` as a single record if it encodes to at most `chunk_size` bytes, and as
+    /// several records of at most `chunk_size` bytes each otherwise. Returns where the last
+    /// chunk landed.
+    ///
+    /// A key is required so every chunk goes to the same partition.
+    pub async fn send_chunked<T: Topic>(
+        &self,
+        topic: &T,
+        payload: &T::Payload,
+        key: &T::Key,
+        chunk_size: usize,
+        timeout: impl Into<Timeout>,
+    ) -> Result<Delivery, SendError> {
+        let encoded = self.encode(topic, payload, Some(key))?;
+        let timeout = timeout.into();
+
+        if encoded.payload.len() <= chunk_size {
+            return Ok(self.send_raw(encoded.record(), timeout).await?);
+        }
+
+        let id = chunk_id();
+        let chunks = encoded.payload.chunks(chunk_size.max(1));
+        let count = chunks.len().to_string();
+        let mut delivery = None;
+        for (index, chunk) in chunks.enumerate() {
+            let index = index.to_string();
+            let headers = OwnedHeaders::new()
+                .insert(Header {
+                    key: CHUNK_ID,
+                    value: Some(&id),
+                })
+                .insert(Header {
+                    key: CHUNK_INDEX,
+                    value: Some(&index),
+                })
+                .insert(Header {
+                    key: CHUNK_COUNT,
+                    value: Some(&count),
+                });
+
+            let record = RawRecord::new(&encoded.topic, encoded.key.as_deref(), Some(chunk))
+                .headers(headers)
+                .payload_headers(&encoded.payload_headers);
+            delivery = Some(self.send_raw(record, timeout).await?);
+        }
+        Ok(delivery.expect("payloads over the chunk size have at least two chunks"))
+    }
+}
+
+struct Partial {
+    chunks: Vec<Option<Vec<u8>>>,
+    received: usize,
+}
+
+/// Collects the chunks written by [`TypedProducer::send_chunked`] until a payload is complete.
+///
+/// Chunked payloads are only complete once their last chunk has been read, so commit offsets
+/// only after the payloads they finish have been handled.
+pub struct ChunkAssembler {
+    partial: HashMap<String, Partial>,
+    order: VecDeque<String>,
+    max_pending: usize,
+    max_chunks: usize,
+}
+impl ChunkAssembler {
+    pub fn new() -> Self {
+        Self {
+            partial: HashMap::new(),
+            order: VecDeque::new(),
+            max_pending: 1024,
+            max_chunks: 1024,
+        }
+    }
+    /// How many incomplete payloads to hold at once. Past this, the oldest is dropped.
+    pub fn with_max_pending(mut self, max_pending: usize) -> Self {
+        self.max_pending = max_pending;
+        self
+    }
+    /// The most chunks a payload may be split into. Chunks claiming more are rejected, so a bad
+    /// `x-chunk-count` header can't make the assembler allocate without bound.
+    ///
+    /// Default: 1024
+    pub fn with_max_chunks(mut self, max_chunks: usize) -> Self {
+        self.max_chunks = max_chunks.max(1);
+        self
+    }
+    /// Adds a message. Returns its payload if it wasn't chunked, or the reassembled payload if
+    /// it was the last missing chunk; otherwise, and for records without a payload, returns
+    /// `None`.
+    ///
+    /// Chunks with a count of zero or over the [`max_chunks`](Self::with_max_chunks), an index
+    /// past their count, or a different count than earlier chunks of the same payload are
+    /// rejected.
+    pub fn push<T: Topic, M: Message>(
+        &mut self,
+        message: &GenericTypedMessage<T, M>,
+    ) -> Result<Option<T::Payload>, DecodeError> {
+        let Some(chunk) = chunk_headers(message) else {
+            return message.payload();
+        };
+        let bytes = chunk
+            .and_then(|(id, index, count)| {
+                let bytes = message.message.payload().unwrap_or_default().to_vec();
+                self.insert(id, index, count, bytes)
+            })
+            .map_err(|source| message.decode_error(RecordField::Payload, source))?;
+        match bytes {
+            Some(bytes) => message.decode_payload(&bytes).map(Some),
+            None => Ok(None),
+        }
+    }
+    /// Stores one chunk, returning the whole payload if it was the last one missing.
+    fn insert(
+        &mut self,
+        id: String,
+        index: usize,
+        count: usize,
+        bytes: Vec<u8>,
+    ) -> Result<Option<Vec<u8>>, CodecError> {
+        if count == 0 || count > self.max_chunks {
+            return Err(CodecError::new(format!(
+                "chunk count {count} is outside 1..={}",
+                self.max_chunks
+            )));
+        }
+        if index >= count {
+            return Err(CodecError::new(format!(
+                "chunk {index} of a payload with {count} chunks"
+            )));
+        }
+        if let Some(partial) = self.partial.get(&id) {
+            if partial.chunks.len() != count {
+                return Err(CodecError::new(format!(
+                    "chunk count {count} disagrees with {} from earlier chunks",
+                    partial.chunks.len()
+                )));
+            }
+        } else {
+            if self.order.len() >= self.max_pending.max(1) {
+                if let Some(oldest) = self.order.pop_front() {
+                    self.partial.remove(&oldest);
+                }
+            }
+            self.order.push_back(id.clone());
+        }
+
+        let partial = self.partial.entry(id.clone()).or_insert_with(|| Partial {
+            chunks: vec![None; count],
+            received: 0,
+        });
+        if partial.chunks[index].replace(bytes).is_none() {
+            partial.received += 1;
+        }
+        if partial.received < partial.chunks.len() {
+            return Ok(None);
+        }
+
+        let partial = self.partial.remove(&id).expect("partial was just updated");
+        self.order.retain(|pending| *pending != id);
+        Ok(Some(
+            partial.chunks.into_iter().flatten().flatten().collect(),
+        ))
+    }
+}
+impl Default for ChunkAssembler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn chunk_headers<T: Topic, M: Message>(
+    message: &GenericTypedMessage<T, M>,
+) -> Option<Result<(String, usize, usize), CodecError>> {
+    let headers = message.headers()?;
+    let header = |name: &str| {
+        headers
+            .iter()
+            .find(|header| header.key == name)
+            .and_then(|header| header.value)
+            .and_then(|value| std::str::from_utf8(value).ok())
+    };
+
+    let id = header(CHUNK_ID)?.to_string();
+    let parse = |name: &str| {
+        header(name)
+            .and_then(|value| value.parse().ok())
+            .ok_or_else(|| CodecError::new(format!("missing or malformed {name} header")))
+    };
+    Some(parse(CHUNK_INDEX).and_then(|index| Ok((id, index, parse(CHUNK_COUNT)?))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reassembles_chunks_in_any_order() {
+        let mut assembler = ChunkAssembler::new();
+
+        assert_eq!(
+            assembler.insert("a".into(), 2, 3, b"ef".to_vec()).unwrap(),
+            None
+        );
+        assert_eq!(
+            assembler.insert("a".into(), 0, 3, b"ab".to_vec()).unwrap(),
+            None
+        );
+        assert_eq!(
+            assembler.insert("a".into(), 0, 3, b"ab".to_vec()).unwrap(),
+            None
+        );
+        assert_eq!(
+            assembler.insert("a".into(), 1, 3, b"cd".to_vec()).unwrap(),
+            Some(b"abcdef".to_vec())
+        );
+        assert!(assembler.partial.is_empty());
+        assert!(assembler.order.is_empty());
+    }
+
+    #[test]
+    fn rejects_inconsistent_chunks() {
+        let mut assembler = ChunkAssembler::new().with_max_chunks(4);
+
+        assert!(assembler.insert("a".into(), 0, 0, Vec::new()).is_err());
+        assert!(assembler.insert("a".into(), 0, 5, Vec::new()).is_err());
+        assert!(assembler.insert("a".into(), 2, 2, Vec::new()).is_err());
+        assembler.insert("a".into(), 0, 2, Vec::new()).unwrap();
+        assert!(assembler.insert("a".into(), 1, 3, Vec::new()).is_err());
+    }
+
+    #[test]
+    fn drops_the_oldest_partial_payload_when_full() {
+        let mut assembler = ChunkAssembler::new().with_max_pending(2);
+        assembler.insert("a".into(), 0, 2, b"a".to_vec()).unwrap();
+        assembler.insert("b".into(), 0, 2, b"b".to_vec()).unwrap();
+        assembler.insert("c".into(), 0, 2, b"c".to_vec()).unwrap();
+
+        assert_eq!(
+            assembler.insert("a".into(), 1, 2, b"a".to_vec()).unwrap(),
+            None
+        );
+        assert_eq!(
+            assembler.insert("c".into(), 1, 2, b"c".to_vec()).unwrap(),
+            Some(b"cc".to_vec())
+        );
+    }
+}
@@ -0,0 +1,188 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use futures::channel::mpsc::{self, UnboundedReceiver, UnboundedSender};
+
+/// Where a [`CircuitBreaker`] is in its cycle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CircuitState {
+    /// Sends go through as usual.
+    Closed,
+    /// Too many deliveries failed in a row, so sends fail straight away until the cooldown is
+    /// over.
+    Open,
+    /// The cooldown is over. A single probe send is let through, and its delivery decides
+    /// whether the circuit closes or opens again; other sends fail fast until then.
+    HalfOpen,
+}
+
+struct Inner {
+    state: CircuitState,
+    consecutive_failures: u32,
+    opened_at: Instant,
+    /// When the half-open probe was let through, if it hasn't been delivered yet.
+    probe_sent_at: Option<Instant>,
+    listeners: Vec<UnboundedSender<CircuitState>>,
+}
+
+/// Stops a producer from piling up sends during a broker outage. After enough deliveries fail
+/// in a row, sends through the producer fail fast with
+/// [`CircuitOpen`](crate::error::RdkafkaExtError::CircuitOpen) for a cooldown period, instead
+/// of each waiting out its own timeout.
+///
+/// A probe that is never delivered, say because its delivery future was dropped, stops
+/// blocking other probes after another cooldown.
+pub struct CircuitBreaker {
+    failure_threshold: u32,
+    cooldown: Duration,
+    inner: Mutex<Inner>,
+}
+impl CircuitBreaker {
+    /// Opens after `failure_threshold` consecutive delivery failures, for `cooldown`.
+    pub fn new(failure_threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            failure_threshold: failure_threshold.max(1),
+            cooldown,
+            inner: Mutex::new(Inner {
+                state: CircuitState::Closed,
+                consecutive_failures: 0,
+                opened_at: Instant::now(),
+                probe_sent_at: None,
+                listeners: Vec::new(),
+            }),
+        }
+    }
+    pub fn state(&self) -> CircuitState {
+        self.lock().state
+    }
+    /// Receives every state change from now on.
+    pub fn events(&self) -> UnboundedReceiver<CircuitState> {
+        let (sender, receiver) = mpsc::unbounded();
+        self.lock().listeners.push(sender);
+        receiver
+    }
+
+    /// Whether a send may go ahead.
+    pub(crate) fn allow(&self) -> bool {
+        let mut inner = self.lock();
+        match inner.state {
+            CircuitState::Closed => true,
+            CircuitState::HalfOpen => {
+                let probing = inner
+                    .probe_sent_at
+                    .is_some_and(|sent_at| sent_at.elapsed() < self.cooldown);
+                if !probing {
+                    inner.probe_sent_at = Some(Instant::now());
+                }
+                !probing
+            }
+            CircuitState::Open if inner.opened_at.elapsed() >= self.cooldown => {
+                inner.probe_sent_at = Some(Instant::now());
+                transition(&mut inner, CircuitState::HalfOpen);
+                true
+            }
+            CircuitState::Open => false,
+        }
+    }
+    /// Lets another probe through after an allowed send never made it into the queue.
+    pub(crate) fn release_probe(&self) {
+        self.lock().probe_sent_at = None;
+    }
+    pub(crate) fn record_success(&self) {
+        let mut inner = self.lock();
+        inner.consecutive_failures = 0;
+        inner.probe_sent_at = None;
+        transition(&mut inner, CircuitState::Closed);
+    }
+    pub(crate) fn record_failure(&self) {
+        let mut inner = self.lock();
+        inner.consecutive_failures = inner.consecutive_failures.saturating_add(1);
+        inner.probe_sent_at = None;
+
+        let trip = match inner.state {
+            CircuitState::Closed => inner.consecutive_failures >= self.failure_threshold,
+            CircuitState::HalfOpen => true,
+            CircuitState::Open => false,
+        };
+        if trip {
+            inner.opened_at = Instant::now();
+            transition(&mut inner, CircuitState::Open);
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+fn transition(inner: &mut Inner, state: CircuitState) {
+    if inner.state == state {
+        return;
+    }
+
+    inner.state = state;
+    inner
+        .listeners
+        .retain(|listener| listener.unbounded_send(state).is_ok());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COOLDOWN: Duration = Duration::from_secs(3600);
+
+    /// Pretends the cooldown has passed since the circuit opened.
+    fn cool_down(breaker: &CircuitBreaker) {
+        breaker.lock().opened_at = Instant::now() - COOLDOWN;
+    }
+
+    #[test]
+    fn opens_after_consecutive_failures() {
+        let breaker = CircuitBreaker::new(2, COOLDOWN);
+        breaker.record_failure();
+        breaker.record_success();
+        breaker.record_failure();
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        assert!(breaker.allow());
+
+        breaker.record_failure();
+        assert_eq!(breaker.state(), CircuitState::Open);
+        assert!(!breaker.allow());
+    }
+
+    #[test]
+    fn lets_one_probe_through_after_the_cooldown() {
+        let breaker = CircuitBreaker::new(1, COOLDOWN);
+        let mut events = breaker.events();
+        breaker.record_failure();
+        cool_down(&breaker);
+
+        assert!(breaker.allow());
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+        assert!(!breaker.allow());
+        breaker.release_probe();
+        assert!(breaker.allow());
+
+        breaker.record_failure();
+        assert_eq!(breaker.state(), CircuitState::Open);
+        cool_down(&breaker);
+        assert!(breaker.allow());
+        breaker.record_success();
+        assert_eq!(breaker.state(), CircuitState::Closed);
+
+        let seen: Vec<_> = std::iter::from_fn(|| events.try_recv().ok()).collect();
+        assert_eq!(
+            seen,
+            [
+                CircuitState::Open,
+                CircuitState::HalfOpen,
+                CircuitState::Open,
+                CircuitState::HalfOpen,
+                CircuitState::Closed,
+            ]
+        );
+    }
+}
@@ -0,0 +1,98 @@
+//! The claim-check pattern: payloads too big for Kafka are stored elsewhere, and only a
+//! reference to them is produced.
+
+use std::{error::Error, future::Future};
+
+use rdkafka::{
+    message::{Header, Headers, OwnedHeaders},
+    util::Timeout,
+    ClientContext,
+};
+
+use crate::{error::RdkafkaExtError, Delivery, RawRecord, Topic, TypedMessage, TypedProducer};
+
+/// The header holding the blob reference of a claim-checked record.
+const CLAIM_CHECK: &str = "x-claim-check";
+
+/// Somewhere to keep payloads that are too big to produce, such as S3 or a shared filesystem.
+pub trait BlobStore: Send + Sync {
+    /// Stores `bytes`, returning a reference [`get`](Self::get) can fetch them back with.
+    fn put(
+        &self,
+        bytes: Vec<u8>,
+    ) -> impl Future<Output = Result<String, Box<dyn Error + Send + Sync>>> + Send;
+    fn get(
+        &self,
+        reference: &str,
+    ) -> impl Future<Output = Result<Vec<u8>, Box<dyn Error + Send + Sync>>> + Send;
+}
+
+/// Produces payloads over a size threshold to a [`BlobStore`], sending only an empty record
+/// with an `x-claim-check` header pointing at them.
+pub struct ClaimCheck<S> {
+    store: S,
+    threshold: usize,
+}
+impl<S: BlobStore> ClaimCheck<S> {
+    /// Stores payloads that encode to more than `threshold` bytes.
+    pub fn new(store: S, threshold: usize) -> Self {
+        Self { store, threshold }
+    }
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+    pub async fn send<T: Topic>(
+        &self,
+        producer: &TypedProducer<impl ClientContext + 'static>,
+        topic: &T,
+        payload: &T::Payload,
+        key: Option<&T::Key>,
+        timeout: impl Into<Timeout>,
+    ) -> Result<Delivery, RdkafkaExtError> {
+        let encoded = producer.encode(topic, payload, key)?;
+        if encoded.payload.len() <= self.threshold {
+            return Ok(producer.send_raw(encoded.record(), timeout).await?);
+        }
+
+        let reference = self
+            .store
+            .put(encoded.payload)
+            .await
+            .map_err(RdkafkaExtError::BlobStore)?;
+        let headers = OwnedHeaders::new().insert(Header {
+            key: CLAIM_CHECK,
+            value: Some(&reference),
+        });
+        let record = RawRecord::new(&encoded.topic, encoded.key.as_deref(), Some(&[]))
+            .headers(headers)
+            .payload_headers(&encoded.payload_headers);
+        Ok(producer.send_raw(record, timeout).await?)
+    }
+    /// Deserializes the message's payload, fetching it from the store first if it was
+    /// claim-checked.
+    pub async fn payload<T: Topic>(
+        &self,
+        message: &TypedMessage<'_, T>,
+    ) -> Result<Option<T::Payload>, RdkafkaExtError> {
+        let Some(reference) = reference(message) else {
+            return Ok(message.payload()?);
+        };
+
+        let bytes = self
+            .store
+            .get(&reference)
+            .await
+            .map_err(RdkafkaExtError::BlobStore)?;
+        let payload = message.decode_payload(&bytes)?;
+        Ok(Some(payload))
+    }
+}
+
+fn reference<T: Topic>(message: &TypedMessage<'_, T>) -> Option<String> {
+    message
+        .headers()?
+        .iter()
+        .find(|header| header.key == CLAIM_CHECK)
+        .and_then(|header| header.value)
+        .map(|value| String::from_utf8_lossy(value).into_owned())
+}
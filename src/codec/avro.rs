@@ -0,0 +1,149 @@
+use std::{
+    collections::HashMap,
+    marker::PhantomData,
+    sync::{Arc, RwLock},
+};
+
+use apache_avro::{from_avro_datum, from_value, to_avro_datum, to_value, AvroSchema, Schema};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    schema_registry::{SchemaRegistryClient, SchemaRegistryError, SchemaType},
+    Topic,
+};
+
+use super::{
+    confluent::{frame, unframe},
+    CodecError, PayloadCodec,
+};
+
+struct Registry {
+    client: Arc<SchemaRegistryClient>,
+    schema_id: u32,
+    writer_schemas: RwLock<HashMap<u32, Arc<Schema>>>,
+}
+
+/// Encodes payloads as Avro using the schema derived from the payload type.
+///
+/// The default codec writes bare Avro datums. A codec created with
+/// [`register`](Self::register) or [`with_schema_id`](Self::with_schema_id) writes the Confluent
+/// wire format instead, and decodes records written with any schema its registry client has
+/// cached, resolving them against the payload's schema.
+pub struct AvroCodec<P> {
+    schema: Arc<Schema>,
+    registry: Option<Arc<Registry>>,
+    _payload: PhantomData<fn() -> P>,
+}
+impl<P: AvroSchema> AvroCodec<P> {
+    /// Registers the payload's schema under `subject`, and caches every schema already
+    /// registered under it so older records can still be read.
+    pub async fn register(
+        client: Arc<SchemaRegistryClient>,
+        subject: &str,
+    ) -> Result<Self, SchemaRegistryError> {
+        let schema = P::get_schema();
+        let schema_id = client
+            .register(subject, &schema.canonical_form(), SchemaType::Avro)
+            .await?;
+        client.prefetch_subject(subject).await?;
+
+        Ok(Self::with_schema_id(client, schema_id))
+    }
+    /// Registers the payload's schema under the subject `topic`'s
+    /// [`subject_name_strategy`](Topic::subject_name_strategy) picks for its values.
+    pub async fn register_for<T: Topic>(
+        client: Arc<SchemaRegistryClient>,
+        topic: &T,
+    ) -> Result<Self, SchemaRegistryError> {
+        let schema = P::get_schema();
+        let record_name = schema.name().map(|name| name.fullname(None));
+        let strategy = topic.subject_name_strategy();
+        let subject = strategy
+            .subject(&topic.topic_string(), record_name.as_deref(), false)
+            .ok_or(SchemaRegistryError::NoSubject(strategy))?;
+
+        Self::register(client, &subject).await
+    }
+    /// Uses a schema id that is already known to match the payload's schema.
+    pub fn with_schema_id(client: Arc<SchemaRegistryClient>, schema_id: u32) -> Self {
+        let schema = Arc::new(P::get_schema());
+        let writer_schemas = HashMap::from([(schema_id, schema.clone())]);
+
+        Self {
+            schema,
+            registry: Some(Arc::new(Registry {
+                client,
+                schema_id,
+                writer_schemas: RwLock::new(writer_schemas),
+            })),
+            _payload: PhantomData,
+        }
+    }
+}
+impl<P> AvroCodec<P> {
+    fn writer_schema(&self, registry: &Registry, id: u32) -> Result<Arc<Schema>, CodecError> {
+        if let Some(schema) = registry
+            .writer_schemas
+            .read()
+            .unwrap_or_else(|err| err.into_inner())
+            .get(&id)
+        {
+            return Ok(schema.clone());
+        }
+
+        let registered = registry.client.cached_schema(id).ok_or_else(|| {
+            CodecError::new(format!(
+                "schema {id} is not cached; prefetch it with the schema registry client"
+            ))
+        })?;
+        let schema = Arc::new(Schema::parse_str(&registered.schema).map_err(CodecError::new)?);
+        registry
+            .writer_schemas
+            .write()
+            .unwrap_or_else(|err| err.into_inner())
+            .insert(id, schema.clone());
+        Ok(schema)
+    }
+}
+impl<P: AvroSchema> Default for AvroCodec<P> {
+    fn default() -> Self {
+        Self {
+            schema: Arc::new(P::get_schema()),
+            registry: None,
+            _payload: PhantomData,
+        }
+    }
+}
+impl<P> Clone for AvroCodec<P> {
+    fn clone(&self) -> Self {
+        Self {
+            schema: self.schema.clone(),
+            registry: self.registry.clone(),
+            _payload: PhantomData,
+        }
+    }
+}
+impl<P: Serialize + DeserializeOwned> PayloadCodec<P> for AvroCodec<P> {
+    fn encode(&self, payload: &P) -> Result<Vec<u8>, CodecError> {
+        let value = to_value(payload).map_err(CodecError::new)?;
+        let datum = to_avro_datum(&self.schema, value).map_err(CodecError::new)?;
+
+        match &self.registry {
+            Some(registry) => Ok(frame(registry.schema_id, &datum)),
+            None => Ok(datum),
+        }
+    }
+    fn decode(&self, bytes: &[u8]) -> Result<P, CodecError> {
+        let (writer_schema, mut datum) = match &self.registry {
+            Some(registry) => {
+                let (id, datum) = unframe(bytes)?;
+                (self.writer_schema(registry, id)?, datum)
+            }
+            None => (self.schema.clone(), bytes),
+        };
+
+        let value = from_avro_datum(&writer_schema, &mut datum, Some(&self.schema))
+            .map_err(CodecError::new)?;
+        from_value(&value).map_err(CodecError::new)
+    }
+}
@@ -0,0 +1,29 @@
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{CodecError, PayloadCodec, SerializingCodec};
+
+/// Encodes payloads as CBOR with `ciborium`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CborCodec;
+impl<P: Serialize + DeserializeOwned> PayloadCodec<P> for CborCodec {
+    fn encode(&self, payload: &P) -> Result<Vec<u8>, CodecError> {
+        let mut bytes = Vec::new();
+        ciborium::into_writer(payload, &mut bytes).map_err(CodecError::new)?;
+        Ok(bytes)
+    }
+    fn decode(&self, bytes: &[u8]) -> Result<P, CodecError> {
+        ciborium::from_reader(bytes).map_err(CodecError::new)
+    }
+    fn encode_into(&self, payload: &P, buffer: &mut Vec<u8>) -> Result<(), CodecError> {
+        ciborium::into_writer(payload, buffer).map_err(CodecError::new)
+    }
+}
+impl SerializingCodec for CborCodec {
+    fn serialize_into<T: Serialize + ?Sized>(
+        &self,
+        value: &T,
+        buffer: &mut Vec<u8>,
+    ) -> Result<(), CodecError> {
+        ciborium::into_writer(value, buffer).map_err(CodecError::new)
+    }
+}
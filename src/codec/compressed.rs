@@ -0,0 +1,253 @@
+use std::io::Read;
+#[cfg(any(feature = "gzip", feature = "lz4"))]
+use std::io::Write;
+
+use super::{CodecError, PayloadCodec};
+
+/// The compression algorithms [`CompressedCodec`] can write. Each is only available with the
+/// cargo feature of the same name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    /// Written uncompressed, but still named in the header so consumers don't try to
+    /// decompress it.
+    None,
+    #[cfg(feature = "gzip")]
+    Gzip,
+    #[cfg(feature = "zstd")]
+    Zstd,
+    #[cfg(feature = "lz4")]
+    Lz4,
+}
+impl Compression {
+    /// The value written to the [`COMPRESSION`] header.
+    pub fn name(self) -> &'static str {
+        match self {
+            Compression::None => "none",
+            #[cfg(feature = "gzip")]
+            Compression::Gzip => "gzip",
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => "zstd",
+            #[cfg(feature = "lz4")]
+            Compression::Lz4 => "lz4",
+        }
+    }
+    fn from_name(name: &[u8]) -> Result<Self, CodecError> {
+        match name {
+            b"none" => return Ok(Compression::None),
+            #[cfg(feature = "gzip")]
+            b"gzip" => return Ok(Compression::Gzip),
+            #[cfg(feature = "zstd")]
+            b"zstd" => return Ok(Compression::Zstd),
+            #[cfg(feature = "lz4")]
+            b"lz4" => return Ok(Compression::Lz4),
+            _ => {}
+        }
+
+        let name = String::from_utf8_lossy(name);
+        match &*name {
+            "gzip" | "zstd" | "lz4" => Err(CodecError::new(format!(
+                "payload is {name} compressed; enable the {name} feature"
+            ))),
+            name => Err(CodecError::new(format!("unknown compression {name:?}"))),
+        }
+    }
+    fn compress(self, bytes: &[u8], out: &mut Vec<u8>) -> Result<(), CodecError> {
+        match self {
+            Compression::None => out.extend_from_slice(bytes),
+            #[cfg(feature = "gzip")]
+            Compression::Gzip => {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(out, flate2::Compression::default());
+                encoder.write_all(bytes).map_err(CodecError::new)?;
+                encoder.finish().map_err(CodecError::new)?;
+            }
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => {
+                zstd::stream::copy_encode(bytes, out, zstd::DEFAULT_COMPRESSION_LEVEL)
+                    .map_err(CodecError::new)?;
+            }
+            #[cfg(feature = "lz4")]
+            Compression::Lz4 => {
+                let mut encoder = lz4_flex::frame::FrameEncoder::new(out);
+                encoder.write_all(bytes).map_err(CodecError::new)?;
+                encoder.finish().map_err(CodecError::new)?;
+            }
+        }
+        Ok(())
+    }
+    /// Decompresses `bytes`, failing once the output would grow past `limit`.
+    fn decompress(self, bytes: &[u8], limit: usize) -> Result<Vec<u8>, CodecError> {
+        let mut out = Vec::new();
+        let bound = limit as u64 + 1;
+        match self {
+            Compression::None => out.extend_from_slice(bytes),
+            #[cfg(feature = "gzip")]
+            Compression::Gzip => {
+                flate2::read::GzDecoder::new(bytes)
+                    .take(bound)
+                    .read_to_end(&mut out)
+                    .map_err(CodecError::new)?;
+            }
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => {
+                zstd::stream::read::Decoder::new(bytes)
+                    .map_err(CodecError::new)?
+                    .take(bound)
+                    .read_to_end(&mut out)
+                    .map_err(CodecError::new)?;
+            }
+            #[cfg(feature = "lz4")]
+            Compression::Lz4 => {
+                lz4_flex::frame::FrameDecoder::new(bytes)
+                    .take(bound)
+                    .read_to_end(&mut out)
+                    .map_err(CodecError::new)?;
+            }
+        }
+        if out.len() > limit {
+            return Err(CodecError::new(format!(
+                "payload decompresses to more than {limit} bytes"
+            )));
+        }
+        Ok(out)
+    }
+}
+impl Default for Compression {
+    /// The fastest enabled algorithm: lz4, then zstd, then gzip.
+    fn default() -> Self {
+        #[cfg(feature = "lz4")]
+        return Compression::Lz4;
+        #[cfg(all(feature = "zstd", not(feature = "lz4")))]
+        return Compression::Zstd;
+        #[cfg(all(feature = "gzip", not(any(feature = "lz4", feature = "zstd"))))]
+        return Compression::Gzip;
+    }
+}
+
+/// The header [`CompressedCodec`] records the algorithm in.
+pub const COMPRESSION: &str = "x-compression";
+
+/// How big a payload [`CompressedCodec`] decompresses by default: 64 MiB.
+pub const DEFAULT_MAX_DECOMPRESSED_LEN: usize = 64 * 1024 * 1024;
+
+/// Compresses whatever another codec writes, for payloads too big to send as is when
+/// broker-side compression isn't an option.
+///
+/// The algorithm is recorded in the [`COMPRESSION`] header, so consumers can read records
+/// written with any enabled algorithm no matter which one their own codec is set to. Records
+/// without the header are assumed to use the codec's own algorithm.
+///
+/// Decompression stops at [`with_max_decompressed_len`](Self::with_max_decompressed_len)
+/// bytes, so a small record can't expand into an unbounded allocation.
+#[derive(Debug, Clone, Copy)]
+pub struct CompressedCodec<C> {
+    inner: C,
+    compression: Compression,
+    max_decompressed_len: usize,
+}
+impl<C> CompressedCodec<C> {
+    pub fn new(inner: C, compression: Compression) -> Self {
+        Self {
+            inner,
+            compression,
+            max_decompressed_len: DEFAULT_MAX_DECOMPRESSED_LEN,
+        }
+    }
+    /// Fails decoding any payload that decompresses to more than `len` bytes.
+    pub fn with_max_decompressed_len(mut self, len: usize) -> Self {
+        self.max_decompressed_len = len;
+        self
+    }
+
+    fn decompress(&self, bytes: &[u8], compression: Compression) -> Result<Vec<u8>, CodecError> {
+        compression.decompress(bytes, self.max_decompressed_len)
+    }
+}
+impl<C: Default> Default for CompressedCodec<C> {
+    fn default() -> Self {
+        Self::new(C::default(), Compression::default())
+    }
+}
+impl<P, C: PayloadCodec<P>> PayloadCodec<P> for CompressedCodec<C> {
+    fn encode(&self, payload: &P) -> Result<Vec<u8>, CodecError> {
+        let mut bytes = Vec::new();
+        self.encode_into(payload, &mut bytes)?;
+        Ok(bytes)
+    }
+    /// Assumes the payload uses this codec's algorithm, since there are no headers to check.
+    fn decode(&self, bytes: &[u8]) -> Result<P, CodecError> {
+        self.inner
+            .decode(&self.decompress(bytes, self.compression)?)
+    }
+    fn encode_into(&self, payload: &P, buffer: &mut Vec<u8>) -> Result<(), CodecError> {
+        let serialized = self.inner.encode(payload)?;
+        self.compression.compress(&serialized, buffer)
+    }
+    fn payload_headers(&self) -> Vec<(&'static str, Vec<u8>)> {
+        let mut headers = self.inner.payload_headers();
+        headers.push((COMPRESSION, self.compression.name().as_bytes().to_vec()));
+        headers
+    }
+    fn decode_with_headers<'h>(
+        &self,
+        bytes: &[u8],
+        header: &dyn Fn(&str) -> Option<&'h [u8]>,
+    ) -> Result<P, CodecError> {
+        let compression = match header(COMPRESSION) {
+            Some(name) => Compression::from_name(name)?,
+            None => self.compression,
+        };
+        self.inner
+            .decode_with_headers(&self.decompress(bytes, compression)?, header)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::JsonCodec;
+
+    #[test]
+    fn round_trips_through_every_algorithm() {
+        let payload = "abc".repeat(100);
+        let mut algorithms = vec![Compression::None];
+        #[cfg(feature = "gzip")]
+        algorithms.push(Compression::Gzip);
+        #[cfg(feature = "zstd")]
+        algorithms.push(Compression::Zstd);
+        #[cfg(feature = "lz4")]
+        algorithms.push(Compression::Lz4);
+
+        for compression in algorithms {
+            let codec = CompressedCodec::new(JsonCodec, compression);
+            let bytes = codec.encode(&payload).unwrap();
+            let headers = PayloadCodec::<String>::payload_headers(&codec);
+            let header = |name: &str| {
+                headers
+                    .iter()
+                    .find(|(header, _)| *header == name)
+                    .map(|(_, value)| &value[..])
+            };
+
+            let decoded: String = CompressedCodec::new(JsonCodec, Compression::None)
+                .decode_with_headers(&bytes, &header)
+                .unwrap();
+            assert_eq!(decoded, payload);
+            assert_eq!(
+                PayloadCodec::<String>::decode(&codec, &bytes).unwrap(),
+                payload
+            );
+        }
+    }
+
+    #[test]
+    fn stops_decompressing_past_the_limit() {
+        let codec = CompressedCodec::<JsonCodec>::default().with_max_decompressed_len(64);
+        let small = codec.encode(&"a".repeat(10)).unwrap();
+        let large = codec.encode(&"a".repeat(1000)).unwrap();
+
+        assert!(PayloadCodec::<String>::decode(&codec, &small).is_ok());
+        let err = PayloadCodec::<String>::decode(&codec, &large).unwrap_err();
+        assert!(err.to_string().contains("more than 64 bytes"));
+    }
+}
@@ -0,0 +1,96 @@
+use super::{CodecError, PayloadCodec};
+
+const MAGIC_BYTE: u8 = 0;
+const HEADER_LEN: usize = 5;
+
+/// Prefixes `payload` with the Confluent wire format header: a zero magic byte followed by the
+/// big-endian schema id.
+pub fn frame(schema_id: u32, payload: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(HEADER_LEN + payload.len());
+    bytes.push(MAGIC_BYTE);
+    bytes.extend_from_slice(&schema_id.to_be_bytes());
+    bytes.extend_from_slice(payload);
+    bytes
+}
+
+/// Splits a record in the Confluent wire format into its schema id and the payload after it.
+pub fn unframe(bytes: &[u8]) -> Result<(u32, &[u8]), CodecError> {
+    match bytes {
+        [MAGIC_BYTE, a, b, c, d, payload @ ..] => {
+            Ok((u32::from_be_bytes([*a, *b, *c, *d]), payload))
+        }
+        [_, _, _, _, _, ..] => Err(CodecError::new("unknown Confluent wire format magic byte")),
+        _ => Err(CodecError::new("missing Confluent wire format header")),
+    }
+}
+
+/// Wraps another codec in the Confluent wire format, so records can be exchanged with Kafka
+/// Connect and the Java serializers.
+///
+/// Decoding accepts any schema id. Encoding needs one, so the default codec can only decode;
+/// override [`Topic::codec`](crate::Topic::codec) with [`with_schema_id`](Self::with_schema_id)
+/// to produce.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ConfluentCodec<C> {
+    inner: C,
+    schema_id: Option<u32>,
+}
+impl<C> ConfluentCodec<C> {
+    pub fn new(inner: C) -> Self {
+        Self {
+            inner,
+            schema_id: None,
+        }
+    }
+    pub fn with_schema_id(inner: C, schema_id: u32) -> Self {
+        Self {
+            inner,
+            schema_id: Some(schema_id),
+        }
+    }
+}
+impl<P, C: PayloadCodec<P>> PayloadCodec<P> for ConfluentCodec<C> {
+    fn encode(&self, payload: &P) -> Result<Vec<u8>, CodecError> {
+        let schema_id = self
+            .schema_id
+            .ok_or_else(|| CodecError::new("no schema id to write in the Confluent header"))?;
+
+        Ok(frame(schema_id, &self.inner.encode(payload)?))
+    }
+    fn decode(&self, bytes: &[u8]) -> Result<P, CodecError> {
+        let (_, payload) = unframe(bytes)?;
+        self.inner.decode(payload)
+    }
+    fn payload_headers(&self) -> Vec<(&'static str, Vec<u8>)> {
+        self.inner.payload_headers()
+    }
+    fn decode_with_headers<'h>(
+        &self,
+        bytes: &[u8],
+        header: &dyn Fn(&str) -> Option<&'h [u8]>,
+    ) -> Result<P, CodecError> {
+        let (_, payload) = unframe(bytes)?;
+        self.inner.decode_with_headers(payload, header)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unframes_what_it_frames() {
+        let bytes = frame(0x0102_0304, b"payload");
+
+        assert_eq!(&bytes[..HEADER_LEN], &[0, 1, 2, 3, 4]);
+        assert_eq!(unframe(&bytes).unwrap(), (0x0102_0304, &b"payload"[..]));
+        assert_eq!(unframe(&frame(7, b"")).unwrap(), (7, &b""[..]));
+    }
+
+    #[test]
+    fn rejects_a_bad_header() {
+        assert!(unframe(&[1, 0, 0, 0, 7, b'x']).is_err());
+        assert!(unframe(&[0, 0, 0, 7]).is_err());
+        assert!(unframe(&[]).is_err());
+    }
+}
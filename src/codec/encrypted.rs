@@ -0,0 +1,318 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt,
+    sync::{Arc, Mutex},
+};
+
+use ring::{
+    aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN},
+    rand::{SecureRandom, SystemRandom},
+};
+
+use super::{CodecError, PayloadCodec};
+
+/// A 256-bit AES-GCM data key.
+pub type DataKey = [u8; 32];
+
+/// Where [`EncryptedCodec`] keeps one data key per subject, such as a table of keys wrapped by a
+/// KMS. Deleting a subject's key shreds it: every payload encrypted for the subject becomes
+/// unreadable, without rewriting the topics it was sent to.
+///
+/// Codecs run synchronously on the send and receive paths, so lookups should be served from a
+/// local cache.
+pub trait KeyProvider: Send + Sync {
+    /// The data key for `subject`, creating one if it doesn't have one yet.
+    fn get_or_create(&self, subject: &str) -> Result<DataKey, Box<dyn Error + Send + Sync>>;
+    /// The data key for `subject`, or `None` if it never had one or it was deleted.
+    fn get(&self, subject: &str) -> Result<Option<DataKey>, Box<dyn Error + Send + Sync>>;
+    /// Deletes the data key for `subject`, so nothing encrypted for it can be decrypted again.
+    fn delete(&self, subject: &str) -> Result<(), Box<dyn Error + Send + Sync>>;
+}
+
+/// Keeps data keys in memory, for tests and single-process setups. Keys are lost on restart,
+/// which shreds every subject.
+pub struct MemoryKeyProvider {
+    keys: Mutex<HashMap<String, DataKey>>,
+    random: SystemRandom,
+}
+impl MemoryKeyProvider {
+    pub fn new() -> Self {
+        Self {
+            keys: Mutex::default(),
+            random: SystemRandom::new(),
+        }
+    }
+}
+impl Default for MemoryKeyProvider {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl KeyProvider for MemoryKeyProvider {
+    fn get_or_create(&self, subject: &str) -> Result<DataKey, Box<dyn Error + Send + Sync>> {
+        let mut keys = self.keys.lock().unwrap_or_else(|err| err.into_inner());
+        if let Some(key) = keys.get(subject) {
+            return Ok(*key);
+        }
+
+        let mut key = DataKey::default();
+        self.random
+            .fill(&mut key)
+            .map_err(|_| "failed to generate a data key")?;
+        keys.insert(subject.to_string(), key);
+        Ok(key)
+    }
+    fn get(&self, subject: &str) -> Result<Option<DataKey>, Box<dyn Error + Send + Sync>> {
+        let keys = self.keys.lock().unwrap_or_else(|err| err.into_inner());
+        Ok(keys.get(subject).copied())
+    }
+    fn delete(&self, subject: &str) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let mut keys = self.keys.lock().unwrap_or_else(|err| err.into_inner());
+        keys.remove(subject);
+        Ok(())
+    }
+}
+
+/// A payload whose subject's data key was deleted. It reaches callers as the [`CodecError`] of a
+/// decode error; use [`CodecError::downcast_ref`] to tell it apart, e.g. to skip the record.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShreddedError {
+    subject: String,
+}
+impl ShreddedError {
+    pub fn subject(&self) -> &str {
+        &self.subject
+    }
+}
+impl fmt::Display for ShreddedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "data key for subject `{}` has been deleted",
+            self.subject
+        )
+    }
+}
+impl Error for ShreddedError {}
+
+/// A payload that knows which subject it's encrypted for, so an [`EncryptedCodec`] for it can be
+/// built with `Default`, as a [`Topic::Codec`](crate::Topic::Codec) must be.
+pub trait EncryptionSubject {
+    fn encryption_subject(&self) -> String;
+}
+
+type Subject<P> = Arc<dyn Fn(&P) -> String + Send + Sync>;
+
+/// Encrypts whatever another codec writes with AES-256-GCM, under a data key per subject, e.g.
+/// per customer, for crypto-shredding: [`KeyProvider::delete`] makes a subject's historical
+/// messages unreadable without rewriting topics.
+///
+/// The subject is taken from each payload and written in the clear ahead of the nonce and
+/// ciphertext, so consumers know which key to ask for. It is also bound to the ciphertext, so a
+/// payload can't be passed off as another subject's.
+///
+/// The `Default` codec takes subjects from [`EncryptionSubject`] and keys from `K::default()`.
+/// Topics that don't override [`Topic::codec`](crate::Topic::codec) share one default codec per
+/// type, so they share one key provider too; reach it through [`keys`](Self::keys).
+pub struct EncryptedCodec<P, C, K> {
+    inner: C,
+    keys: Arc<K>,
+    subject: Subject<P>,
+    random: SystemRandom,
+}
+impl<P, C, K: KeyProvider> EncryptedCodec<P, C, K> {
+    /// Encrypts each payload for the subject `subject` picks out of it, using keys from `keys`.
+    pub fn new(
+        inner: C,
+        keys: Arc<K>,
+        subject: impl Fn(&P) -> String + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            inner,
+            keys,
+            subject: Arc::new(subject),
+            random: SystemRandom::new(),
+        }
+    }
+    pub fn keys(&self) -> &Arc<K> {
+        &self.keys
+    }
+
+    fn cipher(key: &DataKey) -> Result<LessSafeKey, CodecError> {
+        let key =
+            UnboundKey::new(&AES_256_GCM, key).map_err(|_| CodecError::new("invalid data key"))?;
+        Ok(LessSafeKey::new(key))
+    }
+    /// Checks and decrypts a payload, returning what the inner codec wrote.
+    fn decrypt(&self, bytes: &[u8]) -> Result<Vec<u8>, CodecError> {
+        let (len, rest) = bytes
+            .split_first_chunk::<2>()
+            .ok_or_else(|| CodecError::new("missing encryption header"))?;
+        let len = u16::from_be_bytes(*len) as usize;
+        if rest.len() < len + NONCE_LEN {
+            return Err(CodecError::new("truncated encryption header"));
+        }
+        let (subject, rest) = rest.split_at(len);
+        let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+        let subject = std::str::from_utf8(subject).map_err(CodecError::new)?;
+
+        let key = self
+            .keys
+            .get(subject)
+            .map_err(CodecError::new)?
+            .ok_or_else(|| {
+                CodecError::new(ShreddedError {
+                    subject: subject.to_string(),
+                })
+            })?;
+        let nonce = Nonce::try_assume_unique_for_key(nonce)
+            .map_err(|_| CodecError::new("invalid nonce"))?;
+        let mut in_out = ciphertext.to_vec();
+        let plaintext_len = Self::cipher(&key)?
+            .open_in_place(nonce, Aad::from(subject.as_bytes()), &mut in_out)
+            .map_err(|_| CodecError::new("failed to decrypt payload"))?
+            .len();
+        in_out.truncate(plaintext_len);
+        Ok(in_out)
+    }
+}
+impl<P, C, K> Default for EncryptedCodec<P, C, K>
+where
+    P: EncryptionSubject + 'static,
+    C: Default,
+    K: KeyProvider + Default,
+{
+    fn default() -> Self {
+        Self::new(C::default(), Arc::new(K::default()), P::encryption_subject)
+    }
+}
+impl<P, C: Clone, K> Clone for EncryptedCodec<P, C, K> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            keys: self.keys.clone(),
+            subject: self.subject.clone(),
+            random: self.random.clone(),
+        }
+    }
+}
+impl<P, C: PayloadCodec<P>, K: KeyProvider> PayloadCodec<P> for EncryptedCodec<P, C, K> {
+    fn encode(&self, payload: &P) -> Result<Vec<u8>, CodecError> {
+        let mut bytes = Vec::new();
+        self.encode_into(payload, &mut bytes)?;
+        Ok(bytes)
+    }
+    fn decode(&self, bytes: &[u8]) -> Result<P, CodecError> {
+        self.inner.decode(&self.decrypt(bytes)?)
+    }
+    fn encode_into(&self, payload: &P, buffer: &mut Vec<u8>) -> Result<(), CodecError> {
+        let subject = (self.subject)(payload);
+        let len = u16::try_from(subject.len())
+            .map_err(|_| CodecError::new("encryption subject is longer than 65535 bytes"))?;
+        let key = self.keys.get_or_create(&subject).map_err(CodecError::new)?;
+        let mut nonce = [0; NONCE_LEN];
+        self.random
+            .fill(&mut nonce)
+            .map_err(|_| CodecError::new("failed to generate a nonce"))?;
+
+        let mut in_out = self.inner.encode(payload)?;
+        Self::cipher(&key)?
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(subject.as_bytes()),
+                &mut in_out,
+            )
+            .map_err(|_| CodecError::new("failed to encrypt payload"))?;
+
+        buffer.extend_from_slice(&len.to_be_bytes());
+        buffer.extend_from_slice(subject.as_bytes());
+        buffer.extend_from_slice(&nonce);
+        buffer.extend_from_slice(&in_out);
+        Ok(())
+    }
+    fn payload_headers(&self) -> Vec<(&'static str, Vec<u8>)> {
+        self.inner.payload_headers()
+    }
+    fn decode_with_headers<'h>(
+        &self,
+        bytes: &[u8],
+        header: &dyn Fn(&str) -> Option<&'h [u8]>,
+    ) -> Result<P, CodecError> {
+        self.inner
+            .decode_with_headers(&self.decrypt(bytes)?, header)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::JsonCodec;
+
+    fn codec() -> EncryptedCodec<(String, u32), JsonCodec, MemoryKeyProvider> {
+        EncryptedCodec::new(
+            JsonCodec,
+            Arc::new(MemoryKeyProvider::new()),
+            |(subject, _): &(String, u32)| subject.clone(),
+        )
+    }
+
+    #[test]
+    fn round_trips() {
+        let codec = codec();
+        let payload = ("alice".to_string(), 7);
+
+        let bytes = codec.encode(&payload).unwrap();
+        assert_eq!(codec.decode(&bytes).unwrap(), payload);
+    }
+
+    #[test]
+    fn deleting_the_key_shreds_the_subject() {
+        let codec = codec();
+        let alice = codec.encode(&("alice".to_string(), 1)).unwrap();
+        let bob = codec.encode(&("bob".to_string(), 2)).unwrap();
+
+        codec.keys().delete("alice").unwrap();
+        let err = codec.decode(&alice).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<ShreddedError>().unwrap().subject(),
+            "alice"
+        );
+        assert_eq!(codec.decode(&bob).unwrap(), ("bob".to_string(), 2));
+    }
+
+    #[test]
+    fn rejects_a_payload_relabelled_as_another_subject() {
+        let codec = codec();
+        codec.encode(&("bobby".to_string(), 0)).unwrap();
+        let mut bytes = codec.encode(&("alice".to_string(), 1)).unwrap();
+
+        bytes[2..7].copy_from_slice(b"bobby");
+        assert!(codec.decode(&bytes).is_err());
+    }
+
+    #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+    struct Order {
+        customer: String,
+    }
+    impl EncryptionSubject for Order {
+        fn encryption_subject(&self) -> String {
+            self.customer.clone()
+        }
+    }
+
+    #[test]
+    fn default_codec_can_back_a_topic() {
+        fn topic_codec<P, C: PayloadCodec<P> + Default + Send + Sync + 'static>() -> C {
+            C::default()
+        }
+        let codec = topic_codec::<Order, EncryptedCodec<Order, JsonCodec, MemoryKeyProvider>>();
+        let order = Order {
+            customer: "alice".to_string(),
+        };
+
+        let bytes = codec.encode(&order).unwrap();
+        assert_eq!(&bytes[2..7], b"alice");
+        assert_eq!(codec.decode(&bytes).unwrap(), order);
+    }
+}
@@ -0,0 +1,113 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use super::{CodecError, JsonCodec, PayloadCodec, SerializingCodec};
+
+/// Metadata every event carries regardless of its topic, wrapped around the payload.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Envelope<P> {
+    /// A random UUID identifying this event.
+    pub event_id: String,
+    /// When the event was wrapped, in milliseconds since the Unix epoch.
+    pub occurred_at: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub producer_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schema_version: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trace_id: Option<String>,
+    pub payload: P,
+}
+impl<P> Envelope<P> {
+    /// Wraps `payload` with a fresh event id, stamped with the current time.
+    pub fn new(payload: P) -> Self {
+        let occurred_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_millis() as i64);
+
+        Self {
+            event_id: uuid::Uuid::new_v4().to_string(),
+            occurred_at,
+            producer_id: None,
+            schema_version: None,
+            trace_id: None,
+            payload,
+        }
+    }
+    pub fn with_producer_id(mut self, producer_id: impl Into<String>) -> Self {
+        self.producer_id = Some(producer_id.into());
+        self
+    }
+    pub fn with_schema_version(mut self, schema_version: u32) -> Self {
+        self.schema_version = Some(schema_version);
+        self
+    }
+    pub fn with_trace_id(mut self, trace_id: impl Into<String>) -> Self {
+        self.trace_id = Some(trace_id.into());
+        self
+    }
+}
+
+/// Wraps payloads in an [`Envelope`] when encoding and unwraps them when decoding, so topics can
+/// share a metadata contract without their payload types knowing about it.
+///
+/// Use [`TypedMessage::envelope`](crate::TypedMessage::envelope) to read the metadata back.
+#[derive(Debug, Default, Clone)]
+pub struct EnvelopeCodec<C = JsonCodec> {
+    inner: C,
+    producer_id: Option<String>,
+    schema_version: Option<u32>,
+}
+impl<C> EnvelopeCodec<C> {
+    pub fn new(inner: C) -> Self {
+        Self {
+            inner,
+            producer_id: None,
+            schema_version: None,
+        }
+    }
+    /// Stamps every envelope with this producer id.
+    pub fn with_producer_id(mut self, producer_id: impl Into<String>) -> Self {
+        self.producer_id = Some(producer_id.into());
+        self
+    }
+    /// Stamps every envelope with this schema version.
+    pub fn with_schema_version(mut self, schema_version: u32) -> Self {
+        self.schema_version = Some(schema_version);
+        self
+    }
+    /// Decodes the whole envelope rather than just its payload.
+    pub fn decode_envelope<P>(&self, bytes: &[u8]) -> Result<Envelope<P>, CodecError>
+    where
+        C: PayloadCodec<Envelope<P>>,
+    {
+        self.inner.decode(bytes)
+    }
+
+    fn wrap<'p, P>(&self, payload: &'p P) -> Envelope<&'p P> {
+        Envelope {
+            producer_id: self.producer_id.clone(),
+            schema_version: self.schema_version,
+            ..Envelope::new(payload)
+        }
+    }
+}
+/// Encoding wraps a borrow of the payload, which serializes the same as the payload itself.
+impl<P, C> PayloadCodec<P> for EnvelopeCodec<C>
+where
+    P: Serialize,
+    C: PayloadCodec<Envelope<P>> + SerializingCodec,
+{
+    fn encode(&self, payload: &P) -> Result<Vec<u8>, CodecError> {
+        let mut buffer = Vec::new();
+        self.encode_into(payload, &mut buffer)?;
+        Ok(buffer)
+    }
+    fn decode(&self, bytes: &[u8]) -> Result<P, CodecError> {
+        Ok(self.decode_envelope(bytes)?.payload)
+    }
+    fn encode_into(&self, payload: &P, buffer: &mut Vec<u8>) -> Result<(), CodecError> {
+        self.inner.serialize_into(&self.wrap(payload), buffer)
+    }
+}
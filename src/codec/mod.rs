@@ -0,0 +1,498 @@
+#[cfg(feature = "avro")]
+pub mod avro;
+#[cfg(feature = "bincode")]
+pub mod bincode;
+#[cfg(feature = "cbor")]
+pub mod cbor;
+#[cfg(any(feature = "gzip", feature = "lz4", feature = "zstd"))]
+pub mod compressed;
+pub mod confluent;
+#[cfg(feature = "encryption")]
+pub mod encrypted;
+#[cfg(feature = "envelope")]
+pub mod envelope;
+#[cfg(feature = "msgpack")]
+pub mod msgpack;
+#[cfg(feature = "protobuf")]
+pub mod protobuf;
+pub mod versioned;
+
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+    error::Error,
+    fmt,
+    sync::{OnceLock, RwLock},
+};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+
+/// Turns a topic's payloads into bytes on the wire and back again.
+pub trait PayloadCodec<P> {
+    fn encode(&self, payload: &P) -> Result<Vec<u8>, CodecError>;
+    fn decode(&self, bytes: &[u8]) -> Result<P, CodecError>;
+    /// Appends the encoded payload to `buffer`. Codecs that can write into an existing buffer
+    /// should override this so pooled sends don't allocate.
+    fn encode_into(&self, payload: &P, buffer: &mut Vec<u8>) -> Result<(), CodecError> {
+        buffer.extend_from_slice(&self.encode(payload)?);
+        Ok(())
+    }
+    /// Headers to send alongside every payload this codec writes, for codecs that describe the
+    /// encoding out of band.
+    fn payload_headers(&self) -> Vec<(&'static str, Vec<u8>)> {
+        Vec::new()
+    }
+    /// Decodes a payload, looking up the record's headers through `header` for codecs that
+    /// wrote any with [`payload_headers`](Self::payload_headers).
+    fn decode_with_headers<'h>(
+        &self,
+        bytes: &[u8],
+        header: &dyn Fn(&str) -> Option<&'h [u8]>,
+    ) -> Result<P, CodecError> {
+        let _ = header;
+        self.decode(bytes)
+    }
+}
+
+/// The one `Default` instance of `C` shared by every topic that doesn't override
+/// [`Topic::codec`](crate::Topic::codec), so codecs that are costly to build, such as
+/// [`AvroCodec`](avro::AvroCodec) deriving its schema, are only built once per type.
+pub(crate) fn shared_default<C: Default + Send + Sync + 'static>() -> &'static C {
+    type Codecs = RwLock<HashMap<TypeId, &'static (dyn Any + Send + Sync)>>;
+    static CODECS: OnceLock<Codecs> = OnceLock::new();
+
+    let codecs = CODECS.get_or_init(Codecs::default);
+    let shared = codecs
+        .read()
+        .unwrap_or_else(|err| err.into_inner())
+        .get(&TypeId::of::<C>())
+        .copied();
+    let shared = shared.unwrap_or_else(|| {
+        *codecs
+            .write()
+            .unwrap_or_else(|err| err.into_inner())
+            .entry(TypeId::of::<C>())
+            // Leaked on purpose: there is one per codec type, for the life of the process.
+            .or_insert_with(|| Box::leak(Box::new(C::default())))
+    });
+    shared
+        .downcast_ref()
+        .expect("shared codecs are keyed by their type")
+}
+
+/// A codec that can deserialize straight out of the record buffer, for payload types that
+/// borrow strings and byte slices instead of allocating them.
+pub trait BorrowingCodec {
+    fn decode_borrowed<'de, P: Deserialize<'de>>(&self, bytes: &'de [u8]) -> Result<P, CodecError>;
+}
+
+/// A codec that can serialize any `Serialize` value, for wrappers that encode a borrowed payload
+/// inside their own type rather than cloning it.
+pub trait SerializingCodec {
+    fn serialize_into<T: Serialize + ?Sized>(
+        &self,
+        value: &T,
+        buffer: &mut Vec<u8>,
+    ) -> Result<(), CodecError>;
+}
+
+/// An error from a [`PayloadCodec`], wrapping whatever the underlying format reported.
+#[derive(Debug)]
+pub struct CodecError(Box<dyn Error + Send + Sync>);
+impl CodecError {
+    pub fn new(err: impl Into<Box<dyn Error + Send + Sync>>) -> Self {
+        Self(err.into())
+    }
+    pub fn into_inner(self) -> Box<dyn Error + Send + Sync> {
+        self.0
+    }
+    /// The underlying error, if it is an `E`.
+    pub fn downcast_ref<E: Error + 'static>(&self) -> Option<&E> {
+        self.0.downcast_ref()
+    }
+}
+impl fmt::Display for CodecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+impl Error for CodecError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.0.source()
+    }
+}
+impl From<serde_json::Error> for CodecError {
+    fn from(err: serde_json::Error) -> Self {
+        Self::new(err)
+    }
+}
+
+/// Encodes a message key. Keys that serialize to a string, such as `String` or unit enum
+/// variants, are written as bare UTF-8 so they hash and display the same as untyped string keys;
+/// anything else is written as JSON.
+pub fn encode_key<K: Serialize>(key: &K) -> Result<Vec<u8>, CodecError> {
+    match serde_json::to_value(key)? {
+        Value::String(key) => Ok(key.into_bytes()),
+        key => Ok(serde_json::to_vec(&key)?),
+    }
+}
+
+/// Decodes a message key written by [`encode_key`].
+pub fn decode_key<K: DeserializeOwned>(bytes: &[u8]) -> Result<K, CodecError> {
+    if let Ok(key) = std::str::from_utf8(bytes) {
+        if let Ok(key) = serde_json::from_value(Value::String(key.to_string())) {
+            return Ok(key);
+        }
+    }
+    Ok(serde_json::from_slice(bytes)?)
+}
+
+/// Encodes payloads as JSON with `serde_json`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct JsonCodec;
+impl<P: Serialize + DeserializeOwned> PayloadCodec<P> for JsonCodec {
+    fn encode(&self, payload: &P) -> Result<Vec<u8>, CodecError> {
+        Ok(serde_json::to_vec(payload)?)
+    }
+    fn decode(&self, bytes: &[u8]) -> Result<P, CodecError> {
+        Ok(serde_json::from_slice(bytes)?)
+    }
+    fn encode_into(&self, payload: &P, buffer: &mut Vec<u8>) -> Result<(), CodecError> {
+        Ok(serde_json::to_writer(buffer, payload)?)
+    }
+}
+
+impl SerializingCodec for JsonCodec {
+    fn serialize_into<T: Serialize + ?Sized>(
+        &self,
+        value: &T,
+        buffer: &mut Vec<u8>,
+    ) -> Result<(), CodecError> {
+        Ok(serde_json::to_writer(buffer, value)?)
+    }
+}
+
+impl BorrowingCodec for JsonCodec {
+    fn decode_borrowed<'de, P: Deserialize<'de>>(&self, bytes: &'de [u8]) -> Result<P, CodecError> {
+        Ok(serde_json::from_slice(bytes)?)
+    }
+}
+
+/// Encodes payloads as canonical JSON: no whitespace, object keys sorted, and floats with no
+/// fractional part written as integers. Equal payloads always encode to the same bytes, so the
+/// output is safe to hash, sign, or dedupe on. Decodes any JSON.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CanonicalJsonCodec;
+impl<P: Serialize + DeserializeOwned> PayloadCodec<P> for CanonicalJsonCodec {
+    fn encode(&self, payload: &P) -> Result<Vec<u8>, CodecError> {
+        let mut buffer = Vec::new();
+        self.encode_into(payload, &mut buffer)?;
+        Ok(buffer)
+    }
+    fn decode(&self, bytes: &[u8]) -> Result<P, CodecError> {
+        Ok(serde_json::from_slice(bytes)?)
+    }
+    fn encode_into(&self, payload: &P, buffer: &mut Vec<u8>) -> Result<(), CodecError> {
+        self.serialize_into(payload, buffer)
+    }
+}
+
+impl SerializingCodec for CanonicalJsonCodec {
+    fn serialize_into<T: Serialize + ?Sized>(
+        &self,
+        value: &T,
+        buffer: &mut Vec<u8>,
+    ) -> Result<(), CodecError> {
+        write_canonical(&serde_json::to_value(value)?, buffer)
+    }
+}
+
+impl BorrowingCodec for CanonicalJsonCodec {
+    fn decode_borrowed<'de, P: Deserialize<'de>>(&self, bytes: &'de [u8]) -> Result<P, CodecError> {
+        Ok(serde_json::from_slice(bytes)?)
+    }
+}
+
+fn write_canonical(value: &Value, buffer: &mut Vec<u8>) -> Result<(), CodecError> {
+    // Floats past 2^53 can't be told apart from their neighbours as integers, so they keep
+    // serde_json's shortest round-trip formatting.
+    const MAX_EXACT_FLOAT: f64 = 9_007_199_254_740_992.0;
+
+    match value {
+        Value::Number(number) => match number.as_f64() {
+            Some(float)
+                if number.is_f64() && float.fract() == 0.0 && float.abs() < MAX_EXACT_FLOAT =>
+            {
+                serde_json::to_writer(buffer, &(float as i64))?
+            }
+            _ => serde_json::to_writer(buffer, number)?,
+        },
+        Value::Array(values) => {
+            buffer.push(b'[');
+            for (i, value) in values.iter().enumerate() {
+                if i > 0 {
+                    buffer.push(b',');
+                }
+                write_canonical(value, buffer)?;
+            }
+            buffer.push(b']');
+        }
+        Value::Object(map) => {
+            let mut entries: Vec<_> = map.iter().collect();
+            entries.sort_unstable_by_key(|(key, _)| *key);
+
+            buffer.push(b'{');
+            for (i, (key, value)) in entries.into_iter().enumerate() {
+                if i > 0 {
+                    buffer.push(b',');
+                }
+                serde_json::to_writer(&mut *buffer, key)?;
+                buffer.push(b':');
+                write_canonical(value, buffer)?;
+            }
+            buffer.push(b'}');
+        }
+        Value::Null | Value::Bool(_) | Value::String(_) => serde_json::to_writer(buffer, value)?,
+    }
+    Ok(())
+}
+
+/// Encodes payloads as JSON with per-topic conventions that don't depend on the payload type's
+/// `#[serde]` attributes, for talking to legacy topics. Override
+/// [`Topic::codec`](crate::Topic::codec) to configure it.
+///
+/// Field renaming applies to every object key, including those of maps.
+#[derive(Debug, Clone, Copy)]
+pub struct StyledJsonCodec {
+    pretty: bool,
+    rename_fields: Option<RenameFields>,
+}
+#[derive(Debug, Clone, Copy)]
+struct RenameFields {
+    encode: fn(&str) -> String,
+    decode: fn(&str) -> String,
+}
+impl StyledJsonCodec {
+    pub fn new() -> Self {
+        Self {
+            pretty: false,
+            rename_fields: None,
+        }
+    }
+    /// Writes indented, multi-line JSON.
+    pub fn pretty(mut self) -> Self {
+        self.pretty = true;
+        self
+    }
+    /// Renames object keys with `encode` when writing and `decode` when reading. The two should
+    /// undo each other.
+    pub fn with_field_names(
+        mut self,
+        encode: fn(&str) -> String,
+        decode: fn(&str) -> String,
+    ) -> Self {
+        self.rename_fields = Some(RenameFields { encode, decode });
+        self
+    }
+    /// Writes `snake_case` fields as `camelCase`, and reads them back.
+    pub fn with_camel_case_fields(self) -> Self {
+        self.with_field_names(snake_to_camel, camel_to_snake)
+    }
+}
+impl Default for StyledJsonCodec {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl<P: Serialize + DeserializeOwned> PayloadCodec<P> for StyledJsonCodec {
+    fn encode(&self, payload: &P) -> Result<Vec<u8>, CodecError> {
+        let mut buffer = Vec::new();
+        self.encode_into(payload, &mut buffer)?;
+        Ok(buffer)
+    }
+    fn decode(&self, bytes: &[u8]) -> Result<P, CodecError> {
+        let Some(rename) = self.rename_fields else {
+            return Ok(serde_json::from_slice(bytes)?);
+        };
+
+        let value = rename_keys(serde_json::from_slice(bytes)?, rename.decode);
+        Ok(serde_json::from_value(value)?)
+    }
+    fn encode_into(&self, payload: &P, buffer: &mut Vec<u8>) -> Result<(), CodecError> {
+        self.serialize_into(payload, buffer)
+    }
+}
+
+impl SerializingCodec for StyledJsonCodec {
+    fn serialize_into<T: Serialize + ?Sized>(
+        &self,
+        value: &T,
+        buffer: &mut Vec<u8>,
+    ) -> Result<(), CodecError> {
+        let value = match self.rename_fields {
+            Some(rename) => rename_keys(serde_json::to_value(value)?, rename.encode),
+            None => serde_json::to_value(value)?,
+        };
+
+        if self.pretty {
+            serde_json::to_writer_pretty(buffer, &value)?;
+        } else {
+            serde_json::to_writer(buffer, &value)?;
+        }
+        Ok(())
+    }
+}
+
+fn rename_keys(value: Value, rename: fn(&str) -> String) -> Value {
+    match value {
+        Value::Array(values) => Value::Array(
+            values
+                .into_iter()
+                .map(|value| rename_keys(value, rename))
+                .collect(),
+        ),
+        Value::Object(map) => Value::Object(
+            map.into_iter()
+                .map(|(key, value)| (rename(&key), rename_keys(value, rename)))
+                .collect(),
+        ),
+        value => value,
+    }
+}
+
+fn snake_to_camel(name: &str) -> String {
+    let mut camel = String::with_capacity(name.len());
+    let mut upper = false;
+    for c in name.chars() {
+        match c {
+            '_' if !camel.is_empty() => upper = true,
+            c if upper => {
+                camel.extend(c.to_uppercase());
+                upper = false;
+            }
+            c => camel.push(c),
+        }
+    }
+    camel
+}
+
+fn camel_to_snake(name: &str) -> String {
+    let mut snake = String::with_capacity(name.len() + 4);
+    for c in name.chars() {
+        if c.is_uppercase() {
+            if !snake.is_empty() {
+                snake.push('_');
+            }
+            snake.extend(c.to_lowercase());
+        } else {
+            snake.push(c);
+        }
+    }
+    snake
+}
+
+/// Passes payloads through untouched, for topics carrying opaque or externally defined bytes.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RawCodec;
+impl PayloadCodec<Vec<u8>> for RawCodec {
+    fn encode(&self, payload: &Vec<u8>) -> Result<Vec<u8>, CodecError> {
+        Ok(payload.clone())
+    }
+    fn decode(&self, bytes: &[u8]) -> Result<Vec<u8>, CodecError> {
+        Ok(bytes.to_vec())
+    }
+    fn encode_into(&self, payload: &Vec<u8>, buffer: &mut Vec<u8>) -> Result<(), CodecError> {
+        buffer.extend_from_slice(payload);
+        Ok(())
+    }
+}
+
+/// Reads and writes payloads as UTF-8 text, without any quoting or escaping.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TextCodec;
+impl PayloadCodec<String> for TextCodec {
+    fn encode(&self, payload: &String) -> Result<Vec<u8>, CodecError> {
+        Ok(payload.as_bytes().to_vec())
+    }
+    fn decode(&self, bytes: &[u8]) -> Result<String, CodecError> {
+        String::from_utf8(bytes.to_vec()).map_err(CodecError::new)
+    }
+    fn encode_into(&self, payload: &String, buffer: &mut Vec<u8>) -> Result<(), CodecError> {
+        buffer.extend_from_slice(payload.as_bytes());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn writes_string_keys_as_bare_utf8() {
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        enum Region {
+            Eu,
+        }
+
+        assert_eq!(encode_key(&"order-1").unwrap(), b"order-1");
+        assert_eq!(encode_key(&Region::Eu).unwrap(), b"Eu");
+        assert_eq!(encode_key(&42u32).unwrap(), b"42");
+        assert_eq!(encode_key(&(1, "a")).unwrap(), br#"[1,"a"]"#);
+
+        assert_eq!(decode_key::<String>(b"order-1").unwrap(), "order-1");
+        assert_eq!(decode_key::<Region>(b"Eu").unwrap(), Region::Eu);
+        assert_eq!(decode_key::<u32>(b"42").unwrap(), 42);
+        assert_eq!(
+            decode_key::<(u32, String)>(br#"[1,"a"]"#).unwrap(),
+            (1, "a".to_string())
+        );
+        assert!(decode_key::<u32>(b"order-1").is_err());
+    }
+
+    #[test]
+    fn canonical_json_sorts_keys_and_writes_whole_floats_as_integers() {
+        let value = json!({ "b": [2.0, 2.5, 1e300], "a": { "d": null, "c": "x" } });
+
+        let bytes = CanonicalJsonCodec.encode(&value).unwrap();
+        assert_eq!(
+            String::from_utf8(bytes).unwrap(),
+            r#"{"a":{"c":"x","d":null},"b":[2,2.5,1e+300]}"#
+        );
+    }
+
+    #[test]
+    fn converts_between_snake_and_camel_case() {
+        assert_eq!(snake_to_camel("order_id"), "orderId");
+        assert_eq!(snake_to_camel("_private"), "_private");
+        assert_eq!(snake_to_camel("id"), "id");
+        assert_eq!(camel_to_snake("orderId"), "order_id");
+        assert_eq!(camel_to_snake("OrderId"), "order_id");
+        assert_eq!(
+            camel_to_snake(&snake_to_camel("line_item_count")),
+            "line_item_count"
+        );
+    }
+
+    #[test]
+    fn styled_json_renames_fields_both_ways() {
+        let codec = StyledJsonCodec::new().with_camel_case_fields();
+        let value = json!({ "order_id": 1, "line_items": [{ "unit_price": 2 }] });
+
+        let bytes = codec.encode(&value).unwrap();
+        assert_eq!(bytes, br#"{"lineItems":[{"unitPrice":2}],"orderId":1}"#);
+        assert_eq!(
+            PayloadCodec::<serde_json::Value>::decode(&codec, &bytes).unwrap(),
+            value
+        );
+    }
+
+    #[test]
+    fn shares_one_default_codec_per_type() {
+        let a: &'static StyledJsonCodec = shared_default();
+        let b: &'static StyledJsonCodec = shared_default();
+        assert!(std::ptr::eq(a, b));
+    }
+}
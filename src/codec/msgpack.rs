@@ -0,0 +1,33 @@
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use super::{BorrowingCodec, CodecError, PayloadCodec, SerializingCodec};
+
+/// Encodes payloads as MessagePack with `rmp-serde`. Structs are written as maps keyed by field
+/// name, so fields can be added or reordered as with JSON.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct MessagePackCodec;
+impl<P: Serialize + DeserializeOwned> PayloadCodec<P> for MessagePackCodec {
+    fn encode(&self, payload: &P) -> Result<Vec<u8>, CodecError> {
+        rmp_serde::to_vec_named(payload).map_err(CodecError::new)
+    }
+    fn decode(&self, bytes: &[u8]) -> Result<P, CodecError> {
+        rmp_serde::from_slice(bytes).map_err(CodecError::new)
+    }
+    fn encode_into(&self, payload: &P, buffer: &mut Vec<u8>) -> Result<(), CodecError> {
+        rmp_serde::encode::write_named(buffer, payload).map_err(CodecError::new)
+    }
+}
+impl SerializingCodec for MessagePackCodec {
+    fn serialize_into<T: Serialize + ?Sized>(
+        &self,
+        value: &T,
+        buffer: &mut Vec<u8>,
+    ) -> Result<(), CodecError> {
+        rmp_serde::encode::write_named(buffer, value).map_err(CodecError::new)
+    }
+}
+impl BorrowingCodec for MessagePackCodec {
+    fn decode_borrowed<'de, P: Deserialize<'de>>(&self, bytes: &'de [u8]) -> Result<P, CodecError> {
+        rmp_serde::from_slice(bytes).map_err(CodecError::new)
+    }
+}
@@ -0,0 +1,126 @@
+use std::marker::PhantomData;
+
+use prost::Message;
+
+#[cfg(feature = "schema-registry")]
+use crate::schema_registry::{SchemaRegistryClient, SchemaRegistryError, SchemaType};
+
+use super::{
+    confluent::{frame, unframe},
+    CodecError, PayloadCodec,
+};
+
+/// Encodes payloads that implement [`prost::Message`] as protobuf.
+///
+/// The default codec writes bare protobuf messages. A codec created with
+/// [`with_schema_id`](Self::with_schema_id) uses the Confluent protobuf wire format instead: the
+/// schema id followed by the message indexes, always `[0]` (the first message in the schema).
+pub struct ProstCodec<P> {
+    schema_id: Option<u32>,
+    _payload: PhantomData<fn() -> P>,
+}
+impl<P> ProstCodec<P> {
+    pub fn with_schema_id(schema_id: u32) -> Self {
+        Self {
+            schema_id: Some(schema_id),
+            _payload: PhantomData,
+        }
+    }
+    /// Registers the `.proto` source of the payload type under `subject`.
+    #[cfg(feature = "schema-registry")]
+    pub async fn register(
+        client: &SchemaRegistryClient,
+        subject: &str,
+        proto: &str,
+    ) -> Result<Self, SchemaRegistryError> {
+        let schema_id = client
+            .register(subject, proto, SchemaType::Protobuf)
+            .await?;
+
+        Ok(Self::with_schema_id(schema_id))
+    }
+}
+impl<P> Default for ProstCodec<P> {
+    fn default() -> Self {
+        Self {
+            schema_id: None,
+            _payload: PhantomData,
+        }
+    }
+}
+impl<P> Clone for ProstCodec<P> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+impl<P> Copy for ProstCodec<P> {}
+impl<P: Message + Default> PayloadCodec<P> for ProstCodec<P> {
+    fn encode(&self, payload: &P) -> Result<Vec<u8>, CodecError> {
+        match self.schema_id {
+            Some(schema_id) => {
+                let mut message = Vec::with_capacity(payload.encoded_len() + 1);
+                // Message indexes `[0]`, which Confluent abbreviates to a single zero.
+                message.push(0);
+                payload.encode(&mut message).map_err(CodecError::new)?;
+                Ok(frame(schema_id, &message))
+            }
+            None => Ok(payload.encode_to_vec()),
+        }
+    }
+    fn decode(&self, bytes: &[u8]) -> Result<P, CodecError> {
+        let message = match self.schema_id {
+            Some(_) => skip_message_indexes(unframe(bytes)?.1)?,
+            None => bytes,
+        };
+
+        P::decode(message).map_err(CodecError::new)
+    }
+}
+
+fn skip_message_indexes(mut bytes: &[u8]) -> Result<&[u8], CodecError> {
+    let count = read_zigzag_varint(&mut bytes)?;
+    for _ in 0..count {
+        read_zigzag_varint(&mut bytes)?;
+    }
+    Ok(bytes)
+}
+
+fn read_zigzag_varint(bytes: &mut &[u8]) -> Result<i64, CodecError> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = bytes
+            .split_first()
+            .ok_or_else(|| CodecError::new("truncated message indexes"))?;
+        *bytes = rest;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Ok((value >> 1) as i64 ^ -((value & 1) as i64));
+        }
+    }
+    Err(CodecError::new("malformed message indexes"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_zigzag_varints() {
+        let mut bytes = &[0x00, 0x01, 0x02, 0xac, 0x02, 0xff][..];
+
+        assert_eq!(read_zigzag_varint(&mut bytes).unwrap(), 0);
+        assert_eq!(read_zigzag_varint(&mut bytes).unwrap(), -1);
+        assert_eq!(read_zigzag_varint(&mut bytes).unwrap(), 1);
+        assert_eq!(read_zigzag_varint(&mut bytes).unwrap(), 150);
+        assert!(read_zigzag_varint(&mut bytes).is_err());
+        assert!(read_zigzag_varint(&mut &[0x80; 10][..]).is_err());
+    }
+
+    #[test]
+    fn skips_message_indexes() {
+        assert_eq!(skip_message_indexes(&[0, 8, 1]).unwrap(), &[8, 1]);
+        // Two indexes, 1 and 2, zigzag encoded.
+        assert_eq!(skip_message_indexes(&[4, 2, 4, 8]).unwrap(), &[8]);
+        assert!(skip_message_indexes(&[4, 2]).is_err());
+    }
+}
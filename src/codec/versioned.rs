@@ -0,0 +1,172 @@
+use std::sync::Arc;
+
+use super::{CodecError, PayloadCodec};
+
+type Fallback<P, C> = Box<dyn Fn(&C, &[u8]) -> Result<P, CodecError> + Send + Sync>;
+
+/// The historical shapes of a payload, newest first, each with a function upgrading it to the
+/// current shape.
+pub struct Fallbacks<P, C> {
+    fallbacks: Vec<Fallback<P, C>>,
+}
+impl<P, C> Fallbacks<P, C> {
+    pub fn new() -> Self {
+        Self {
+            fallbacks: Vec::new(),
+        }
+    }
+    /// Adds an older payload shape, tried after every shape added before it.
+    pub fn then<Old>(mut self, upgrade: impl Fn(Old) -> P + Send + Sync + 'static) -> Self
+    where
+        C: PayloadCodec<Old>,
+    {
+        self.fallbacks
+            .push(Box::new(move |codec: &C, bytes: &[u8]| {
+                codec.decode(bytes).map(&upgrade)
+            }));
+        self
+    }
+}
+impl<P, C> Default for Fallbacks<P, C> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A payload whose older versions can still be read and upgraded, for topics written by
+/// producers that haven't all rolled forward yet.
+///
+/// Implement it with [`Fallbacks::new`] and one [`Fallbacks::then`] per older version, e.g.
+/// `.then(|order: OrderV2| order.into())`.
+pub trait VersionedPayload<C>: Sized {
+    fn fallbacks() -> Fallbacks<Self, C>;
+}
+
+/// Decodes payloads as the current version, falling back through
+/// [`VersionedPayload::fallbacks`] in order when that fails. Payloads are always encoded as the
+/// current version.
+pub struct VersionedCodec<P, C> {
+    inner: C,
+    fallbacks: Arc<Fallbacks<P, C>>,
+}
+impl<P: VersionedPayload<C>, C> VersionedCodec<P, C> {
+    pub fn new(inner: C) -> Self {
+        Self {
+            inner,
+            fallbacks: Arc::new(P::fallbacks()),
+        }
+    }
+}
+impl<P: VersionedPayload<C>, C: Default> Default for VersionedCodec<P, C> {
+    fn default() -> Self {
+        Self::new(C::default())
+    }
+}
+impl<P, C: Clone> Clone for VersionedCodec<P, C> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            fallbacks: self.fallbacks.clone(),
+        }
+    }
+}
+impl<P, C: PayloadCodec<P>> PayloadCodec<P> for VersionedCodec<P, C> {
+    fn encode(&self, payload: &P) -> Result<Vec<u8>, CodecError> {
+        self.inner.encode(payload)
+    }
+    /// Fails with the current version's error if no version matches.
+    fn decode(&self, bytes: &[u8]) -> Result<P, CodecError> {
+        let err = match self.inner.decode(bytes) {
+            Ok(payload) => return Ok(payload),
+            Err(err) => err,
+        };
+
+        self.fallbacks
+            .fallbacks
+            .iter()
+            .find_map(|fallback| fallback(&self.inner, bytes).ok())
+            .ok_or(err)
+    }
+    fn encode_into(&self, payload: &P, buffer: &mut Vec<u8>) -> Result<(), CodecError> {
+        self.inner.encode_into(payload, buffer)
+    }
+    fn payload_headers(&self) -> Vec<(&'static str, Vec<u8>)> {
+        self.inner.payload_headers()
+    }
+    /// Only the current version sees the headers; older versions are tried as plain decodes.
+    fn decode_with_headers<'h>(
+        &self,
+        bytes: &[u8],
+        header: &dyn Fn(&str) -> Option<&'h [u8]>,
+    ) -> Result<P, CodecError> {
+        let err = match self.inner.decode_with_headers(bytes, header) {
+            Ok(payload) => return Ok(payload),
+            Err(err) => err,
+        };
+
+        self.fallbacks
+            .fallbacks
+            .iter()
+            .find_map(|fallback| fallback(&self.inner, bytes).ok())
+            .ok_or(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+    use crate::codec::JsonCodec;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct OrderV3 {
+        id: u64,
+        total_cents: u64,
+    }
+    #[derive(Serialize, Deserialize)]
+    struct OrderV2 {
+        id: u64,
+        total: f64,
+    }
+    #[derive(Serialize, Deserialize)]
+    struct OrderV1 {
+        id: u64,
+    }
+    impl VersionedPayload<JsonCodec> for OrderV3 {
+        fn fallbacks() -> Fallbacks<Self, JsonCodec> {
+            Fallbacks::new()
+                .then(|order: OrderV2| OrderV3 {
+                    id: order.id,
+                    total_cents: (order.total * 100.0) as u64,
+                })
+                .then(|order: OrderV1| OrderV3 {
+                    id: order.id,
+                    total_cents: 0,
+                })
+        }
+    }
+
+    #[test]
+    fn upgrades_older_versions_in_order() {
+        let codec = VersionedCodec::<OrderV3, JsonCodec>::default();
+        let current = OrderV3 {
+            id: 1,
+            total_cents: 250,
+        };
+
+        assert_eq!(
+            codec.decode(&codec.encode(&current).unwrap()).unwrap(),
+            current
+        );
+        assert_eq!(codec.decode(br#"{"id":1,"total":2.5}"#).unwrap(), current);
+        assert_eq!(
+            codec.decode(br#"{"id":1}"#).unwrap(),
+            OrderV3 {
+                id: 1,
+                total_cents: 0
+            }
+        );
+        assert!(codec.decode(br#"{"total":2.5}"#).is_err());
+    }
+}
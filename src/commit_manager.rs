@@ -0,0 +1,154 @@
+//! Committing processed offsets in batches rather than one message at a time, which costs a
+//! round trip to the group coordinator for every message.
+
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use rdkafka::{
+    consumer::{CommitMode, Consumer, ConsumerContext},
+    Message, Offset, TopicPartitionList,
+};
+
+use crate::{error::RdkafkaExtError, Topic, TypedConsumer, TypedMessage};
+
+/// The next offset to commit for each partition with processed messages not yet committed.
+/// Kept in the consumer's context, so they can be committed before their partitions are
+/// revoked.
+#[derive(Default)]
+pub(crate) struct ProcessedOffsets {
+    offsets: Mutex<HashMap<(String, i32), i64>>,
+}
+impl ProcessedOffsets {
+    fn mark(&self, topic: &str, partition: i32, next: i64) {
+        let mut offsets = self.offsets.lock().unwrap_or_else(|err| err.into_inner());
+        let mark = offsets
+            .entry((topic.to_string(), partition))
+            .or_insert(next);
+        *mark = (*mark).max(next);
+    }
+    /// Takes the marks of the partitions `filter` accepts, as a list ready to commit.
+    fn take(&self, mut filter: impl FnMut(&str, i32) -> bool) -> TopicPartitionList {
+        let mut offsets = self.offsets.lock().unwrap_or_else(|err| err.into_inner());
+        let mut list = TopicPartitionList::new();
+        offsets.retain(|(topic, partition), next| match filter(topic, *partition) {
+            true => {
+                let _ = list.add_partition_offset(topic, *partition, Offset::Offset(*next));
+                false
+            }
+            false => true,
+        });
+        list
+    }
+    /// Puts back marks that couldn't be committed, unless newer ones have been made since.
+    fn restore(&self, list: &TopicPartitionList) {
+        for element in list.elements() {
+            if let Offset::Offset(next) = element.offset() {
+                self.mark(element.topic(), element.partition(), next);
+            }
+        }
+    }
+    fn is_empty(&self) -> bool {
+        let offsets = self.offsets.lock().unwrap_or_else(|err| err.into_inner());
+        offsets.is_empty()
+    }
+    /// Commits the marks of partitions about to be revoked, so whoever gets them next doesn't
+    /// process those messages again. Called from the rebalance callback.
+    pub(crate) fn commit_revoked<C: ConsumerContext>(
+        &self,
+        consumer: &impl Consumer<C>,
+        revoked: &TopicPartitionList,
+    ) {
+        let list = self.take(|topic, partition| {
+            revoked
+                .elements()
+                .iter()
+                .any(|element| element.topic() == topic && element.partition() == partition)
+        });
+        // Nothing can be done about a failure here; the messages are processed again.
+        if list.count() > 0 {
+            let _ = consumer.commit(&list, CommitMode::Sync);
+        }
+    }
+}
+
+/// Tracks the messages a [`TypedConsumer`] has processed and commits them in batches: once
+/// enough have been processed, or once enough time has passed since the last commit, whichever
+/// comes first.
+///
+/// Each partition's mark is the highest offset processed in it, so with messages processed out
+/// of order, committing also covers lower offsets that aren't done yet. Marks for partitions
+/// being revoked are committed before the rebalance completes. Only makes sense with
+/// `enable.auto.commit` off.
+pub struct CommitManager<'c, T, C: ConsumerContext + 'static> {
+    consumer: &'c TypedConsumer<T, C>,
+    max_messages: usize,
+    max_interval: Duration,
+    uncommitted: usize,
+    last_commit: Instant,
+}
+impl<'c, T: Topic, C: ConsumerContext + 'static> CommitManager<'c, T, C> {
+    /// Commits every 1000 messages or 5 seconds.
+    pub fn new(consumer: &'c TypedConsumer<T, C>) -> Self {
+        Self {
+            consumer,
+            max_messages: 1000,
+            max_interval: Duration::from_secs(5),
+            uncommitted: 0,
+            last_commit: Instant::now(),
+        }
+    }
+    pub fn with_max_messages(mut self, max_messages: usize) -> Self {
+        self.max_messages = max_messages.max(1);
+        self
+    }
+    pub fn with_max_interval(mut self, max_interval: Duration) -> Self {
+        self.max_interval = max_interval;
+        self
+    }
+    /// Marks `message` as processed, committing asynchronously if a commit is due.
+    pub fn processed(&mut self, message: &TypedMessage<'_, T>) -> Result<(), RdkafkaExtError> {
+        let message = &message.message;
+        self.consumer.inner.context().processed.mark(
+            message.topic(),
+            message.partition(),
+            message.offset() + 1,
+        );
+        self.uncommitted += 1;
+        self.commit_if_due()
+    }
+    /// Commits asynchronously if enough messages or time have gone by. Call it periodically
+    /// when messages are sparse, so the last few don't wait for the next one to be committed.
+    pub fn commit_if_due(&mut self) -> Result<(), RdkafkaExtError> {
+        if self.uncommitted >= self.max_messages || self.last_commit.elapsed() >= self.max_interval
+        {
+            self.commit(CommitMode::Async)?;
+        }
+        Ok(())
+    }
+    /// Commits every mark now.
+    pub fn commit(&mut self, mode: CommitMode) -> Result<(), RdkafkaExtError> {
+        let processed = &self.consumer.inner.context().processed;
+        let list = processed.take(|_, _| true);
+        self.uncommitted = 0;
+        self.last_commit = Instant::now();
+        if list.count() == 0 {
+            return Ok(());
+        }
+
+        self.consumer.inner.commit(&list, mode).map_err(|err| {
+            processed.restore(&list);
+            err.into()
+        })
+    }
+    /// Whether any processed messages are waiting to be committed.
+    pub fn has_uncommitted(&self) -> bool {
+        !self.consumer.inner.context().processed.is_empty()
+    }
+    /// Commits every mark synchronously, for shutting down without processing anything again.
+    pub fn shutdown(mut self) -> Result<(), RdkafkaExtError> {
+        self.commit(CommitMode::Sync)
+    }
+}
@@ -0,0 +1,121 @@
+use std::{
+    error::Error,
+    sync::{Arc, Mutex, OnceLock, RwLock, Weak},
+};
+
+use futures::channel::mpsc::UnboundedSender;
+use rdkafka::{
+    client::OAuthToken,
+    config::RDKafkaLogLevel,
+    consumer::{ConsumerContext, Rebalance, StreamConsumer},
+    error::{KafkaError, KafkaResult},
+    util::Timeout,
+    ClientContext, Statistics, TopicPartitionList,
+};
+
+use crate::{
+    commit_manager::ProcessedOffsets,
+    interceptor::ConsumerInterceptor,
+    offsets::AssignedPartition,
+    rebalance::{RebalanceEvent, SplitChange},
+    retry_topics::ParkedPartitions,
+    stats::{ConsumerStats, StatsReports},
+};
+
+/// The context of every [`TypedConsumer`](crate::TypedConsumer). It holds the state the
+/// consumer's extensions share with librdkafka's callbacks, such as rebalance listeners,
+/// statistics, processed offsets and interceptors, then hands every callback on to the
+/// caller's context.
+pub(crate) struct ConsumerExtContext<C: ConsumerContext + 'static> {
+    /// Set as soon as the consumer is created, so partition queues can be split off from inside
+    /// the rebalance callback.
+    pub(crate) consumer: OnceLock<Weak<StreamConsumer<ConsumerExtContext<C>>>>,
+    pub(crate) context: C,
+    pub(crate) partition_queues: Mutex<Option<UnboundedSender<SplitChange<C>>>>,
+    pub(crate) listeners: Mutex<Vec<UnboundedSender<RebalanceEvent>>>,
+    pub(crate) stats: StatsReports<ConsumerStats>,
+    pub(crate) processed: ProcessedOffsets,
+    pub(crate) parked: ParkedPartitions,
+    pub(crate) interceptors: RwLock<Vec<Arc<dyn ConsumerInterceptor>>>,
+}
+impl<C: ConsumerContext + 'static> ConsumerExtContext<C> {
+    pub(crate) fn new(context: C) -> Self {
+        Self {
+            consumer: OnceLock::new(),
+            context,
+            partition_queues: Mutex::default(),
+            listeners: Mutex::default(),
+            stats: StatsReports::default(),
+            processed: ProcessedOffsets::default(),
+            parked: ParkedPartitions::default(),
+            interceptors: RwLock::default(),
+        }
+    }
+    /// Runs `f` on every interceptor, in order.
+    pub(crate) fn intercept(&self, f: impl FnMut(&Arc<dyn ConsumerInterceptor>) -> bool) -> bool {
+        self.interceptors
+            .read()
+            .unwrap_or_else(|err| err.into_inner())
+            .iter()
+            .all(f)
+    }
+}
+impl<C: ConsumerContext + 'static> ClientContext for ConsumerExtContext<C> {
+    const ENABLE_REFRESH_OAUTH_TOKEN: bool = C::ENABLE_REFRESH_OAUTH_TOKEN;
+
+    fn log(&self, level: RDKafkaLogLevel, fac: &str, log_message: &str) {
+        self.context.log(level, fac, log_message);
+    }
+    fn stats(&self, statistics: Statistics) {
+        self.stats.record(statistics.clone());
+        self.context.stats(statistics);
+    }
+    fn error(&self, error: KafkaError, reason: &str) {
+        self.context.error(error, reason);
+    }
+    fn generate_oauth_token(
+        &self,
+        oauthbearer_config: Option<&str>,
+    ) -> Result<OAuthToken, Box<dyn Error>> {
+        self.context.generate_oauth_token(oauthbearer_config)
+    }
+}
+impl<C: ConsumerContext + 'static> ConsumerContext for ConsumerExtContext<C> {
+    fn pre_rebalance(&self, rebalance: &Rebalance<'_>) {
+        self.context.pre_rebalance(rebalance);
+        if let Rebalance::Revoke(partitions) = rebalance {
+            self.parked.forget(partitions);
+        }
+        if let (Rebalance::Revoke(partitions), Some(consumer)) =
+            (rebalance, self.consumer.get().and_then(Weak::upgrade))
+        {
+            self.processed.commit_revoked(&*consumer, partitions);
+        }
+        match rebalance {
+            Rebalance::Revoke(partitions) => self.notify(RebalanceEvent::Revoked(
+                AssignedPartition::from_list(partitions),
+            )),
+            Rebalance::Error(err) => self.notify(RebalanceEvent::Failed(err.clone())),
+            Rebalance::Assign(_) => {}
+        }
+    }
+    fn post_rebalance(&self, rebalance: &Rebalance<'_>) {
+        self.split_queues(rebalance);
+        if let Rebalance::Assign(partitions) = rebalance {
+            self.notify(RebalanceEvent::Assigned(AssignedPartition::from_list(
+                partitions,
+            )));
+        }
+        self.context.post_rebalance(rebalance);
+    }
+    fn commit_callback(&self, result: KafkaResult<()>, offsets: &TopicPartitionList) {
+        self.intercept(|interceptor| {
+            interceptor.on_commit(result.as_ref().map(|()| offsets));
+            true
+        });
+        self.context.commit_callback(result, offsets);
+    }
+    fn main_queue_min_poll_interval(&self) -> Timeout {
+        self.context.main_queue_min_poll_interval()
+    }
+}
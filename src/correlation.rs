@@ -0,0 +1,41 @@
+//! Tagging records with a `correlation-id` header, to trace a request as it passes between
+//! services over Kafka.
+
+#[cfg(feature = "correlation-id")]
+use rdkafka::{types::RDKafkaErrorCode, ClientContext};
+
+#[cfg(feature = "correlation-id")]
+use crate::{
+    interceptor::{OutgoingRecord, ProducerInterceptor},
+    TypedProducer,
+};
+
+/// The header holding a record's correlation ID.
+pub const CORRELATION_ID: &str = "correlation-id";
+
+/// Gives every record without a `correlation-id` header a new random UUID. Records sent with
+/// one, e.g. copied from the message being handled, keep it.
+#[cfg(feature = "correlation-id")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CorrelationIds;
+#[cfg(feature = "correlation-id")]
+impl ProducerInterceptor for CorrelationIds {
+    fn on_send(&self, record: &mut OutgoingRecord<'_>) -> Result<(), RDKafkaErrorCode> {
+        if record.headers.get_raw(CORRELATION_ID).is_none() {
+            let id = uuid::Uuid::new_v4().to_string();
+            record
+                .headers
+                .insert_raw(CORRELATION_ID, Some(id.into_bytes()));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "correlation-id")]
+impl<C: ClientContext + 'static> TypedProducer<C> {
+    /// Attaches a `correlation-id` header to every record that doesn't already have one. Pass
+    /// one through [`send_with_headers`](Self::send_with_headers) to set it yourself.
+    pub fn with_correlation_ids(self) -> Self {
+        self.with_interceptor(CorrelationIds)
+    }
+}
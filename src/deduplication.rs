@@ -0,0 +1,162 @@
+//! Dropping messages that were already processed, for effectively-once processing on top of
+//! at-least-once delivery. Messages are recognised by their `x-idempotency-key` header, see
+//! [`idempotency`](crate::idempotency), or else by where they were read from.
+
+use std::{
+    collections::{HashSet, VecDeque},
+    error::Error,
+    future::Future,
+    sync::Mutex,
+};
+
+use rdkafka::{
+    consumer::{CommitMode, ConsumerContext, DefaultConsumerContext},
+    message::Headers,
+    Message,
+};
+
+use crate::{
+    error::RdkafkaExtError, idempotency::IDEMPOTENCY_KEY, Topic, TypedConsumer, TypedMessage,
+};
+
+/// Somewhere to remember which messages have been processed, such as Redis or a database table.
+/// To survive restarts and rebalances it should outlive the consumer.
+pub trait DeduplicationStore: Send + Sync {
+    /// Whether a message with this idempotency key has been processed.
+    fn contains(
+        &self,
+        key: &str,
+    ) -> impl Future<Output = Result<bool, Box<dyn Error + Send + Sync>>> + Send;
+    /// Records that a message with this idempotency key has been processed.
+    fn insert(
+        &self,
+        key: &str,
+    ) -> impl Future<Output = Result<(), Box<dyn Error + Send + Sync>>> + Send;
+}
+
+struct RecentKeys {
+    keys: HashSet<String>,
+    order: VecDeque<String>,
+}
+
+/// Remembers the most recently processed `capacity` keys in memory, forgetting the oldest first.
+/// Only catches duplicates seen by this process.
+pub struct MemoryDeduplicationStore {
+    capacity: usize,
+    recent: Mutex<RecentKeys>,
+}
+impl MemoryDeduplicationStore {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            recent: Mutex::new(RecentKeys {
+                keys: HashSet::new(),
+                order: VecDeque::new(),
+            }),
+        }
+    }
+    pub fn len(&self) -> usize {
+        self.recent
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .keys
+            .len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+impl DeduplicationStore for MemoryDeduplicationStore {
+    async fn contains(&self, key: &str) -> Result<bool, Box<dyn Error + Send + Sync>> {
+        let recent = self.recent.lock().unwrap_or_else(|err| err.into_inner());
+        Ok(recent.keys.contains(key))
+    }
+    async fn insert(&self, key: &str) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let mut recent = self.recent.lock().unwrap_or_else(|err| err.into_inner());
+        if recent.keys.insert(key.to_string()) {
+            recent.order.push_back(key.to_string());
+        }
+        while recent.order.len() > self.capacity {
+            if let Some(oldest) = recent.order.pop_front() {
+                recent.keys.remove(&oldest);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The key a message is deduplicated by: its `x-idempotency-key` header, or else its topic,
+/// partition and offset, which only catches redeliveries of the same record.
+pub fn deduplication_key<T: Topic>(message: &TypedMessage<'_, T>) -> String {
+    let header = message
+        .headers()
+        .and_then(|headers| headers.iter().find(|header| header.key == IDEMPOTENCY_KEY))
+        .and_then(|header| header.value);
+    match header {
+        Some(value) => String::from_utf8_lossy(value).into_owned(),
+        None => format!(
+            "{}-{}@{}",
+            message.message.topic(),
+            message.partition(),
+            message.offset()
+        ),
+    }
+}
+
+/// A [`TypedConsumer`] that skips messages its [`DeduplicationStore`] says were already
+/// processed, and records each one it processes.
+pub struct DeduplicatingConsumer<T, S, C: ConsumerContext + 'static = DefaultConsumerContext> {
+    consumer: TypedConsumer<T, C>,
+    store: S,
+}
+impl<T: Topic, S: DeduplicationStore, C: ConsumerContext + 'static> DeduplicatingConsumer<T, S, C> {
+    pub fn new(consumer: TypedConsumer<T, C>, store: S) -> Self {
+        Self { consumer, store }
+    }
+    pub fn consumer(&self) -> &TypedConsumer<T, C> {
+        &self.consumer
+    }
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+    pub async fn is_duplicate(
+        &self,
+        message: &TypedMessage<'_, T>,
+    ) -> Result<bool, RdkafkaExtError> {
+        self.store
+            .contains(&deduplication_key(message))
+            .await
+            .map_err(RdkafkaExtError::DeduplicationStore)
+    }
+    /// Records that the message has been processed, so later copies of it are skipped.
+    pub async fn mark_processed(
+        &self,
+        message: &TypedMessage<'_, T>,
+    ) -> Result<(), RdkafkaExtError> {
+        self.store
+            .insert(&deduplication_key(message))
+            .await
+            .map_err(RdkafkaExtError::DeduplicationStore)
+    }
+    /// Like [`TypedConsumer::process_each`], but skips duplicates, committing them without
+    /// calling `handler`. Messages are marked processed once the handler succeeds, before
+    /// their offset is committed, so if the consumer dies in between, the redelivered copy is
+    /// skipped.
+    pub async fn process_each<F, E>(&self, mut handler: F) -> Result<(), RdkafkaExtError>
+    where
+        F: AsyncFnMut(&TypedMessage<'_, T>) -> Result<(), E>,
+        E: Into<Box<dyn Error + Send + Sync>>,
+    {
+        loop {
+            let message = self.consumer.recv().await?;
+            if !self.is_duplicate(&message).await? {
+                self.consumer
+                    .poison_policy
+                    .attempt(&message.message, async || handler(&message).await)
+                    .await?;
+                self.mark_processed(&message).await?;
+            }
+            self.consumer.commit_message(&message, CommitMode::Async)?;
+        }
+    }
+}
@@ -0,0 +1,235 @@
+//! Holding records back until a delay has passed, since Kafka has no delayed delivery of its
+//! own.
+//!
+//! Records are parked in memory, so ones still parked when the process exits are lost. Use it
+//! for retry backoffs and short-lived scheduling, not as a durable job queue.
+
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, VecDeque},
+    pin::Pin,
+    task::{Context, Poll},
+    time::{Duration, Instant, SystemTime},
+};
+
+use futures::{
+    channel::mpsc::{self, UnboundedReceiver, UnboundedSender},
+    stream::FuturesUnordered,
+    FutureExt, Stream, StreamExt,
+};
+use rdkafka::{
+    client::DefaultClientContext, error::KafkaError, message::OwnedMessage,
+    types::RDKafkaErrorCode, ClientContext, Timestamp,
+};
+use tokio::time::Sleep;
+
+use crate::{
+    delivery::{DeliveryReport, Tagged},
+    error::{DeliveryError, RdkafkaExtError, SendError},
+    EncodedRecord, Topic, TypedProducer,
+};
+
+/// How long to hold a due record back when the producer queue is full.
+const QUEUE_FULL_BACKOFF: Duration = Duration::from_millis(10);
+
+struct Parked<Tag> {
+    due: Instant,
+    sequence: u64,
+    record: EncodedRecord,
+    tag: Tag,
+}
+// Ordered so the `BinaryHeap` pops the earliest due record first, oldest first among equals.
+impl<Tag> Ord for Parked<Tag> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (other.due, other.sequence).cmp(&(self.due, self.sequence))
+    }
+}
+impl<Tag> PartialOrd for Parked<Tag> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl<Tag> PartialEq for Parked<Tag> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+impl<Tag> Eq for Parked<Tag> {}
+
+/// Schedules records to be sent later. They're sent, and their outcomes reported, by the paired
+/// [`DelayedDeliveries`] stream, which has to be polled for anything to be sent at all.
+pub struct DelayedProducer<Tag, C: ClientContext + 'static = DefaultClientContext> {
+    producer: TypedProducer<C>,
+    parked: UnboundedSender<(Instant, EncodedRecord, Tag)>,
+}
+impl<Tag, C: ClientContext + 'static> Clone for DelayedProducer<Tag, C> {
+    fn clone(&self) -> Self {
+        Self {
+            producer: self.producer.clone(),
+            parked: self.parked.clone(),
+        }
+    }
+}
+impl<Tag, C: ClientContext + 'static> DelayedProducer<Tag, C> {
+    /// Serializes a record now and sends it once `delay` has passed.
+    ///
+    /// Fails with a [`Canceled`](KafkaError::Canceled) delivery error if the
+    /// [`DelayedDeliveries`] stream has been dropped.
+    pub fn send_after<T: Topic>(
+        &self,
+        topic: &T,
+        payload: &T::Payload,
+        key: Option<&T::Key>,
+        delay: Duration,
+        tag: Tag,
+    ) -> Result<(), SendError> {
+        let encoded = self.producer.encode(topic, payload, key)?;
+
+        self.parked
+            .unbounded_send((Instant::now() + delay, encoded, tag))
+            .map_err(|err| {
+                let (_, encoded, _) = err.into_inner();
+                DeliveryError {
+                    error: KafkaError::Canceled.into(),
+                    message: Box::new(OwnedMessage::new(
+                        Some(encoded.payload),
+                        encoded.key,
+                        encoded.topic,
+                        Timestamp::NotAvailable,
+                        -1,
+                        -1,
+                        None,
+                    )),
+                }
+                .into()
+            })
+    }
+    /// Like [`send_after`](Self::send_after), but sends at `at`, or straight away if that has
+    /// already passed.
+    pub fn send_at<T: Topic>(
+        &self,
+        topic: &T,
+        payload: &T::Payload,
+        key: Option<&T::Key>,
+        at: SystemTime,
+        tag: Tag,
+    ) -> Result<(), SendError> {
+        let delay = at.duration_since(SystemTime::now()).unwrap_or_default();
+        self.send_after(topic, payload, key, delay, tag)
+    }
+}
+
+/// Sends records parked by [`DelayedProducer`]s as they come due, yielding their outcomes in
+/// the order they complete. Ends once every delayed producer is dropped and every record is
+/// accounted for.
+pub struct DelayedDeliveries<Tag, C: ClientContext + 'static = DefaultClientContext> {
+    producer: TypedProducer<C>,
+    incoming: UnboundedReceiver<(Instant, EncodedRecord, Tag)>,
+    incoming_done: bool,
+    next_sequence: u64,
+    parked: BinaryHeap<Parked<Tag>>,
+    timer: Pin<Box<Sleep>>,
+    failed: VecDeque<DeliveryReport<Tag>>,
+    pending: FuturesUnordered<Tagged<Tag>>,
+}
+impl<Tag, C: ClientContext + 'static> DelayedDeliveries<Tag, C> {
+    /// How many records are parked, waiting to come due.
+    pub fn parked_count(&self) -> usize {
+        self.parked.len()
+    }
+    fn park(&mut self, due: Instant, record: EncodedRecord, tag: Tag) {
+        self.parked.push(Parked {
+            due,
+            sequence: self.next_sequence,
+            record,
+            tag,
+        });
+        self.next_sequence += 1;
+    }
+    /// Enqueues every record that has come due.
+    fn send_due(&mut self) {
+        let now = Instant::now();
+        while self.parked.peek().is_some_and(|parked| parked.due <= now) {
+            let parked = self.parked.pop().expect("peeked");
+
+            match self.producer.enqueue_raw(parked.record.record()) {
+                Ok(delivery) => self.pending.push(Tagged::new(parked.tag, delivery)),
+                Err(err)
+                    if matches!(
+                        err.error,
+                        RdkafkaExtError::Kafka(KafkaError::MessageProduction(
+                            RDKafkaErrorCode::QueueFull
+                        ))
+                    ) =>
+                {
+                    self.park(now + QUEUE_FULL_BACKOFF, parked.record, parked.tag);
+                    break;
+                }
+                Err(err) => self.failed.push_back(DeliveryReport {
+                    tag: parked.tag,
+                    topic: parked.record.topic,
+                    result: Err(err),
+                }),
+            }
+        }
+    }
+}
+impl<Tag, C: ClientContext + 'static> Unpin for DelayedDeliveries<Tag, C> {}
+impl<Tag, C: ClientContext + 'static> Stream for DelayedDeliveries<Tag, C> {
+    type Item = DeliveryReport<Tag>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        while !self.incoming_done {
+            match self.incoming.poll_next_unpin(cx) {
+                Poll::Ready(Some((due, record, tag))) => self.park(due, record, tag),
+                Poll::Ready(None) => self.incoming_done = true,
+                Poll::Pending => break,
+            }
+        }
+
+        loop {
+            self.send_due();
+            let Some(due) = self.parked.peek().map(|parked| parked.due) else {
+                break;
+            };
+            self.timer.as_mut().reset(due.into());
+            if self.timer.poll_unpin(cx).is_pending() {
+                break;
+            }
+        }
+
+        if let Some(report) = self.failed.pop_front() {
+            return Poll::Ready(Some(report));
+        }
+        match self.pending.poll_next_unpin(cx) {
+            Poll::Ready(Some(report)) => Poll::Ready(Some(report)),
+            _ if self.incoming_done && self.parked.is_empty() && self.pending.is_empty() => {
+                Poll::Ready(None)
+            }
+            _ => Poll::Pending,
+        }
+    }
+}
+
+impl<C: ClientContext + 'static> TypedProducer<C> {
+    /// Switches to delayed sends, which are made and reported by a stream that has to be polled.
+    pub fn delayed<Tag>(&self) -> (DelayedProducer<Tag, C>, DelayedDeliveries<Tag, C>) {
+        let (sender, receiver) = mpsc::unbounded();
+
+        let producer = DelayedProducer {
+            producer: self.clone(),
+            parked: sender,
+        };
+        let deliveries = DelayedDeliveries {
+            producer: self.clone(),
+            incoming: receiver,
+            incoming_done: false,
+            next_sequence: 0,
+            parked: BinaryHeap::new(),
+            timer: Box::pin(tokio::time::sleep(Duration::ZERO)),
+            failed: VecDeque::new(),
+            pending: FuturesUnordered::new(),
+        };
+        (producer, deliveries)
+    }
+}
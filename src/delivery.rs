@@ -0,0 +1,214 @@
+//! Tracking deliveries without awaiting each send.
+
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use futures::{
+    channel::mpsc::{self, UnboundedReceiver, UnboundedSender},
+    stream::FuturesUnordered,
+    FutureExt, Stream, StreamExt,
+};
+use rdkafka::{
+    client::DefaultClientContext, error::KafkaError, message::OwnedMessage,
+    producer::DeliveryFuture, ClientContext, Timestamp,
+};
+use tokio::sync::Notify;
+
+use crate::{
+    circuit_breaker::CircuitBreaker,
+    error::{DeliveryError, SendError},
+    interceptor::ProducerInterceptor,
+    record_outcome, report_delivery, Delivery, Topic, TypedProducer,
+};
+
+/// A record that has been enqueued, resolving once the broker acknowledges it or it fails.
+/// Dropping it doesn't cancel the send; the outcome is just never seen.
+pub struct PendingDelivery {
+    future: DeliveryFuture,
+    topic: String,
+    timestamp: Timestamp,
+    breaker: Option<Arc<CircuitBreaker>>,
+    interceptors: Vec<Arc<dyn ProducerInterceptor>>,
+    drained: Arc<Notify>,
+}
+impl PendingDelivery {
+    pub(crate) fn new(
+        future: DeliveryFuture,
+        topic: String,
+        timestamp: Timestamp,
+        breaker: Option<Arc<CircuitBreaker>>,
+        interceptors: Vec<Arc<dyn ProducerInterceptor>>,
+        drained: Arc<Notify>,
+    ) -> Self {
+        Self {
+            future,
+            topic,
+            timestamp,
+            breaker,
+            interceptors,
+            drained,
+        }
+    }
+}
+impl Future for PendingDelivery {
+    type Output = Result<Delivery, DeliveryError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let result = match self.future.poll_unpin(cx) {
+            Poll::Ready(result) => result,
+            Poll::Pending => return Poll::Pending,
+        };
+
+        record_outcome(self.breaker.as_deref(), matches!(result, Ok(Ok(_))));
+        self.drained.notify_waiters();
+        let result = match result {
+            Ok(Ok((partition, offset))) => Ok(Delivery {
+                partition,
+                offset,
+                timestamp: self.timestamp,
+            }),
+            Ok(Err((error, message))) => Err(DeliveryError {
+                error: error.into(),
+                message: Box::new(message),
+            }),
+            // The producer was dropped with the record still queued.
+            Err(_) => Err(DeliveryError {
+                error: KafkaError::Canceled.into(),
+                message: Box::new(OwnedMessage::new(
+                    None,
+                    None,
+                    self.topic.clone(),
+                    self.timestamp,
+                    -1,
+                    -1,
+                    None,
+                )),
+            }),
+        };
+
+        report_delivery(&self.interceptors, &self.topic, result.as_ref());
+        Poll::Ready(result)
+    }
+}
+
+/// The outcome of a record sent through a [`DeliveryReporter`].
+#[derive(Debug)]
+pub struct DeliveryReport<Tag> {
+    /// Whatever the caller passed to [`DeliveryReporter::send`] to tell records apart.
+    pub tag: Tag,
+    pub topic: String,
+    pub result: Result<Delivery, DeliveryError>,
+}
+
+/// Sends records without waiting for them to be delivered. Their outcomes arrive on the paired
+/// [`DeliveryReports`] stream instead.
+pub struct DeliveryReporter<Tag, C: ClientContext + 'static = DefaultClientContext> {
+    producer: TypedProducer<C>,
+    pending: UnboundedSender<(Tag, PendingDelivery)>,
+}
+impl<Tag, C: ClientContext + 'static> Clone for DeliveryReporter<Tag, C> {
+    fn clone(&self) -> Self {
+        Self {
+            producer: self.producer.clone(),
+            pending: self.pending.clone(),
+        }
+    }
+}
+impl<Tag, C: ClientContext + 'static> DeliveryReporter<Tag, C> {
+    /// Enqueues a record, returning once it's queued. Errors are only returned for records that
+    /// couldn't be serialized or queued; delivery failures are reported on the stream.
+    pub fn send<T: Topic>(
+        &self,
+        topic: &T,
+        payload: &T::Payload,
+        key: Option<&T::Key>,
+        tag: Tag,
+    ) -> Result<(), SendError> {
+        let encoded = self.producer.encode(topic, payload, key)?;
+        let pending = self.producer.enqueue_raw(encoded.record())?;
+
+        // The stream going away only means nobody is listening for reports any more.
+        let _ = self.pending.unbounded_send((tag, pending));
+        Ok(())
+    }
+}
+
+/// A pending delivery resolving to a report with its tag.
+pub(crate) struct Tagged<Tag> {
+    tag: Option<Tag>,
+    delivery: PendingDelivery,
+}
+impl<Tag> Tagged<Tag> {
+    pub(crate) fn new(tag: Tag, delivery: PendingDelivery) -> Self {
+        Self {
+            tag: Some(tag),
+            delivery,
+        }
+    }
+}
+impl<Tag> Unpin for Tagged<Tag> {}
+impl<Tag> Future for Tagged<Tag> {
+    type Output = DeliveryReport<Tag>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let result = match self.delivery.poll_unpin(cx) {
+            Poll::Ready(result) => result,
+            Poll::Pending => return Poll::Pending,
+        };
+
+        Poll::Ready(DeliveryReport {
+            tag: self.tag.take().expect("polled after completion"),
+            topic: self.delivery.topic.clone(),
+            result,
+        })
+    }
+}
+
+/// The outcomes of records sent through a [`DeliveryReporter`], in the order they complete.
+/// Ends once every reporter is dropped and every record is accounted for.
+pub struct DeliveryReports<Tag> {
+    incoming: UnboundedReceiver<(Tag, PendingDelivery)>,
+    incoming_done: bool,
+    pending: FuturesUnordered<Tagged<Tag>>,
+}
+impl<Tag> Stream for DeliveryReports<Tag> {
+    type Item = DeliveryReport<Tag>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        while !self.incoming_done {
+            match self.incoming.poll_next_unpin(cx) {
+                Poll::Ready(Some((tag, delivery))) => self.pending.push(Tagged::new(tag, delivery)),
+                Poll::Ready(None) => self.incoming_done = true,
+                Poll::Pending => break,
+            }
+        }
+
+        match self.pending.poll_next_unpin(cx) {
+            Poll::Ready(None) if !self.incoming_done => Poll::Pending,
+            poll => poll,
+        }
+    }
+}
+
+impl<C: ClientContext + 'static> TypedProducer<C> {
+    /// Switches to fire-and-forget sends, reporting each outcome on a stream. The stream has to
+    /// be polled for reports to be collected.
+    pub fn delivery_reports<Tag>(&self) -> (DeliveryReporter<Tag, C>, DeliveryReports<Tag>) {
+        let (sender, receiver) = mpsc::unbounded();
+
+        let reporter = DeliveryReporter {
+            producer: self.clone(),
+            pending: sender,
+        };
+        let reports = DeliveryReports {
+            incoming: receiver,
+            incoming_done: false,
+            pending: FuturesUnordered::new(),
+        };
+        (reporter, reports)
+    }
+}
@@ -0,0 +1,449 @@
+use std::{error::Error, fmt};
+
+use rdkafka::{
+    error::KafkaError,
+    message::{Message, OwnedMessage},
+    types::RDKafkaErrorCode,
+};
+
+use crate::codec::CodecError;
+#[cfg(feature = "schema-registry")]
+use crate::schema_registry::SchemaRegistryError;
+
+#[derive(Debug)]
+pub enum RdkafkaExtError {
+    /// An error reported by librdkafka.
+    Kafka(KafkaError),
+    /// A payload could not be serialized or deserialized.
+    Codec(CodecError),
+    /// A record couldn't be serialized or delivered. Boxed, since it keeps the topic, key and
+    /// the unsent record.
+    Send(Box<SendError>),
+    /// A received payload could not be deserialized.
+    Decode(DecodeError),
+    /// An admin operation failed for the given resource.
+    Admin {
+        resource: String,
+        code: RDKafkaErrorCode,
+    },
+    /// A config builder was asked to produce an invalid config.
+    Config(ConfigValidationError),
+    /// A message handler gave up on a message.
+    Handler(Box<dyn Error + Send + Sync>),
+    /// A claim-check blob store failed to store or fetch a payload.
+    BlobStore(Box<dyn Error + Send + Sync>),
+    /// A deduplication store failed to look up or record a processed message.
+    DeduplicationStore(Box<dyn Error + Send + Sync>),
+    /// An offset store failed to load or save an offset.
+    OffsetStore(Box<dyn Error + Send + Sync>),
+    /// An outbox source failed to fetch pending records or mark them sent.
+    Outbox(Box<dyn Error + Send + Sync>),
+    /// A message arrived from a topic its consumer's [`Topic`](crate::Topic) type couldn't be
+    /// rebuilt from.
+    UnknownTopic(String),
+    /// The runtime a blocking client drives its async client on couldn't be started.
+    Runtime(std::io::Error),
+    /// The producer's [`CircuitBreaker`](crate::circuit_breaker::CircuitBreaker) is open, so
+    /// the record wasn't sent.
+    CircuitOpen,
+    /// The producer is shutting down and no longer accepts records.
+    ShuttingDown,
+    /// The schema registry couldn't be reached or rejected a request.
+    #[cfg(feature = "schema-registry")]
+    SchemaRegistry(SchemaRegistryError),
+}
+impl fmt::Display for RdkafkaExtError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RdkafkaExtError::Kafka(err) => err.fmt(f),
+            RdkafkaExtError::Codec(err) => err.fmt(f),
+            RdkafkaExtError::Send(err) => err.fmt(f),
+            RdkafkaExtError::Decode(err) => err.fmt(f),
+            RdkafkaExtError::Admin { resource, .. } => {
+                write!(f, "admin operation on `{resource}` failed")
+            }
+            RdkafkaExtError::Config(err) => err.fmt(f),
+            RdkafkaExtError::Handler(_) => f.write_str("message handler failed"),
+            RdkafkaExtError::BlobStore(_) => f.write_str("blob store request failed"),
+            RdkafkaExtError::DeduplicationStore(_) => {
+                f.write_str("deduplication store request failed")
+            }
+            RdkafkaExtError::OffsetStore(_) => f.write_str("offset store request failed"),
+            RdkafkaExtError::Outbox(_) => f.write_str("outbox request failed"),
+            RdkafkaExtError::UnknownTopic(topic) => {
+                write!(f, "received a message from unknown topic `{topic}`")
+            }
+            RdkafkaExtError::Runtime(_) => f.write_str("failed to start blocking runtime"),
+            RdkafkaExtError::CircuitOpen => f.write_str("circuit breaker is open"),
+            RdkafkaExtError::ShuttingDown => f.write_str("producer is shutting down"),
+            #[cfg(feature = "schema-registry")]
+            RdkafkaExtError::SchemaRegistry(err) => err.fmt(f),
+        }
+    }
+}
+impl Error for RdkafkaExtError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            RdkafkaExtError::Kafka(err) => err.source(),
+            RdkafkaExtError::Codec(err) => err.source(),
+            RdkafkaExtError::Send(err) => err.source(),
+            RdkafkaExtError::Decode(err) => err.source(),
+            RdkafkaExtError::Admin { code, .. } => Some(code),
+            RdkafkaExtError::Config(err) => err.source(),
+            RdkafkaExtError::Handler(err)
+            | RdkafkaExtError::BlobStore(err)
+            | RdkafkaExtError::DeduplicationStore(err)
+            | RdkafkaExtError::OffsetStore(err)
+            | RdkafkaExtError::Outbox(err) => Some(&**err),
+            RdkafkaExtError::UnknownTopic(_)
+            | RdkafkaExtError::CircuitOpen
+            | RdkafkaExtError::ShuttingDown => None,
+            RdkafkaExtError::Runtime(err) => Some(err),
+            #[cfg(feature = "schema-registry")]
+            RdkafkaExtError::SchemaRegistry(err) => err.source(),
+        }
+    }
+}
+impl RdkafkaExtError {
+    /// Broadly classifies the error so callers can decide whether to retry it.
+    pub fn class(&self) -> ErrorClass {
+        match self {
+            RdkafkaExtError::Kafka(err) => ErrorClass::of_kafka_error(err),
+            RdkafkaExtError::Codec(_) | RdkafkaExtError::Decode(_) => ErrorClass::Serialization,
+            RdkafkaExtError::Send(err) => err.class(),
+            RdkafkaExtError::Admin { code, .. } => ErrorClass::of_code(*code),
+            RdkafkaExtError::Config(_)
+            | RdkafkaExtError::Handler(_)
+            | RdkafkaExtError::UnknownTopic(_)
+            | RdkafkaExtError::Runtime(_)
+            | RdkafkaExtError::ShuttingDown => ErrorClass::Fatal,
+            RdkafkaExtError::BlobStore(_)
+            | RdkafkaExtError::CircuitOpen
+            | RdkafkaExtError::DeduplicationStore(_)
+            | RdkafkaExtError::OffsetStore(_)
+            | RdkafkaExtError::Outbox(_) => ErrorClass::Retryable,
+            #[cfg(feature = "schema-registry")]
+            RdkafkaExtError::SchemaRegistry(err) => err.class(),
+        }
+    }
+}
+impl From<KafkaError> for RdkafkaExtError {
+    fn from(err: KafkaError) -> Self {
+        RdkafkaExtError::Kafka(err)
+    }
+}
+impl From<CodecError> for RdkafkaExtError {
+    fn from(err: CodecError) -> Self {
+        RdkafkaExtError::Codec(err)
+    }
+}
+impl From<DecodeError> for RdkafkaExtError {
+    fn from(err: DecodeError) -> Self {
+        RdkafkaExtError::Decode(err)
+    }
+}
+impl From<SendError> for RdkafkaExtError {
+    fn from(err: SendError) -> Self {
+        RdkafkaExtError::Send(Box::new(err))
+    }
+}
+impl From<SerializeError> for RdkafkaExtError {
+    fn from(err: SerializeError) -> Self {
+        SendError::from(err).into()
+    }
+}
+impl From<DeliveryError> for RdkafkaExtError {
+    fn from(err: DeliveryError) -> Self {
+        SendError::from(err).into()
+    }
+}
+#[cfg(feature = "schema-registry")]
+impl From<SchemaRegistryError> for RdkafkaExtError {
+    fn from(err: SchemaRegistryError) -> Self {
+        RdkafkaExtError::SchemaRegistry(err)
+    }
+}
+impl From<ConfigValidationError> for RdkafkaExtError {
+    fn from(err: ConfigValidationError) -> Self {
+        RdkafkaExtError::Config(err)
+    }
+}
+
+/// A coarse classification of errors, for retry loops and circuit breakers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorClass {
+    /// Likely to succeed if tried again later, e.g. a full queue or a broker failover.
+    Retryable,
+    /// Will keep failing until something about the client, config, or cluster changes.
+    Fatal,
+    /// A payload couldn't be serialized or deserialized. Retrying won't help.
+    Serialization,
+    /// The client isn't allowed to do what it tried to do.
+    Authorization,
+}
+impl ErrorClass {
+    pub fn of_code(code: RDKafkaErrorCode) -> Self {
+        use RDKafkaErrorCode::*;
+
+        match code {
+            KeySerialization | ValueSerialization | KeyDeserialization | ValueDeserialization
+            | BadMessage | BadCompression | InvalidMessage | InvalidRecord => {
+                ErrorClass::Serialization
+            }
+            Authentication
+            | TopicAuthorizationFailed
+            | GroupAuthorizationFailed
+            | ClusterAuthorizationFailed
+            | TransactionalIdAuthorizationFailed
+            | DelegationTokenAuthorizationFailed
+            | SaslAuthenticationFailed
+            | UnsupportedSASLMechanism
+            | IllegalSASLState
+            | UnacceptableCredential => ErrorClass::Authorization,
+            QueueFull
+            | BrokerTransportFailure
+            | AllBrokersDown
+            | Resolve
+            | MessageTimedOut
+            | OperationTimedOut
+            | TimedOutQueue
+            | RequestTimedOut
+            | NetworkException
+            | LeaderNotAvailable
+            | NotLeaderForPartition
+            | BrokerNotAvailable
+            | ReplicaNotAvailable
+            | CoordinatorLoadInProgress
+            | CoordinatorNotAvailable
+            | NotCoordinator
+            | WaitingForCoordinator
+            | NotEnoughReplicas
+            | NotEnoughReplicasAfterAppend
+            | RebalanceInProgress
+            | KafkaStorageError
+            | FetchSessionIdNotFound
+            | InvalidFetchSessionEpoch
+            | UnknownLeaderEpoch
+            | OffsetNotAvailable
+            | PreferredLeaderNotAvailable
+            | EligibleLeadersNotAvailable
+            | ThrottlingQuotaExceeded
+            | UnstableOffsetCommit
+            | ConcurrentTransactions
+            | Retry => ErrorClass::Retryable,
+            _ => ErrorClass::Fatal,
+        }
+    }
+    pub fn of_kafka_error(err: &KafkaError) -> Self {
+        match err {
+            KafkaError::Transaction(err) if err.is_retriable() => ErrorClass::Retryable,
+            KafkaError::NoMessageReceived | KafkaError::PartitionEOF(_) => ErrorClass::Retryable,
+            err => err
+                .rdkafka_error_code()
+                .map_or(ErrorClass::Fatal, ErrorClass::of_code),
+        }
+    }
+}
+
+/// The part of a record that failed to deserialize.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordField {
+    Key,
+    Payload,
+    Header,
+}
+impl fmt::Display for RecordField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecordField::Key => f.write_str("key"),
+            RecordField::Payload => f.write_str("payload"),
+            RecordField::Header => f.write_str("header"),
+        }
+    }
+}
+
+/// A record that failed to deserialize, along with enough about it to go find it.
+#[derive(Debug)]
+pub struct DecodeError {
+    pub field: RecordField,
+    pub topic: String,
+    pub partition: i32,
+    pub offset: i64,
+    pub key: Option<Vec<u8>>,
+    pub payload_len: usize,
+    pub source: CodecError,
+}
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "failed to deserialize {} of {} byte record at {}[{}]@{}",
+            self.field, self.payload_len, self.topic, self.partition, self.offset
+        )
+    }
+}
+impl Error for DecodeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+/// Why a record could not be sent. Serialization failures happen before anything is enqueued
+/// and are never worth retrying; delivery failures happen afterwards and often are.
+#[derive(Debug)]
+pub enum SendError {
+    Serialize(SerializeError),
+    Delivery(DeliveryError),
+}
+impl SendError {
+    /// The topic the record was meant for.
+    pub fn topic(&self) -> &str {
+        match self {
+            SendError::Serialize(err) => &err.topic,
+            SendError::Delivery(err) => err.message.topic(),
+        }
+    }
+    /// The record that failed to send, if it got far enough to be serialized.
+    pub fn message(&self) -> Option<&OwnedMessage> {
+        match self {
+            SendError::Serialize(_) => None,
+            SendError::Delivery(err) => Some(&err.message),
+        }
+    }
+    pub fn class(&self) -> ErrorClass {
+        match self {
+            SendError::Serialize(_) => ErrorClass::Serialization,
+            SendError::Delivery(err) => err.error.class(),
+        }
+    }
+    pub fn into_message(self) -> Option<OwnedMessage> {
+        match self {
+            SendError::Serialize(_) => None,
+            SendError::Delivery(err) => Some(*err.message),
+        }
+    }
+}
+impl fmt::Display for SendError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SendError::Serialize(err) => err.fmt(f),
+            SendError::Delivery(err) => err.fmt(f),
+        }
+    }
+}
+impl Error for SendError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SendError::Serialize(err) => err.source(),
+            SendError::Delivery(err) => err.source(),
+        }
+    }
+}
+impl From<SerializeError> for SendError {
+    fn from(err: SerializeError) -> Self {
+        SendError::Serialize(err)
+    }
+}
+impl From<DeliveryError> for SendError {
+    fn from(err: DeliveryError) -> Self {
+        SendError::Delivery(err)
+    }
+}
+
+/// A key or payload that couldn't be serialized, so nothing was enqueued.
+#[derive(Debug)]
+pub struct SerializeError {
+    pub topic: String,
+    pub source: CodecError,
+}
+impl fmt::Display for SerializeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "failed to serialize record for {}", self.topic)
+    }
+}
+impl Error for SerializeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+/// A serialized record that couldn't be enqueued or wasn't acknowledged. The record is handed
+/// back so it can be retried or buffered without serializing it again.
+#[derive(Debug)]
+pub struct DeliveryError {
+    pub error: RdkafkaExtError,
+    /// Boxed so send results stay small; the record is only looked at when a send fails.
+    pub message: Box<OwnedMessage>,
+}
+impl fmt::Display for DeliveryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "failed to deliver record to {}", self.message.topic())
+    }
+}
+impl Error for DeliveryError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+/// A payload that broke one of its topic's invariants, from
+/// [`Topic::validate_outgoing`](crate::Topic::validate_outgoing) or
+/// [`Topic::validate_incoming`](crate::Topic::validate_incoming).
+///
+/// It reaches callers as the [`CodecError`] of a [`SerializeError`] or [`DecodeError`]; use
+/// [`CodecError::downcast_ref`] to tell it apart from a format error.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationError {
+    reason: String,
+}
+impl ValidationError {
+    pub fn new(reason: impl Into<String>) -> Self {
+        Self {
+            reason: reason.into(),
+        }
+    }
+    pub fn reason(&self) -> &str {
+        &self.reason
+    }
+}
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid payload: {}", self.reason)
+    }
+}
+impl Error for ValidationError {}
+
+/// The problems found while validating a client config.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigValidationError {
+    problems: Vec<String>,
+}
+impl ConfigValidationError {
+    pub fn new(problems: Vec<String>) -> Self {
+        Self { problems }
+    }
+    pub fn problems(&self) -> &[String] {
+        &self.problems
+    }
+}
+impl fmt::Display for ConfigValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid config: {}", self.problems.join("; "))
+    }
+}
+impl Error for ConfigValidationError {}
+
+// Everything above should be usable with `?` in `anyhow::Result` and `Box<dyn Error + Send + Sync>`
+// returning code, which needs these bounds.
+const _: () = {
+    const fn assert_send_sync<T: Error + Send + Sync + 'static>() {}
+    assert_send_sync::<RdkafkaExtError>();
+    assert_send_sync::<SendError>();
+    assert_send_sync::<SerializeError>();
+    assert_send_sync::<DeliveryError>();
+    assert_send_sync::<DecodeError>();
+    assert_send_sync::<ConfigValidationError>();
+    assert_send_sync::<ValidationError>();
+    #[cfg(feature = "schema-registry")]
+    assert_send_sync::<SchemaRegistryError>();
+};
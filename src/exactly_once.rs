@@ -0,0 +1,176 @@
+//! Exactly-once consume-transform-produce: what a handler produces for a batch of messages and
+//! the offsets of those messages are committed in the same transaction, so a crash either
+//! loses both or keeps both.
+
+use std::{collections::HashMap, error::Error, time::Duration};
+
+use rdkafka::{
+    client::DefaultClientContext,
+    consumer::{Consumer, ConsumerContext, DefaultConsumerContext},
+    error::KafkaError,
+    types::RDKafkaErrorCode,
+    util::Timeout,
+    ClientConfig, ClientContext, Message, Offset, TopicPartitionList,
+};
+
+use crate::{
+    error::RdkafkaExtError,
+    transaction::{Transaction, TypedTransactionalProducer},
+    Topic, TypedConsumer, TypedMessage,
+};
+
+/// Consumes `T`, passing each message to a handler along with an open transaction to produce
+/// its output in. The consumed offsets are sent to the same transaction, so the output only
+/// becomes visible, and the input only counts as consumed, once it commits.
+///
+/// Downstream consumers need `isolation.level=read_committed`, the default, to only see
+/// committed output.
+///
+/// `C` is the consumer's context and `PC` the producer's.
+pub struct ExactlyOncePipeline<
+    T,
+    C: ConsumerContext + 'static = DefaultConsumerContext,
+    PC: ClientContext + 'static = DefaultClientContext,
+> {
+    consumer: TypedConsumer<T, C>,
+    producer: TypedTransactionalProducer<PC>,
+    max_messages: usize,
+    max_wait: Duration,
+}
+impl<T: Topic> ExactlyOncePipeline<T> {
+    /// Creates the consumer with `isolation.level=read_committed` and automatic commits off,
+    /// and a transactional producer, which needs a `transactional.id` in `producer_config`.
+    /// Transaction calls block for up to `timeout`.
+    pub fn new(
+        mut consumer_config: ClientConfig,
+        topic: T,
+        producer_config: ClientConfig,
+        timeout: impl Into<Timeout>,
+    ) -> Result<Self, RdkafkaExtError> {
+        consumer_config
+            .set("isolation.level", "read_committed")
+            .set("enable.auto.commit", "false");
+        let consumer = TypedConsumer::new(consumer_config, topic)?;
+        let producer = TypedTransactionalProducer::new(producer_config, timeout)?;
+
+        Ok(Self::from_parts(consumer, producer))
+    }
+}
+impl<T: Topic, C: ConsumerContext + 'static, PC: ClientContext + 'static>
+    ExactlyOncePipeline<T, C, PC>
+{
+    /// Ties together a consumer and producer made elsewhere. The consumer should have
+    /// `isolation.level=read_committed` and `enable.auto.commit` off.
+    pub fn from_parts(
+        consumer: TypedConsumer<T, C>,
+        producer: TypedTransactionalProducer<PC>,
+    ) -> Self {
+        Self {
+            consumer,
+            producer,
+            max_messages: 100,
+            max_wait: Duration::from_millis(100),
+        }
+    }
+    /// How many messages to handle per transaction, and how long to wait for them to arrive.
+    ///
+    /// Default: 100 messages, 100ms
+    pub fn with_batch(mut self, max_messages: usize, max_wait: Duration) -> Self {
+        self.max_messages = max_messages.max(1);
+        self.max_wait = max_wait;
+        self
+    }
+    pub fn consumer(&self) -> &TypedConsumer<T, C> {
+        &self.consumer
+    }
+    pub fn producer(&self) -> &TypedTransactionalProducer<PC> {
+        &self.producer
+    }
+    /// Receives a batch of messages and handles them in one transaction, returning how many
+    /// there were.
+    ///
+    /// If the handler or the transaction fails, the transaction is aborted and the batch's
+    /// partitions are rewound, so the same messages are received again next time.
+    pub async fn process_batch<F, E>(&self, handler: &mut F) -> Result<usize, RdkafkaExtError>
+    where
+        F: AsyncFnMut(&TypedMessage<'_, T>, &Transaction<PC>) -> Result<(), E>,
+        E: Into<Box<dyn Error + Send + Sync>>,
+    {
+        let batch = self
+            .consumer
+            .recv_batch(self.max_messages, self.max_wait)
+            .await?;
+        if batch.is_empty() {
+            return Ok(0);
+        }
+
+        // The first and next offsets of every partition in the batch.
+        let mut offsets: HashMap<(&str, i32), (i64, i64)> = HashMap::new();
+        for message in &batch {
+            let message = &message.message;
+            let offset = message.offset();
+            let range = offsets
+                .entry((message.topic(), message.partition()))
+                .or_insert((offset, offset + 1));
+            *range = (range.0.min(offset), range.1.max(offset + 1));
+        }
+
+        let transaction = self.producer.begin()?;
+        let result = self.transact(&batch, &offsets, &transaction, handler).await;
+        if let Err(err) = result {
+            let timeout = self.producer.timeout();
+            // The commit aborts the transaction itself if it fails; otherwise this does, and
+            // the original error is kept either way.
+            let _ = transaction.abort();
+            for (&(topic, partition), &(first, _)) in &offsets {
+                self.consumer
+                    .inner
+                    .seek(topic, partition, Offset::Offset(first), timeout)?;
+            }
+            return Err(err);
+        }
+        Ok(batch.len())
+    }
+    /// Handles batches until something fails.
+    pub async fn run<F, E>(&self, mut handler: F) -> Result<(), RdkafkaExtError>
+    where
+        F: AsyncFnMut(&TypedMessage<'_, T>, &Transaction<PC>) -> Result<(), E>,
+        E: Into<Box<dyn Error + Send + Sync>>,
+    {
+        loop {
+            self.process_batch(&mut handler).await?;
+        }
+    }
+
+    async fn transact<F, E>(
+        &self,
+        batch: &[TypedMessage<'_, T>],
+        offsets: &HashMap<(&str, i32), (i64, i64)>,
+        transaction: &Transaction<PC>,
+        handler: &mut F,
+    ) -> Result<(), RdkafkaExtError>
+    where
+        F: AsyncFnMut(&TypedMessage<'_, T>, &Transaction<PC>) -> Result<(), E>,
+        E: Into<Box<dyn Error + Send + Sync>>,
+    {
+        for message in batch {
+            handler(message, transaction)
+                .await
+                .map_err(|err| RdkafkaExtError::Handler(err.into()))?;
+        }
+
+        let mut list = TopicPartitionList::new();
+        for (&(topic, partition), &(_, next)) in offsets {
+            list.add_partition_offset(topic, partition, Offset::Offset(next))?;
+        }
+        // Only missing if the consumer isn't in a group, which transactions can't be used
+        // without.
+        let group = self
+            .consumer
+            .inner
+            .group_metadata()
+            .ok_or(KafkaError::ConsumerCommit(RDKafkaErrorCode::InvalidGroupId))?;
+        transaction.send_offsets(&list, &group)?;
+        transaction.commit()
+    }
+}
@@ -0,0 +1,75 @@
+use rdkafka::message::{Header, Headers, OwnedHeaders};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::codec::{self, CodecError};
+
+/// Record headers with typed values. Values are written the same way as message keys: strings
+/// as bare UTF-8 so other clients can read them, anything else as JSON.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HeaderMap {
+    entries: Vec<(String, Option<Vec<u8>>)>,
+}
+impl HeaderMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Adds a header. Kafka allows repeated header names, so this never replaces an existing
+    /// header.
+    pub fn insert<V: Serialize>(
+        &mut self,
+        name: impl Into<String>,
+        value: &V,
+    ) -> Result<&mut Self, CodecError> {
+        let value = codec::encode_key(value)?;
+        Ok(self.insert_raw(name, Some(value)))
+    }
+    /// Adds a header with an already encoded value, or none at all.
+    pub fn insert_raw(&mut self, name: impl Into<String>, value: Option<Vec<u8>>) -> &mut Self {
+        self.entries.push((name.into(), value));
+        self
+    }
+    /// Decodes the last header called `name`, or returns `None` if there isn't one or it has no
+    /// value.
+    pub fn get<V: DeserializeOwned>(&self, name: &str) -> Option<Result<V, CodecError>> {
+        self.get_raw(name).map(codec::decode_key)
+    }
+    /// The value of the last header called `name`.
+    pub fn get_raw(&self, name: &str) -> Option<&[u8]> {
+        self.entries
+            .iter()
+            .rev()
+            .find(|(entry, _)| entry == name)
+            .and_then(|(_, value)| value.as_deref())
+    }
+    pub fn iter(&self) -> impl Iterator<Item = (&str, Option<&[u8]>)> {
+        self.entries
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_deref()))
+    }
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub(crate) fn from_headers(headers: &impl Headers) -> Self {
+        Self {
+            entries: headers
+                .iter()
+                .map(|header| (header.key.to_string(), header.value.map(<[u8]>::to_vec)))
+                .collect(),
+        }
+    }
+    pub(crate) fn to_owned_headers(&self) -> OwnedHeaders {
+        self.entries.iter().fold(
+            OwnedHeaders::new_with_capacity(self.len()),
+            |headers, (name, value)| {
+                headers.insert(Header {
+                    key: name,
+                    value: value.as_deref(),
+                })
+            },
+        )
+    }
+}
@@ -0,0 +1,98 @@
+//! Tagging records with an `x-idempotency-key` header, so consumers can recognise a record that
+//! was sent twice, e.g. after a retry whose first attempt actually landed.
+
+use rdkafka::{types::RDKafkaErrorCode, ClientContext};
+
+use crate::{
+    interceptor::{OutgoingRecord, ProducerInterceptor},
+    TypedProducer,
+};
+
+/// The header holding a record's idempotency key.
+pub const IDEMPOTENCY_KEY: &str = "x-idempotency-key";
+
+const FNV_OFFSET_BASIS: u128 = 0x6c62272e07bb014262b821756295c58d;
+const FNV_PRIME: u128 = 0x0000000001000000000000000000013b;
+
+/// The key derived from a record's encoded key and payload: a 128-bit FNV-1a hash, in hex.
+///
+/// It only depends on the bytes, so it's the same across processes and releases, but two
+/// records with the same key and payload get the same key even if they were meant as separate
+/// events.
+pub fn idempotency_key(key: Option<&[u8]>, payload: Option<&[u8]>) -> String {
+    let mut hash = FNV_OFFSET_BASIS;
+    let mut write = |bytes: &[u8]| {
+        for byte in bytes {
+            hash ^= u128::from(*byte);
+            hash = hash.wrapping_mul(FNV_PRIME);
+        }
+    };
+    // Length-prefixed, so a key/payload split can't collide with a different split of the same
+    // bytes.
+    for part in [key, payload] {
+        match part {
+            Some(bytes) => {
+                write(&[1]);
+                write(&(bytes.len() as u64).to_be_bytes());
+                write(bytes);
+            }
+            None => write(&[0]),
+        }
+    }
+    format!("{hash:032x}")
+}
+
+/// Gives every record without an `x-idempotency-key` header one derived from its key and
+/// payload with [`idempotency_key`]. Records sent with one keep it.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct IdempotencyKeys;
+impl ProducerInterceptor for IdempotencyKeys {
+    fn on_send(&self, record: &mut OutgoingRecord<'_>) -> Result<(), RDKafkaErrorCode> {
+        if record.headers.get_raw(IDEMPOTENCY_KEY).is_none() {
+            let key = idempotency_key(record.key, record.payload);
+            record
+                .headers
+                .insert_raw(IDEMPOTENCY_KEY, Some(key.into_bytes()));
+        }
+        Ok(())
+    }
+}
+
+impl<C: ClientContext + 'static> TypedProducer<C> {
+    /// Attaches an `x-idempotency-key` header to every record that doesn't already have one.
+    /// Pass one through [`send_with_headers`](Self::send_with_headers) to use an ID of your own,
+    /// such as the ID of the request being handled.
+    pub fn with_idempotency_keys(self) -> Self {
+        self.with_interceptor(IdempotencyKeys)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn depends_only_on_the_bytes() {
+        let key = idempotency_key(Some(b"order-1"), Some(b"{}"));
+
+        assert_eq!(key.len(), 32);
+        assert_eq!(key, idempotency_key(Some(b"order-1"), Some(b"{}")));
+        assert_ne!(key, idempotency_key(Some(b"order-2"), Some(b"{}")));
+    }
+
+    #[test]
+    fn tells_apart_splits_of_the_same_bytes() {
+        assert_ne!(
+            idempotency_key(Some(b"ab"), Some(b"c")),
+            idempotency_key(Some(b"a"), Some(b"bc"))
+        );
+        assert_ne!(
+            idempotency_key(None, Some(b"")),
+            idempotency_key(Some(b""), None)
+        );
+        assert_ne!(
+            idempotency_key(None, None),
+            idempotency_key(Some(b""), None)
+        );
+    }
+}
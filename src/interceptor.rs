@@ -0,0 +1,80 @@
+//! Hooks into every record a [`TypedProducer`](crate::TypedProducer) sends or a
+//! [`TypedConsumer`](crate::TypedConsumer) receives, for tracing, auditing, metrics or policy
+//! checks that shouldn't be repeated at every call site.
+
+use rdkafka::{
+    error::KafkaError, message::BorrowedMessage, types::RDKafkaErrorCode, TopicPartitionList,
+};
+
+use crate::{
+    codec::CodecError,
+    error::{DecodeError, RdkafkaExtError},
+    headers::HeaderMap,
+    Delivery,
+};
+
+/// A record about to be enqueued, as seen by [`ProducerInterceptor::on_send`].
+pub struct OutgoingRecord<'a> {
+    pub topic: &'a str,
+    pub key: Option<&'a [u8]>,
+    pub payload: Option<&'a [u8]>,
+    /// The partition the record was explicitly sent to. Setting it overrides the partitioner.
+    pub partition: Option<i32>,
+    pub headers: &'a mut HeaderMap,
+}
+
+/// Observes, and optionally changes or stops, the records a producer sends. Interceptors run in
+/// the order they were added with
+/// [`TypedProducer::with_interceptor`](crate::TypedProducer::with_interceptor).
+///
+/// Hooks run inline on the sending task, so keep them quick.
+pub trait ProducerInterceptor: Send + Sync {
+    /// Called once a record is serialized, with its encoded payload, or with why its key or
+    /// payload couldn't be serialized. Not called for tombstones.
+    fn on_serialize(&self, topic: &str, result: Result<&[u8], &CodecError>) {
+        let _ = (topic, result);
+    }
+    /// Called just before a record is enqueued. Headers and the partition can be changed here.
+    ///
+    /// Returning an error code vetoes the send, which then fails with a
+    /// [`DeliveryError`](crate::error::DeliveryError) carrying that code; later interceptors
+    /// don't see the record.
+    fn on_send(&self, record: &mut OutgoingRecord<'_>) -> Result<(), RDKafkaErrorCode> {
+        let _ = record;
+        Ok(())
+    }
+    /// Called once for every record passed to [`on_send`](Self::on_send), when the broker
+    /// acknowledges it or it fails, including when it was vetoed or never enqueued.
+    fn on_delivery(&self, topic: &str, result: Result<&Delivery, &RdkafkaExtError>) {
+        let _ = (topic, result);
+    }
+}
+
+/// Observes, and optionally filters, the messages a consumer receives. Interceptors run in the
+/// order they were added with
+/// [`TypedConsumer::with_interceptor`](crate::TypedConsumer::with_interceptor).
+///
+/// Hooks run inline on the receiving task, or on the polling thread for commits, so keep them
+/// quick.
+pub trait ConsumerInterceptor: Send + Sync {
+    /// Called for every message received, before it's handed out.
+    ///
+    /// Returning `false` skips the message, which still counts as consumed; later interceptors
+    /// don't see it.
+    fn on_receive(&self, message: &BorrowedMessage<'_>) -> bool {
+        let _ = message;
+        true
+    }
+    /// Called when the consumer decodes a payload itself, in
+    /// [`handle`](crate::TypedConsumer::handle), [`run`](crate::TypedConsumer::run) and
+    /// [`stream_with_errors`](crate::TypedConsumer::stream_with_errors), with why it couldn't
+    /// be decoded if it couldn't. Not called for tombstones.
+    fn on_decode(&self, message: &BorrowedMessage<'_>, result: Result<(), &DecodeError>) {
+        let _ = (message, result);
+    }
+    /// Called once a commit completes, automatic or not, with the offsets committed or why
+    /// the commit failed.
+    fn on_commit(&self, result: Result<&TopicPartitionList, &KafkaError>) {
+        let _ = result;
+    }
+}
@@ -0,0 +1,243 @@
+//! Joining two co-partitioned topics by key: records from either side are paired with records
+//! from the other side that have the same key and were written within a time window of them.
+//!
+//! Both topics must have the same number of partitions and be keyed the same way, so records to
+//! be joined land in the same partition number. One consumer reads both, so the group always
+//! assigns it the same partitions of each. Unmatched records are buffered in memory until they
+//! fall out of the window, so a restart only joins records read after it.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use rdkafka::{
+    consumer::{Consumer, StreamConsumer},
+    message::BorrowedMessage,
+    ClientConfig, Message,
+};
+
+use crate::{error::RdkafkaExtError, Topic, TypedMessage};
+
+/// A record from each side of a [`Join`] with the same key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Joined<K, L, R> {
+    pub key: K,
+    pub partition: i32,
+    pub left: L,
+    pub right: R,
+}
+
+/// A record's partition and raw key.
+type RecordKey = (i32, Vec<u8>);
+type Decoded<T> = (<T as Topic>::Key, <T as Topic>::Payload);
+
+/// One side's records still within the window, by raw key.
+struct Side<P> {
+    records: HashMap<RecordKey, VecDeque<(i64, P)>>,
+    /// Every buffered record's timestamp and key, in the order they arrived.
+    arrivals: VecDeque<(i64, RecordKey)>,
+}
+impl<P> Default for Side<P> {
+    fn default() -> Self {
+        Self {
+            records: HashMap::new(),
+            arrivals: VecDeque::new(),
+        }
+    }
+}
+impl<P> Side<P> {
+    fn insert(&mut self, key: RecordKey, timestamp: i64, payload: P) {
+        self.arrivals.push_back((timestamp, key.clone()));
+        self.records
+            .entry(key)
+            .or_default()
+            .push_back((timestamp, payload));
+    }
+    fn matches<'a>(
+        &'a self,
+        key: &RecordKey,
+        timestamp: i64,
+        window: i64,
+    ) -> impl Iterator<Item = &'a P> {
+        self.records
+            .get(key)
+            .into_iter()
+            .flatten()
+            .filter(move |(at, _)| at.abs_diff(timestamp) <= window as u64)
+            .map(|(_, payload)| payload)
+    }
+    /// Drops records that arrived before any still in the window and are older than `cutoff`.
+    fn evict(&mut self, cutoff: i64) {
+        while let Some((timestamp, key)) = self.arrivals.front() {
+            if *timestamp >= cutoff {
+                break;
+            }
+            if let Some(records) = self.records.get_mut(key) {
+                records.retain(|(at, _)| *at >= cutoff);
+                if records.is_empty() {
+                    self.records.remove(key);
+                }
+            }
+            self.arrivals.pop_front();
+        }
+    }
+}
+
+/// Consumes two topics with the same key type and emits every pair of records with the same
+/// key whose timestamps are at most the window apart, like an inner windowed join in Kafka
+/// Streams.
+///
+/// Records without a key or payload are ignored, and records that can't be decoded are
+/// returned as errors.
+pub struct Join<L: Topic, R: Topic<Key = L::Key>> {
+    consumer: StreamConsumer,
+    left_topic: String,
+    left: L,
+    right: R,
+    window: Duration,
+    lefts: Side<L::Payload>,
+    rights: Side<R::Payload>,
+    latest: i64,
+    ready: VecDeque<Joined<L::Key, L::Payload, R::Payload>>,
+}
+impl<L, R> Join<L, R>
+where
+    L: Topic,
+    R: Topic<Key = L::Key>,
+    L::Key: Clone,
+    L::Payload: Clone,
+    R::Payload: Clone,
+{
+    /// Subscribes to both topics.
+    pub fn new(
+        client_config: ClientConfig,
+        left: L,
+        right: R,
+        window: Duration,
+    ) -> Result<Self, RdkafkaExtError> {
+        let consumer: StreamConsumer = client_config.create()?;
+        consumer.subscribe(&[&left.topic_string(), &right.topic_string()])?;
+
+        Ok(Self {
+            consumer,
+            left_topic: left.topic_string(),
+            left,
+            right,
+            window,
+            lefts: Side::default(),
+            rights: Side::default(),
+            latest: i64::MIN,
+            ready: VecDeque::new(),
+        })
+    }
+    /// How many records are buffered, waiting for a match or to fall out of the window.
+    pub fn buffered(&self) -> usize {
+        self.lefts.arrivals.len() + self.rights.arrivals.len()
+    }
+    /// Receives records until one matches, returning the next joined pair.
+    pub async fn recv(
+        &mut self,
+    ) -> Result<Joined<L::Key, L::Payload, R::Payload>, RdkafkaExtError> {
+        let window = self.window.as_millis().try_into().unwrap_or(i64::MAX);
+        loop {
+            if let Some(joined) = self.ready.pop_front() {
+                return Ok(joined);
+            }
+            let message = self.consumer.recv().await?;
+            let partition = message.partition();
+            let timestamp = timestamp_of(&message);
+            let raw_key = message.key().map(<[u8]>::to_vec).unwrap_or_default();
+            let key = (partition, raw_key);
+
+            if message.topic() == self.left_topic {
+                let Some((typed_key, payload)) = decode(&self.left, message)? else {
+                    continue;
+                };
+                for right in self.rights.matches(&key, timestamp, window) {
+                    self.ready.push_back(Joined {
+                        key: typed_key.clone(),
+                        partition,
+                        left: payload.clone(),
+                        right: right.clone(),
+                    });
+                }
+                self.lefts.insert(key, timestamp, payload);
+            } else {
+                let Some((typed_key, payload)) = decode(&self.right, message)? else {
+                    continue;
+                };
+                for left in self.lefts.matches(&key, timestamp, window) {
+                    self.ready.push_back(Joined {
+                        key: typed_key.clone(),
+                        partition,
+                        left: left.clone(),
+                        right: payload.clone(),
+                    });
+                }
+                self.rights.insert(key, timestamp, payload);
+            }
+
+            self.latest = self.latest.max(timestamp);
+            let cutoff = self.latest.saturating_sub(window);
+            self.lefts.evict(cutoff);
+            self.rights.evict(cutoff);
+        }
+    }
+}
+
+/// The key and payload of a record, or `None` if it's missing either.
+fn decode<T: Topic>(
+    topic: &T,
+    message: BorrowedMessage<'_>,
+) -> Result<Option<Decoded<T>>, RdkafkaExtError> {
+    let message = TypedMessage {
+        message,
+        topic: topic.clone(),
+    };
+    let key = message.typed_key()?;
+    let payload = message.payload()?;
+    Ok(key.zip(payload))
+}
+
+/// When a record was written, or now if it carries no timestamp.
+fn timestamp_of(message: &BorrowedMessage<'_>) -> i64 {
+    message.timestamp().to_millis().unwrap_or_else(|| {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_millis() as i64)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evicts_records_older_than_the_cutoff() {
+        let mut side = Side::default();
+        side.insert((0, b"a".to_vec()), 10, "a1");
+        side.insert((0, b"b".to_vec()), 20, "b1");
+        side.insert((0, b"a".to_vec()), 30, "a2");
+
+        side.evict(25);
+        assert!(!side.records.contains_key(&(0, b"b".to_vec())));
+        assert_eq!(
+            side.matches(&(0, b"a".to_vec()), 30, 100)
+                .collect::<Vec<_>>(),
+            [&"a2"]
+        );
+        assert_eq!(side.arrivals.len(), 1);
+    }
+
+    #[test]
+    fn stops_at_the_first_record_still_in_the_window() {
+        let mut side = Side::default();
+        side.insert((0, b"a".to_vec()), 30, "a1");
+        side.insert((0, b"b".to_vec()), 10, "b1");
+
+        side.evict(25);
+        assert_eq!(side.arrivals.len(), 2);
+        assert_eq!(side.matches(&(0, b"b".to_vec()), 10, 0).count(), 1);
+    }
+}
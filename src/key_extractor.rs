@@ -0,0 +1,84 @@
+//! Deriving record keys from payloads, so partition affinity is decided in one place instead of
+//! at every call site.
+
+use std::marker::PhantomData;
+
+use rdkafka::{client::DefaultClientContext, util::Timeout, ClientContext};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{error::SendError, Delivery, Topic, TypedProducer};
+
+/// Picks the key of a payload, e.g. `|order: &Order| order.customer_id.clone()`.
+pub trait KeyExtractor<P>: Send + Sync {
+    type Key: Serialize + DeserializeOwned;
+
+    fn key(&self, payload: &P) -> Self::Key;
+}
+impl<P, K, F> KeyExtractor<P> for F
+where
+    F: Fn(&P) -> K + Send + Sync,
+    K: Serialize + DeserializeOwned,
+{
+    type Key = K;
+
+    fn key(&self, payload: &P) -> K {
+        self(payload)
+    }
+}
+
+/// A producer that keys every record with a [`KeyExtractor`], from
+/// [`TypedProducer::keyed_by`].
+pub struct KeyedProducer<P, E, C: ClientContext + 'static = DefaultClientContext> {
+    producer: TypedProducer<C>,
+    extractor: E,
+    payload: PhantomData<fn(&P)>,
+}
+impl<P, E: Clone, C: ClientContext + 'static> Clone for KeyedProducer<P, E, C> {
+    fn clone(&self) -> Self {
+        Self {
+            producer: self.producer.clone(),
+            extractor: self.extractor.clone(),
+            payload: PhantomData,
+        }
+    }
+}
+impl<P, E: KeyExtractor<P>, C: ClientContext + 'static> KeyedProducer<P, E, C> {
+    /// The producer underneath, for sends that need something other than the extracted key.
+    pub fn producer(&self) -> &TypedProducer<C> {
+        &self.producer
+    }
+    /// Sends with the producer's [`default_timeout`](TypedProducer::default_timeout).
+    pub async fn send<T>(&self, topic: &T, payload: &P) -> Result<Delivery, SendError>
+    where
+        T: Topic<Payload = P, Key = E::Key>,
+    {
+        self.send_with_timeout(topic, payload, self.producer.default_timeout())
+            .await
+    }
+    /// Like [`send`](Self::send), but waits up to `timeout` instead of the default.
+    pub async fn send_with_timeout<T>(
+        &self,
+        topic: &T,
+        payload: &P,
+        timeout: impl Into<Timeout>,
+    ) -> Result<Delivery, SendError>
+    where
+        T: Topic<Payload = P, Key = E::Key>,
+    {
+        let key = self.extractor.key(payload);
+        self.producer
+            .send_with_timeout(topic, payload, Some(&key), timeout)
+            .await
+    }
+}
+
+impl<C: ClientContext + 'static> TypedProducer<C> {
+    /// Wraps the producer so every `P` it sends is keyed by `extractor`.
+    pub fn keyed_by<P, E: KeyExtractor<P>>(self, extractor: E) -> KeyedProducer<P, E, C> {
+        KeyedProducer {
+            producer: self,
+            extractor,
+            payload: PhantomData,
+        }
+    }
+}
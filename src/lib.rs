@@ -1,37 +1,349 @@
+pub mod backpressure;
+pub mod blocking;
 pub mod builders;
+pub mod catch_up;
+pub mod chunking;
+pub mod circuit_breaker;
+pub mod claim_check;
+pub mod codec;
+pub mod commit_manager;
+mod consumer_context;
+pub mod correlation;
+pub mod deduplication;
+pub mod delayed;
+pub mod delivery;
+pub mod error;
+pub mod exactly_once;
+pub mod headers;
+pub mod idempotency;
+pub mod interceptor;
+pub mod join;
+pub mod key_extractor;
+pub mod materialized_view;
+pub mod offset_store;
+pub mod offsets;
+pub mod outbox;
+pub mod parallel;
+pub mod partitioner;
+mod pool;
+pub mod producer_pool;
+pub mod rebalance;
+pub mod retry;
+pub mod retry_topics;
+pub mod router;
+#[cfg(feature = "schema-registry")]
+pub mod schema_registry;
+pub mod stats;
+pub mod tap;
+#[cfg(feature = "otel")]
+pub mod trace_context;
+pub mod transaction;
 
+use std::{
+    error::Error,
+    future::Future,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use backpressure::QueueLimits;
 use builders::{traits::KafkaConfigBuilder, ConsumerConfigBuilder, ProducerConfigBuilder};
-use futures::{Stream, StreamExt};
+use circuit_breaker::CircuitBreaker;
+use codec::{BorrowingCodec, CodecError, JsonCodec, PayloadCodec, RawCodec, TextCodec};
+use consumer_context::ConsumerExtContext;
+use delivery::PendingDelivery;
+use error::{
+    DecodeError, DeliveryError, ErrorClass, RdkafkaExtError, RecordField, SendError,
+    SerializeError, ValidationError,
+};
+use futures::{
+    channel::{
+        mpsc::{self, UnboundedReceiver},
+        oneshot,
+    },
+    future, stream, Stream, StreamExt,
+};
+use headers::HeaderMap;
+use interceptor::{ConsumerInterceptor, OutgoingRecord, ProducerInterceptor};
+use partitioner::{KeyPartitioner, PartitionerState};
+use pool::BufferPool;
 use rdkafka::{
     admin::{AdminClient, AdminOptions, NewTopic, TopicReplication},
     client::DefaultClientContext,
-    consumer::{Consumer, StreamConsumer},
-    error::KafkaError,
-    message::{BorrowedHeaders, BorrowedMessage},
-    producer::{FutureProducer, FutureRecord},
+    consumer::{CommitMode, Consumer, ConsumerContext, DefaultConsumerContext, StreamConsumer},
+    error::{KafkaError, KafkaResult},
+    message::{BorrowedMessage, Header, Headers, OwnedHeaders, OwnedMessage},
+    producer::{FutureProducer, FutureRecord, Producer},
+    types::RDKafkaErrorCode,
     util::Timeout,
-    ClientConfig, Message, Timestamp,
+    ClientConfig, ClientContext, Message, Offset, Timestamp, TopicPartitionList,
 };
-use serde::{de::DeserializeOwned, Serialize};
+use retry::RetryPolicy;
+use retry_topics::{RetrySchedule, RetryTiers};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use stats::{ConsumerStats, ProducerStats, StatsContext, StatsReports};
+use tap::Taps;
+use tokio::sync::Notify;
 
 pub trait Topic: Clone {
-    type Payload: Serialize + DeserializeOwned;
+    type Payload;
+    /// The type of message keys, usually `String`. See [`codec::encode_key`] for how keys are
+    /// written.
+    type Key: Serialize + DeserializeOwned;
+    /// The wire format of the payload, usually [`JsonCodec`].
+    type Codec: PayloadCodec<Self::Payload> + Default + Send + Sync + 'static;
 
     fn topic_string(&self) -> String;
+    /// Rebuilds the topic from its name, for consumers subscribed with
+    /// [`TypedConsumer::new_pattern`]. Returns `None` for names that aren't this kind of topic.
+    fn from_topic_string(_topic: &str) -> Option<Self> {
+        None
+    }
+    /// The codec used for this topic's payloads. By default every topic with the same codec
+    /// type shares one `Default` instance, built the first time it's needed. Override this to
+    /// return a codec kept in the topic for codecs that need state beyond their `Default`.
+    fn codec(&self) -> &Self::Codec {
+        codec::shared_default()
+    }
+    /// Checks a payload before it's serialized and sent. Payloads that fail aren't sent.
+    fn validate_outgoing(&self, _payload: &Self::Payload) -> Result<(), ValidationError> {
+        Ok(())
+    }
+    /// Checks a payload after it's received and deserialized. Payloads that fail are treated
+    /// like ones that couldn't be deserialized.
+    fn validate_incoming(&self, _payload: &Self::Payload) -> Result<(), ValidationError> {
+        Ok(())
+    }
+    /// The key to partition a payload by when it's sent without an explicit key, so records
+    /// about the same entity always land on the same partition.
+    fn partition_key(&self, _payload: &Self::Payload) -> Option<Vec<u8>> {
+        None
+    }
+    /// How schema registry subjects are named for this topic's schemas.
+    #[cfg(feature = "schema-registry")]
+    fn subject_name_strategy(&self) -> schema_registry::SubjectNameStrategy {
+        schema_registry::SubjectNameStrategy::TopicName
+    }
+}
+
+/// A topic whose payloads are plain bytes, read and written as is.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RawTopic(pub String);
+impl RawTopic {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self(name.into())
+    }
+}
+impl Topic for RawTopic {
+    type Payload = Vec<u8>;
+    type Key = String;
+    type Codec = RawCodec;
+
+    fn topic_string(&self) -> String {
+        self.0.clone()
+    }
+}
+
+/// A topic whose payloads are UTF-8 text, such as log or CSV lines.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct StringTopic(pub String);
+impl StringTopic {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self(name.into())
+    }
+}
+impl Topic for StringTopic {
+    type Payload = String;
+    type Key = String;
+    type Codec = TextCodec;
+
+    fn topic_string(&self) -> String {
+        self.0.clone()
+    }
 }
 
-pub struct TypedMessage<'a, T> {
-    message: BorrowedMessage<'a>,
+/// The result of decoding a message payload, keeping empty records apart from bad ones.
+#[derive(Debug)]
+pub enum PayloadOutcome<P> {
+    /// The record has no payload, e.g. a tombstone on a compacted topic.
+    Missing,
+    Decoded(P),
+    Invalid(DecodeError),
+}
+
+/// A received message of topic `T`, decoded on demand. `M` is the underlying message: borrowed
+/// from the consumer in a [`TypedMessage`], or owned in an [`OwnedTypedMessage`].
+#[derive(Clone)]
+pub struct GenericTypedMessage<T, M> {
+    message: M,
     topic: T,
 }
-impl<'a, T: Topic> TypedMessage<'a, T> {
+pub type TypedMessage<'a, T> = GenericTypedMessage<T, BorrowedMessage<'a>>;
+/// A message that no longer borrows from its consumer, so it can be moved into another task or
+/// queued for later. Made with [`TypedMessage::into_owned`].
+pub type OwnedTypedMessage<T> = GenericTypedMessage<T, OwnedMessage>;
+impl<T> TypedMessage<'_, T> {
+    /// Copies the message out of the consumer's buffer.
+    pub fn into_owned(self) -> OwnedTypedMessage<T> {
+        GenericTypedMessage {
+            message: self.message.detach(),
+            topic: self.topic,
+        }
+    }
+}
+impl<T: Topic, M: Message> GenericTypedMessage<T, M> {
     pub fn key(&self) -> Option<&[u8]> {
         self.message.key()
     }
-    pub fn payload(&self) -> Option<T::Payload> {
+    pub fn payload(&self) -> Result<Option<T::Payload>, DecodeError> {
+        self.message
+            .payload()
+            .map(|bytes| self.decode_payload(bytes))
+            .transpose()
+    }
+    /// Deserializes the payload as a type borrowing from the message buffer, avoiding the
+    /// allocations [`payload`](Self::payload) makes for strings and bytes.
+    pub fn payload_borrowed<'b, P: Deserialize<'b>>(&'b self) -> Result<Option<P>, DecodeError>
+    where
+        T::Codec: BorrowingCodec,
+    {
+        self.message
+            .payload()
+            .map(|bytes| self.topic.codec().decode_borrowed(bytes))
+            .transpose()
+            .map_err(|source| self.decode_error(RecordField::Payload, source))
+    }
+    pub fn typed_key(&self) -> Result<Option<T::Key>, DecodeError> {
+        self.message
+            .key()
+            .map(codec::decode_key)
+            .transpose()
+            .map_err(|source| self.decode_error(RecordField::Key, source))
+    }
+    /// Decodes the last header called `name`, as written by
+    /// [`TypedProducer::send_with_headers`].
+    pub fn header<V: DeserializeOwned>(&self, name: &str) -> Result<Option<V>, DecodeError> {
+        self.typed_headers()
+            .get(name)
+            .transpose()
+            .map_err(|source| self.decode_error(RecordField::Header, source))
+    }
+    /// Copies out every header on the record.
+    pub fn typed_headers(&self) -> HeaderMap {
+        self.message
+            .headers()
+            .map(HeaderMap::from_headers)
+            .unwrap_or_default()
+    }
+    /// The record's [`correlation-id`](correlation::CORRELATION_ID) header, if it has one and
+    /// it's UTF-8.
+    pub fn correlation_id(&self) -> Option<&str> {
+        let header = self
+            .message
+            .headers()?
+            .iter()
+            .filter(|header| header.key == correlation::CORRELATION_ID)
+            .last()?;
+        header
+            .value
+            .and_then(|value| std::str::from_utf8(value).ok())
+    }
+    /// How many times the record has been republished for retry, from its
+    /// [`x-retry-count`](retry_topics::RETRY_COUNT) header. Zero on its first delivery.
+    pub fn retry_count(&self) -> u32 {
+        self.message
+            .headers()
+            .and_then(|headers| {
+                headers
+                    .iter()
+                    .filter(|header| header.key == retry_topics::RETRY_COUNT)
+                    .last()
+            })
+            .and_then(|header| header.value)
+            .and_then(|value| std::str::from_utf8(value).ok())
+            .and_then(|count| count.parse().ok())
+            .unwrap_or(0)
+    }
+    /// The span context written on the record by the producer, for use as the parent of the
+    /// span handling it.
+    #[cfg(feature = "otel")]
+    pub fn trace_context(&self) -> Option<opentelemetry::Context> {
+        let headers = self.message.headers()?;
+        let header = |name: &str| {
+            headers
+                .iter()
+                .filter(|header| header.key == name)
+                .last()
+                .and_then(|header| header.value)
+                .and_then(|value| std::str::from_utf8(value).ok())
+        };
+        trace_context::extract(
+            header(trace_context::TRACEPARENT)?,
+            header(trace_context::TRACESTATE),
+        )
+    }
+    /// Whether this record is a tombstone: a key with no payload, marking the key as deleted on
+    /// a compacted topic.
+    pub fn is_tombstone(&self) -> bool {
+        self.message.key().is_some() && self.message.payload().is_none()
+    }
+    pub fn payload_outcome(&self) -> PayloadOutcome<T::Payload> {
+        match self.payload() {
+            Ok(Some(payload)) => PayloadOutcome::Decoded(payload),
+            Ok(None) => PayloadOutcome::Missing,
+            Err(err) => PayloadOutcome::Invalid(err),
+        }
+    }
+    /// Deserializes the payload's [`Envelope`](codec::envelope::Envelope), for reading its
+    /// metadata along with the payload.
+    #[cfg(feature = "envelope")]
+    pub fn envelope<C>(&self) -> Result<Option<codec::envelope::Envelope<T::Payload>>, DecodeError>
+    where
+        T: Topic<Codec = codec::envelope::EnvelopeCodec<C>>,
+        C: PayloadCodec<codec::envelope::Envelope<T::Payload>>,
+    {
         self.message
             .payload()
-            .map(|val| serde_json::from_slice(val).unwrap())
+            .map(|bytes| self.topic.codec().decode_envelope(bytes))
+            .transpose()
+            .map_err(|source| self.decode_error(RecordField::Payload, source))
+    }
+    /// Decodes and validates payload bytes read from this message, or reassembled from it.
+    fn decode_payload(&self, bytes: &[u8]) -> Result<T::Payload, DecodeError> {
+        let header = |name: &str| {
+            self.message
+                .headers()?
+                .iter()
+                .filter(|header| header.key == name)
+                .last()?
+                .value
+        };
+        let decoded = self
+            .topic
+            .codec()
+            .decode_with_headers(bytes, &header)
+            .and_then(|payload| {
+                self.topic
+                    .validate_incoming(&payload)
+                    .map_err(CodecError::new)?;
+                Ok(payload)
+            });
+        decoded.map_err(|source| self.decode_error(RecordField::Payload, source))
+    }
+    fn decode_error(&self, field: RecordField, source: CodecError) -> DecodeError {
+        DecodeError {
+            field,
+            topic: self.message.topic().to_string(),
+            partition: self.message.partition(),
+            offset: self.message.offset(),
+            key: self.message.key().map(<[u8]>::to_vec),
+            payload_len: self.message.payload().map_or(0, <[u8]>::len),
+            source,
+        }
     }
     pub fn topic(&self) -> &T {
         &self.topic
@@ -45,103 +357,1405 @@ impl<'a, T: Topic> TypedMessage<'a, T> {
     pub fn timestamp(&self) -> Timestamp {
         self.message.timestamp()
     }
-    pub fn headers(&self) -> Option<&BorrowedHeaders> {
+    pub fn headers(&self) -> Option<&M::Headers> {
         self.message.headers()
     }
 }
 
-#[derive(Clone)]
-pub struct TypedProducer {
-    inner: FutureProducer,
+/// Validates and encodes a payload about to be sent to `topic`.
+pub(crate) fn encode_payload<T: Topic>(
+    topic: &T,
+    payload: &T::Payload,
+) -> Result<Vec<u8>, CodecError> {
+    topic.validate_outgoing(payload).map_err(CodecError::new)?;
+    topic.codec().encode(payload)
 }
-impl TypedProducer {
-    pub fn new(config: ClientConfig) -> Self {
+
+/// Encodes the key to send a payload with, falling back to the topic's
+/// [`partition_key`](Topic::partition_key).
+pub(crate) fn encode_record_key<T: Topic>(
+    topic: &T,
+    payload: &T::Payload,
+    key: Option<&T::Key>,
+) -> Result<Option<Vec<u8>>, CodecError> {
+    match key {
+        Some(key) => codec::encode_key(key).map(Some),
+        None => Ok(topic.partition_key(payload)),
+    }
+}
+
+/// A record's key and payload, serialized for its topic, plus any headers the codec sends
+/// alongside the payload.
+pub(crate) struct EncodedRecord {
+    pub(crate) topic: String,
+    pub(crate) key: Option<Vec<u8>>,
+    pub(crate) payload: Vec<u8>,
+    pub(crate) payload_headers: Vec<(&'static str, Vec<u8>)>,
+}
+impl EncodedRecord {
+    pub(crate) fn new<T: Topic>(
+        topic: &T,
+        payload: &T::Payload,
+        key: Option<&T::Key>,
+    ) -> Result<Self, SerializeError> {
+        let topic_string = topic.topic_string();
+        let serialize_error = |source| SerializeError {
+            topic: topic_string.clone(),
+            source,
+        };
+        let key = encode_record_key(topic, payload, key).map_err(serialize_error)?;
+        let payload = encode_payload(topic, payload).map_err(serialize_error)?;
+
+        Ok(Self {
+            topic: topic_string,
+            key,
+            payload,
+            payload_headers: topic.codec().payload_headers(),
+        })
+    }
+    pub(crate) fn record(&self) -> RawRecord<'_> {
+        RawRecord::new(&self.topic, self.key.as_deref(), Some(&self.payload))
+            .payload_headers(&self.payload_headers)
+    }
+}
+
+/// A serialized record on its way to the broker.
+pub(crate) struct RawRecord<'a> {
+    topic: &'a str,
+    key: Option<&'a [u8]>,
+    payload: Option<&'a [u8]>,
+    headers: Option<OwnedHeaders>,
+    payload_headers: &'a [(&'static str, Vec<u8>)],
+    partition: Option<i32>,
+    timestamp: Option<i64>,
+}
+impl<'a> RawRecord<'a> {
+    pub(crate) fn new(topic: &'a str, key: Option<&'a [u8]>, payload: Option<&'a [u8]>) -> Self {
         Self {
-            inner: config.create().unwrap(),
+            topic,
+            key,
+            payload,
+            headers: None,
+            payload_headers: &[],
+            partition: None,
+            timestamp: None,
         }
     }
+    pub(crate) fn headers(mut self, headers: OwnedHeaders) -> Self {
+        self.headers = Some(headers);
+        self
+    }
+    /// Headers from the payload's codec, added after [`headers`](Self::headers).
+    pub(crate) fn payload_headers(mut self, headers: &'a [(&'static str, Vec<u8>)]) -> Self {
+        self.payload_headers = headers;
+        self
+    }
+    pub(crate) fn partition(mut self, partition: i32) -> Self {
+        self.partition = Some(partition);
+        self
+    }
+    pub(crate) fn timestamp(mut self, timestamp: i64) -> Self {
+        self.timestamp = Some(timestamp);
+        self
+    }
+}
+
+/// Which records [`TypedProducer::purge`] drops. Purges nothing until at least one of
+/// [`queue`](Self::queue) or [`in_flight`](Self::in_flight) is set.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PurgeConfig {
+    flags: i32,
+}
+impl PurgeConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Drops records still waiting in the producer queue.
+    pub fn queue(mut self) -> Self {
+        self.flags |= rdkafka_sys::bindings::RD_KAFKA_PURGE_F_QUEUE;
+        self
+    }
+    /// Drops records sent to a broker and awaiting a response.
+    pub fn in_flight(mut self) -> Self {
+        self.flags |= rdkafka_sys::bindings::RD_KAFKA_PURGE_F_INFLIGHT;
+        self
+    }
+    /// Returns straight away instead of waiting for in-flight requests to be cleaned up.
+    pub fn non_blocking(mut self) -> Self {
+        self.flags |= rdkafka_sys::bindings::RD_KAFKA_PURGE_F_NON_BLOCKING;
+        self
+    }
+}
+
+/// Where a record landed once it was acknowledged by the broker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Delivery {
+    pub partition: i32,
+    pub offset: i64,
+    pub timestamp: Timestamp,
+}
+
+/// Sends typed records. The context `C` receives librdkafka's logs, errors and statistics, and
+/// generates OAuth tokens; delivery outcomes are reported through
+/// [`ProducerInterceptor::on_delivery`] instead.
+pub struct TypedProducer<C: ClientContext + 'static = DefaultClientContext> {
+    inner: FutureProducer<StatsContext<C>>,
+    context: Arc<C>,
+    stats: StatsReports,
+    buffers: Arc<BufferPool>,
+    closed: Arc<AtomicBool>,
+    breaker: Option<Arc<CircuitBreaker>>,
+    partitioner: Option<Arc<PartitionerState>>,
+    default_timeout: Timeout,
+    interceptors: Vec<Arc<dyn ProducerInterceptor>>,
+    queue_limits: QueueLimits,
+    /// Notified whenever a send completes or a statistics report arrives, for
+    /// [`await_capacity`](Self::await_capacity).
+    drained: Arc<Notify>,
+    /// Held for the length of each [`send_all_transactional`](Self::send_all_transactional);
+    /// whether transactions have been initialized.
+    transactions: Arc<futures::lock::Mutex<bool>>,
+}
+impl<C: ClientContext + 'static> Clone for TypedProducer<C> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            context: self.context.clone(),
+            stats: self.stats.clone(),
+            buffers: self.buffers.clone(),
+            closed: self.closed.clone(),
+            breaker: self.breaker.clone(),
+            partitioner: self.partitioner.clone(),
+            default_timeout: self.default_timeout,
+            interceptors: self.interceptors.clone(),
+            queue_limits: self.queue_limits,
+            drained: self.drained.clone(),
+            transactions: self.transactions.clone(),
+        }
+    }
+}
+impl TypedProducer {
+    pub fn new(config: ClientConfig) -> Result<Self, RdkafkaExtError> {
+        Self::new_with_context(config, DefaultClientContext)
+    }
+}
+impl<C: ClientContext + 'static> TypedProducer<C> {
+    /// Like [`new`](TypedProducer::new), but with a client context of your own, e.g. to forward
+    /// librdkafka's logs or refresh OAuth tokens.
+    pub fn new_with_context(config: ClientConfig, context: C) -> Result<Self, RdkafkaExtError> {
+        let context = Arc::new(context);
+        let stats = StatsReports::default();
+        let drained = Arc::new(Notify::new());
+        let stats_context = StatsContext::new(stats.clone(), context.clone(), drained.clone());
+        Ok(Self {
+            inner: config.create_with_context(stats_context)?,
+            context,
+            stats,
+            buffers: Arc::default(),
+            closed: Arc::default(),
+            breaker: None,
+            partitioner: None,
+            default_timeout: Timeout::Never,
+            interceptors: vec![
+                #[cfg(feature = "otel")]
+                Arc::new(trace_context::TraceContextInterceptor),
+            ],
+            queue_limits: QueueLimits::new(&config),
+            drained,
+            transactions: Arc::default(),
+        })
+    }
+    /// Runs `interceptor` on every record sent through this producer, after any interceptors
+    /// added before it.
+    pub fn with_interceptor(mut self, interceptor: impl ProducerInterceptor + 'static) -> Self {
+        self.interceptors.push(Arc::new(interceptor));
+        self
+    }
+    /// How long [`send`](Self::send) waits for room in the producer queue. Defaults to waiting
+    /// forever.
+    pub fn with_default_timeout(mut self, timeout: impl Into<Timeout>) -> Self {
+        self.default_timeout = timeout.into();
+        self
+    }
+    pub fn default_timeout(&self) -> Timeout {
+        self.default_timeout
+    }
+    /// Places records sent without an explicit partition with `partitioner` instead of
+    /// librdkafka's `partitioner` setting. Records fall back to librdkafka's choice while their
+    /// topic's partition count is being looked up, or can't be.
+    pub fn with_partitioner(mut self, partitioner: impl KeyPartitioner + 'static) -> Self {
+        self.partitioner = Some(Arc::new(PartitionerState::new(partitioner)));
+        self
+    }
+    /// Guards every send on this producer and its clones with `breaker`.
+    pub fn with_circuit_breaker(mut self, breaker: CircuitBreaker) -> Self {
+        self.breaker = Some(Arc::new(breaker));
+        self
+    }
+    pub fn circuit_breaker(&self) -> Option<&CircuitBreaker> {
+        self.breaker.as_deref()
+    }
+    /// Blocks until every enqueued record has been delivered or failed, or `timeout` runs out.
+    pub fn flush(&self, timeout: impl Into<Timeout>) -> Result<(), RdkafkaExtError> {
+        Ok(self.inner.flush(timeout)?)
+    }
+    pub fn context(&self) -> &C {
+        &self.context
+    }
+    /// The latest statistics report, if there has been one. Reports are only made when
+    /// `statistics.interval.ms` is set.
+    pub fn stats(&self) -> Option<ProducerStats> {
+        self.stats.latest()
+    }
+    /// Receives every statistics report from now on.
+    pub fn stats_stream(&self) -> UnboundedReceiver<ProducerStats> {
+        self.stats.subscribe()
+    }
+    /// Drops records without delivering them, failing their sends with
+    /// [`PurgeQueue`](RDKafkaErrorCode::PurgeQueue) or
+    /// [`PurgeInflight`](RDKafkaErrorCode::PurgeInflight). Meant for emergency shutdowns and
+    /// decommissioned topics, where waiting for delivery isn't an option.
+    ///
+    /// Records already written to a broker may still be committed there even if purged, and
+    /// purged sends count as failures towards the circuit breaker.
+    pub fn purge(&self, config: PurgeConfig) -> Result<(), RdkafkaExtError> {
+        // SAFETY: the pointer is valid for as long as `self.inner` is, and rd_kafka_purge is
+        // safe to call from any thread.
+        let code = unsafe {
+            rdkafka_sys::bindings::rd_kafka_purge(self.inner.client().native_ptr(), config.flags)
+        };
+        match RDKafkaErrorCode::from(code) {
+            RDKafkaErrorCode::NoError => Ok(()),
+            code => Err(KafkaError::Global(code).into()),
+        }
+    }
+    /// How many records have been enqueued but not yet delivered or failed.
+    pub fn in_flight_count(&self) -> usize {
+        self.inner.in_flight_count().max(0) as usize
+    }
+    /// Stops accepting sends on this producer and all its clones, then waits up to `timeout` for
+    /// the records already enqueued to be delivered. Returns how many were still outstanding
+    /// when it gave up, which will be lost if the producer is dropped.
+    ///
+    /// Sends after shutdown fail with a [`DeliveryError`] holding the unsent record.
+    pub async fn shutdown(&self, timeout: Duration) -> usize {
+        self.closed.store(true, Ordering::Release);
+
+        // Flushing blocks, so it runs on its own thread rather than the caller's task.
+        let producer = self.inner.clone();
+        let (sender, receiver) = oneshot::channel();
+        thread::spawn(move || {
+            let _ = producer.flush(timeout);
+            let _ = sender.send(());
+        });
+        let _ = receiver.await;
+        self.in_flight_count()
+    }
+    /// Sends with the producer's [`default_timeout`](Self::default_timeout).
     pub async fn send<T: Topic>(
         &self,
         topic: &T,
         payload: &T::Payload,
-        key: Option<String>,
+        key: Option<&T::Key>,
+    ) -> Result<Delivery, SendError> {
+        self.send_with_timeout(topic, payload, key, self.default_timeout)
+            .await
+    }
+    /// Like [`send`](Self::send), but waits up to `timeout` instead of the default.
+    pub async fn send_with_timeout<T: Topic>(
+        &self,
+        topic: &T,
+        payload: &T::Payload,
+        key: Option<&T::Key>,
         timeout: impl Into<Timeout>,
-    ) {
-        let bytes = serde_json::to_vec(payload).unwrap();
+    ) -> Result<Delivery, SendError> {
+        let encoded = self.encode(topic, payload, key)?;
+
+        Ok(self.send_raw(encoded.record(), timeout).await?)
+    }
+    /// Like [`send`](Self::send), but also writes `headers` on the record.
+    pub async fn send_with_headers<T: Topic>(
+        &self,
+        topic: &T,
+        payload: &T::Payload,
+        key: Option<&T::Key>,
+        headers: &HeaderMap,
+        timeout: impl Into<Timeout>,
+    ) -> Result<Delivery, SendError> {
+        let encoded = self.encode(topic, payload, key)?;
+        let record = encoded.record().headers(headers.to_owned_headers());
+
+        Ok(self.send_raw(record, timeout).await?)
+    }
+    /// Like [`send`](Self::send), but writes to `partition` instead of the one the partitioner
+    /// picks for the key.
+    pub async fn send_to_partition<T: Topic>(
+        &self,
+        topic: &T,
+        partition: i32,
+        payload: &T::Payload,
+        key: Option<&T::Key>,
+        timeout: impl Into<Timeout>,
+    ) -> Result<Delivery, SendError> {
+        let encoded = self.encode(topic, payload, key)?;
+        let record = encoded.record().partition(partition);
+
+        Ok(self.send_raw(record, timeout).await?)
+    }
+    /// Like [`send`](Self::send), but stamps the record with `timestamp`, in milliseconds since
+    /// the Unix epoch, instead of the time it was sent. Use this to write event time.
+    pub async fn send_with_timestamp<T: Topic>(
+        &self,
+        topic: &T,
+        payload: &T::Payload,
+        key: Option<&T::Key>,
+        timestamp: i64,
+        timeout: impl Into<Timeout>,
+    ) -> Result<Delivery, SendError> {
+        let encoded = self.encode(topic, payload, key)?;
+        let record = encoded.record().timestamp(timestamp);
+
+        Ok(self.send_raw(record, timeout).await?)
+    }
+    /// Like [`send`](Self::send), but retries deliveries that fail with a
+    /// [`Retryable`](ErrorClass::Retryable) error, such as a full queue or a broker failover,
+    /// waiting between attempts as `policy` says. Other errors are returned straight away.
+    pub async fn send_with_retry<T: Topic>(
+        &self,
+        policy: &RetryPolicy,
+        topic: &T,
+        payload: &T::Payload,
+        key: Option<&T::Key>,
+        timeout: impl Into<Timeout>,
+    ) -> Result<Delivery, SendError> {
+        let encoded = self.encode(topic, payload, key)?;
+        let timeout = timeout.into();
+
+        let mut attempt = 0;
+        loop {
+            match self.send_raw(encoded.record(), timeout).await {
+                Ok(delivery) => return Ok(delivery),
+                Err(err)
+                    if err.error.class() == ErrorClass::Retryable
+                        && policy.should_retry(attempt) =>
+                {
+                    tokio::time::sleep(policy.backoff_after(attempt)).await;
+                    attempt += 1;
+                }
+                Err(err) => return Err(err.into()),
+            }
+        }
+    }
+    /// Serializes and enqueues a record, returning as soon as it's queued rather than once it's
+    /// delivered. Await the returned [`PendingDelivery`] to find out how delivery went.
+    ///
+    /// Unlike [`send`](Self::send), this doesn't wait for room when the queue is full, and
+    /// fails instead.
+    pub fn enqueue<T: Topic>(
+        &self,
+        topic: &T,
+        payload: &T::Payload,
+        key: Option<&T::Key>,
+    ) -> Result<PendingDelivery, SendError> {
+        let encoded = self.encode(topic, payload, key)?;
+
+        Ok(self.enqueue_raw(encoded.record())?)
+    }
+    /// Serializes and enqueues every record, then waits for all of their deliveries at once.
+    /// Returns one result per record, in order.
+    pub async fn send_batch<T: Topic>(
+        &self,
+        topic: &T,
+        records: impl IntoIterator<Item = (Option<T::Key>, T::Payload)>,
+        timeout: impl Into<Timeout>,
+    ) -> Vec<Result<Delivery, SendError>> {
+        let timeout = timeout.into();
+        let encoded: Vec<_> = records
+            .into_iter()
+            .map(|(key, payload)| self.encode(topic, &payload, key.as_ref()))
+            .collect();
+
+        future::join_all(encoded.into_iter().map(|encoded| async move {
+            let encoded = encoded?;
+            Ok(self.send_raw(encoded.record(), timeout).await?)
+        }))
+        .await
+    }
+    /// Like [`send`](Self::send), but serializes into a buffer reused across sends instead of
+    /// allocating a new one for every record.
+    pub async fn send_pooled<T: Topic>(
+        &self,
+        topic: &T,
+        payload: &T::Payload,
+        key: Option<&T::Key>,
+        timeout: impl Into<Timeout>,
+    ) -> Result<Delivery, SendError> {
         let topic_string = topic.topic_string();
+        let serialize_error = |source| SerializeError {
+            topic: topic_string.clone(),
+            source,
+        };
+        let mut buffer = self.buffers.take();
+        let key = encode_record_key(topic, payload, key).and_then(|key| {
+            topic.validate_outgoing(payload).map_err(CodecError::new)?;
+            topic.codec().encode_into(payload, &mut buffer)?;
+            Ok(key)
+        });
+        self.serialized(&topic_string, key.as_ref().map(|_| buffer.as_slice()));
+        let key = match key {
+            Ok(key) => key,
+            Err(err) => {
+                self.buffers.give(buffer);
+                return Err(serialize_error(err).into());
+            }
+        };
 
-        let record = FutureRecord::to(&topic_string).payload(&bytes);
-        let record = if let Some(ref key) = key {
-            record.key(key)
-        } else {
-            record
+        let payload_headers = topic.codec().payload_headers();
+        let record = RawRecord::new(&topic_string, key.as_deref(), Some(&buffer))
+            .payload_headers(&payload_headers);
+        let result = self.send_raw(record, timeout).await;
+        self.buffers.give(buffer);
+
+        Ok(result?)
+    }
+    /// Produces a record with no payload, which deletes `key` from a compacted topic.
+    pub async fn send_tombstone<T: Topic>(
+        &self,
+        topic: &T,
+        key: &T::Key,
+        timeout: impl Into<Timeout>,
+    ) -> Result<Delivery, SendError> {
+        let topic_string = topic.topic_string();
+        let key = codec::encode_key(key).map_err(|source| SerializeError {
+            topic: topic_string.clone(),
+            source,
+        })?;
+
+        let record = RawRecord::new(&topic_string, Some(&key), None);
+        Ok(self.send_raw(record, timeout).await?)
+    }
+    /// Serializes a record for `topic`, letting the interceptors know how it went.
+    pub(crate) fn encode<T: Topic>(
+        &self,
+        topic: &T,
+        payload: &T::Payload,
+        key: Option<&T::Key>,
+    ) -> Result<EncodedRecord, SerializeError> {
+        let encoded = EncodedRecord::new(topic, payload, key);
+        match &encoded {
+            Ok(encoded) => self.serialized(&encoded.topic, Ok(&encoded.payload)),
+            Err(err) => self.serialized(&err.topic, Err(&err.source)),
+        }
+        encoded
+    }
+    fn serialized(&self, topic: &str, result: Result<&[u8], &CodecError>) {
+        for interceptor in &self.interceptors {
+            interceptor.on_serialize(topic, result);
+        }
+    }
+    pub(crate) async fn send_raw(
+        &self,
+        raw: RawRecord<'_>,
+        timeout: impl Into<Timeout>,
+    ) -> Result<Delivery, DeliveryError> {
+        let topic = raw.topic;
+        let result = async {
+            let (record, timestamp) = self.prepare(raw)?;
+
+            let result = self.inner.send(record, timeout).await;
+            record_outcome(self.breaker.as_deref(), result.is_ok());
+            self.drained.notify_waiters();
+            let (partition, offset) = result.map_err(|(error, message)| DeliveryError {
+                error: error.into(),
+                message: Box::new(message),
+            })?;
+
+            Ok(Delivery {
+                partition,
+                offset,
+                timestamp,
+            })
+        }
+        .await;
+
+        report_delivery(&self.interceptors, topic, result.as_ref());
+        result
+    }
+    /// Enqueues a record without waiting for it to be delivered. Fails straight away if the
+    /// producer's queue is full.
+    pub(crate) fn enqueue_raw(&self, raw: RawRecord<'_>) -> Result<PendingDelivery, DeliveryError> {
+        let topic = raw.topic;
+        let result = self.prepare(raw).and_then(|(record, timestamp)| {
+            match self.inner.send_result(record) {
+                Ok(future) => Ok(PendingDelivery::new(
+                    future,
+                    topic.to_string(),
+                    timestamp,
+                    self.breaker.clone(),
+                    self.interceptors.clone(),
+                    self.drained.clone(),
+                )),
+                Err((error, record)) => {
+                    if let Some(breaker) = &self.breaker {
+                        breaker.release_probe();
+                    }
+                    Err(DeliveryError {
+                        error: error.into(),
+                        message: owned_message(record),
+                    })
+                }
+            }
+        });
+
+        if let Err(err) = &result {
+            report_delivery(&self.interceptors, topic, Err(err));
+        }
+        result
+    }
+    fn prepare<'a>(&self, raw: RawRecord<'a>) -> Result<PreparedRecord<'a>, DeliveryError> {
+        let timestamp = raw
+            .timestamp
+            .map_or_else(Timestamp::now, Timestamp::CreateTime);
+
+        let mut headers = raw.headers;
+        if !raw.payload_headers.is_empty() {
+            let mut header_map = headers
+                .as_ref()
+                .map(HeaderMap::from_headers)
+                .unwrap_or_default();
+            for (name, value) in raw.payload_headers {
+                header_map.insert_raw(*name, Some(value.clone()));
+            }
+            headers = Some(header_map.to_owned_headers());
+        }
+        let mut partition = raw.partition;
+        let mut veto = None;
+        if !self.interceptors.is_empty() {
+            let mut header_map = headers
+                .as_ref()
+                .map(HeaderMap::from_headers)
+                .unwrap_or_default();
+            let mut outgoing = OutgoingRecord {
+                topic: raw.topic,
+                key: raw.key,
+                payload: raw.payload,
+                partition,
+                headers: &mut header_map,
+            };
+            veto = self
+                .interceptors
+                .iter()
+                .find_map(|interceptor| interceptor.on_send(&mut outgoing).err());
+            partition = outgoing.partition;
+            headers = (!header_map.is_empty()).then(|| header_map.to_owned_headers());
+        }
+
+        let mut record = FutureRecord::<[u8], [u8]>::to(raw.topic)
+            .timestamp(timestamp.to_millis().unwrap_or_default());
+        if let Some(headers) = headers {
+            record = record.headers(headers);
+        }
+        if let Some(key) = raw.key {
+            record = record.key(key);
+        }
+        if let Some(payload) = raw.payload {
+            record = record.payload(payload);
+        }
+        let partition = partition.or_else(|| {
+            let partitioner = self.partitioner.as_ref()?;
+            partitioner.partition(&self.inner, raw.topic, raw.key)
+        });
+        if let Some(partition) = partition {
+            record = record.partition(partition);
+        }
+
+        if let Some(code) = veto {
+            return Err(DeliveryError {
+                error: KafkaError::MessageProduction(code).into(),
+                message: owned_message(record),
+            });
+        }
+        if self.closed.load(Ordering::Acquire) {
+            return Err(DeliveryError {
+                error: RdkafkaExtError::ShuttingDown,
+                message: owned_message(record),
+            });
+        }
+        if let Some(breaker) = &self.breaker {
+            if !breaker.allow() {
+                return Err(DeliveryError {
+                    error: RdkafkaExtError::CircuitOpen,
+                    message: owned_message(record),
+                });
+            }
+        }
+        Ok((record, timestamp))
+    }
+}
+
+pub(crate) fn record_outcome(breaker: Option<&CircuitBreaker>, delivered: bool) {
+    match breaker {
+        Some(breaker) if delivered => breaker.record_success(),
+        Some(breaker) => breaker.record_failure(),
+        None => {}
+    }
+}
+
+pub(crate) fn report_delivery(
+    interceptors: &[Arc<dyn ProducerInterceptor>],
+    topic: &str,
+    result: Result<&Delivery, &DeliveryError>,
+) {
+    for interceptor in interceptors {
+        interceptor.on_delivery(topic, result.map_err(|err| &err.error));
+    }
+}
+
+type PreparedRecord<'a> = (FutureRecord<'a, [u8], [u8]>, Timestamp);
+
+/// Copies a record that never made it into the producer's queue, to hand back to the caller.
+fn owned_message(record: FutureRecord<'_, [u8], [u8]>) -> Box<OwnedMessage> {
+    Box::new(OwnedMessage::new(
+        record.payload.map(<[u8]>::to_vec),
+        record.key.map(<[u8]>::to_vec),
+        record.topic.to_string(),
+        record
+            .timestamp
+            .map_or(Timestamp::NotAvailable, Timestamp::CreateTime),
+        record.partition.unwrap_or(-1),
+        -1,
+        record.headers,
+    ))
+}
+
+/// What a [`TypedConsumer`] does with a message whose payload can't be deserialized, or whose
+/// handler returns an error.
+#[derive(Clone)]
+pub enum PoisonPolicy {
+    /// Drop the message and move on.
+    Skip,
+    /// Stop and return the error to the caller.
+    Fail,
+    /// Forward the raw message to another topic, then move on.
+    DeadLetter {
+        producer: TypedProducer,
+        topic: String,
+    },
+    /// Run the handler up to this many more times, then fail. Payloads that can't be
+    /// deserialized fail immediately.
+    Retry(usize),
+    /// Republish the message to the next of its retry tiers, or to the dead-letter topic once
+    /// it has been through them all, then move on. See [`retry_topics`].
+    RetryTopics(RetryTiers),
+}
+impl PoisonPolicy {
+    /// Runs `attempt` until it succeeds or runs out of retries, then applies the policy to the
+    /// last failure.
+    pub(crate) async fn attempt<E>(
+        &self,
+        message: &BorrowedMessage<'_>,
+        mut attempt: impl AsyncFnMut() -> Result<(), E>,
+    ) -> Result<(), RdkafkaExtError>
+    where
+        E: Into<Box<dyn Error + Send + Sync>>,
+    {
+        let mut retries = match self {
+            PoisonPolicy::Retry(count) => *count,
+            _ => 0,
         };
 
-        self.inner.send(record, timeout).await.unwrap();
+        let err = loop {
+            match attempt().await {
+                Ok(()) => return Ok(()),
+                Err(_) if retries > 0 => retries -= 1,
+                Err(err) => break RdkafkaExtError::Handler(err.into()),
+            }
+        };
+        self.apply(message, err).await
+    }
+    /// Skips, fails or dead-letters a message that couldn't be handled, as the policy says.
+    pub(crate) async fn apply(
+        &self,
+        message: &BorrowedMessage<'_>,
+        err: RdkafkaExtError,
+    ) -> Result<(), RdkafkaExtError> {
+        match self {
+            PoisonPolicy::Skip => Ok(()),
+            PoisonPolicy::Fail | PoisonPolicy::Retry(_) => Err(err),
+            PoisonPolicy::DeadLetter { producer, topic } => {
+                let reason = err.to_string();
+                let partition = message.partition().to_string();
+                let offset = message.offset().to_string();
+                let headers = OwnedHeaders::new()
+                    .insert(Header {
+                        key: "x-dead-letter-reason",
+                        value: Some(&reason),
+                    })
+                    .insert(Header {
+                        key: "x-original-topic",
+                        value: Some(message.topic()),
+                    })
+                    .insert(Header {
+                        key: "x-original-partition",
+                        value: Some(&partition),
+                    })
+                    .insert(Header {
+                        key: "x-original-offset",
+                        value: Some(&offset),
+                    });
+                let record =
+                    RawRecord::new(topic, message.key(), message.payload()).headers(headers);
+                producer.send_raw(record, Timeout::Never).await?;
+                Ok(())
+            }
+            PoisonPolicy::RetryTopics(tiers) => tiers.republish(message, err).await,
+        }
     }
 }
 
-pub struct TypedConsumer<T> {
-    inner: StreamConsumer,
-    topic: T,
+/// What a [`TypedConsumer`] is subscribed to.
+enum Subscription<T> {
+    /// Topics by name.
+    Topics(Vec<(String, T)>),
+    Pattern(String),
 }
 
-impl<T: Topic> TypedConsumer<T> {
-    pub fn new(client_config: ClientConfig, topic: T) -> Self {
-        let inner: StreamConsumer = client_config.create().unwrap();
-        inner.subscribe(&[&topic.topic_string()]).unwrap();
+/// Receives typed messages. The context `C` receives librdkafka's logs, errors, statistics and
+/// commit outcomes, generates OAuth tokens, and hears about rebalances through
+/// [`pre_rebalance`](ConsumerContext::pre_rebalance) and
+/// [`post_rebalance`](ConsumerContext::post_rebalance).
+pub struct TypedConsumer<T, C: ConsumerContext + 'static = DefaultConsumerContext> {
+    inner: Arc<StreamConsumer<ConsumerExtContext<C>>>,
+    subscription: Subscription<T>,
+    retry_schedule: Option<RetrySchedule>,
+    poison_policy: PoisonPolicy,
+    max_age: Option<Duration>,
+    stale_skipped: AtomicU64,
+    taps: Taps<T>,
+    commit_on_drop: bool,
+    unsubscribe_on_drop: bool,
+}
 
-        Self { inner, topic }
+impl<T: Topic> TypedConsumer<T> {
+    pub fn new(client_config: ClientConfig, topic: T) -> Result<Self, RdkafkaExtError> {
+        Self::new_with_context(client_config, topic, DefaultConsumerContext)
     }
-    pub fn topic(&self) -> &T {
-        &self.topic
+    /// Subscribes to several topics of the same type, e.g. many sessions' topics. Each message
+    /// carries the topic it arrived on.
+    pub fn new_multi(client_config: ClientConfig, topics: Vec<T>) -> Result<Self, RdkafkaExtError> {
+        let topics: Vec<_> = topics
+            .into_iter()
+            .map(|topic| (topic.topic_string(), topic))
+            .collect();
+        Self::subscribe(
+            client_config,
+            Subscription::Topics(topics),
+            None,
+            DefaultConsumerContext,
+        )
+    }
+    /// Subscribes to every topic whose name matches the regex `pattern`, e.g. `session:.*`,
+    /// including ones created later. The typed topic of each message is rebuilt from its name
+    /// with [`Topic::from_topic_string`], and messages from topics it returns `None` for fail
+    /// with [`RdkafkaExtError::UnknownTopic`].
+    ///
+    /// The pattern is anchored to the start of the name, as librdkafka requires.
+    pub fn new_pattern(
+        client_config: ClientConfig,
+        pattern: &str,
+    ) -> Result<Self, RdkafkaExtError> {
+        let pattern = match pattern.starts_with('^') {
+            true => pattern.to_string(),
+            false => format!("^{pattern}"),
+        };
+        Self::subscribe(
+            client_config,
+            Subscription::Pattern(pattern),
+            None,
+            DefaultConsumerContext,
+        )
+    }
+}
+impl<T: Topic, C: ConsumerContext + 'static> TypedConsumer<T, C> {
+    /// Like [`new`](TypedConsumer::new), but with a consumer context of your own, e.g. to act on
+    /// rebalances or commit outcomes, or to forward librdkafka's logs.
+    ///
+    /// The context's own [`rebalance`](ConsumerContext::rebalance) isn't called; partitions are
+    /// always assigned and revoked the default way, with its `pre_rebalance` and
+    /// `post_rebalance` called around that.
+    pub fn new_with_context(
+        client_config: ClientConfig,
+        topic: T,
+        context: C,
+    ) -> Result<Self, RdkafkaExtError> {
+        let topics = vec![(topic.topic_string(), topic)];
+        Self::subscribe(client_config, Subscription::Topics(topics), None, context)
     }
-    pub async fn recv(&self) -> Result<TypedMessage<T>, KafkaError> {
-        Ok(TypedMessage {
-            message: self.inner.recv().await?,
-            topic: self.topic.clone(),
+    fn subscribe(
+        client_config: ClientConfig,
+        subscription: Subscription<T>,
+        retry_schedule: Option<RetrySchedule>,
+        context: C,
+    ) -> Result<Self, RdkafkaExtError> {
+        let inner: Arc<StreamConsumer<_>> =
+            Arc::new(client_config.create_with_context(ConsumerExtContext::new(context))?);
+        let _ = inner.context().consumer.set(Arc::downgrade(&inner));
+        let mut topics: Vec<_> = match &subscription {
+            Subscription::Topics(topics) => topics.iter().map(|(name, _)| name.as_str()).collect(),
+            Subscription::Pattern(pattern) => vec![pattern.as_str()],
+        };
+        topics.extend(retry_schedule.iter().flat_map(RetrySchedule::tier_topics));
+        inner.subscribe(&topics)?;
+
+        Ok(Self {
+            inner,
+            subscription,
+            retry_schedule,
+            poison_policy: PoisonPolicy::Fail,
+            max_age: None,
+            stale_skipped: AtomicU64::new(0),
+            taps: Taps::default(),
+            commit_on_drop: false,
+            unsubscribe_on_drop: false,
         })
     }
-    pub async fn stream(&self) -> impl Stream<Item = Result<TypedMessage<T>, KafkaError>> + '_ {
-        self.inner.stream().map(|val| {
-            val.map(|borrowed_message| TypedMessage {
-                message: borrowed_message,
-                topic: self.topic.clone(),
+    /// Sets what happens to poison messages in [`handle`](Self::handle) and
+    /// [`run`](Self::run).
+    ///
+    /// Default: [`PoisonPolicy::Fail`]
+    pub fn with_poison_policy(mut self, policy: PoisonPolicy) -> Self {
+        self.poison_policy = policy;
+        self
+    }
+    /// Runs `interceptor` on every message received and every commit made by this consumer,
+    /// after any interceptors added before it.
+    pub fn with_interceptor(self, interceptor: impl ConsumerInterceptor + 'static) -> Self {
+        self.inner
+            .context()
+            .interceptors
+            .write()
+            .unwrap_or_else(|err| err.into_inner())
+            .push(Arc::new(interceptor));
+        self
+    }
+    /// Skips messages whose timestamp is more than `max_age` old by the time they're received,
+    /// e.g. to drop requests nobody is waiting on any more after catching up on a backlog.
+    /// Messages without a timestamp are never skipped. Skipped messages still count as
+    /// consumed, and [`stale_skipped`](Self::stale_skipped) counts them.
+    pub fn with_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+    /// How many messages have been skipped for being older than the
+    /// [`max_age`](Self::with_max_age).
+    pub fn stale_skipped(&self) -> u64 {
+        self.stale_skipped.load(Ordering::Relaxed)
+    }
+    /// Commits the stored offsets when the consumer is dropped, so short-lived consumers in jobs
+    /// and tests don't reprocess their last messages on the next run. The commit is best-effort:
+    /// it is queued without waiting, and failures are ignored. Use [`close`](Self::close) to wait
+    /// for it and see them.
+    pub fn with_commit_on_drop(mut self) -> Self {
+        self.commit_on_drop = true;
+        self
+    }
+    /// Leaves the group when the consumer is dropped, so its partitions are handed to other
+    /// members straight away rather than after the session times out.
+    pub fn with_unsubscribe_on_drop(mut self) -> Self {
+        self.unsubscribe_on_drop = true;
+        self
+    }
+    /// Commits the stored offsets and leaves the group. Blocks the calling thread until the
+    /// commit is done, so call it from [`spawn_blocking`](tokio::task::spawn_blocking) or
+    /// outside the runtime if that matters. Nothing more happens when the consumer is dropped.
+    pub fn close(mut self) -> Result<(), RdkafkaExtError> {
+        self.commit_on_drop = false;
+        self.unsubscribe_on_drop = false;
+        let committed = self.commit_stored();
+        self.inner.unsubscribe();
+        committed
+    }
+    /// Synchronously commits the offsets stored for every assigned partition, if there are
+    /// any.
+    fn commit_stored(&self) -> Result<(), RdkafkaExtError> {
+        match self.inner.commit_consumer_state(CommitMode::Sync) {
+            Err(KafkaError::ConsumerCommit(RDKafkaErrorCode::NoOffset)) => Ok(()),
+            result => Ok(result?),
+        }
+    }
+    pub fn context(&self) -> &C {
+        &self.inner.context().context
+    }
+    /// The latest statistics report, if there has been one. Reports are only made when
+    /// `statistics.interval.ms` is set, e.g. with
+    /// [`statistics_interval`](builders::traits::KafkaConfigBuilder::statistics_interval).
+    pub fn stats(&self) -> Option<ConsumerStats> {
+        self.inner.context().stats.latest()
+    }
+    /// Receives every statistics report from now on, e.g. to export lag to a metrics system.
+    pub fn stats_stream(&self) -> UnboundedReceiver<ConsumerStats> {
+        self.inner.context().stats.subscribe()
+    }
+    /// The topic subscribed to, unless subscribed to several or to a pattern.
+    pub fn topic(&self) -> Option<&T> {
+        match &self.subscription {
+            Subscription::Topics(topics) if topics.len() == 1 => Some(&topics[0].1),
+            _ => None,
+        }
+    }
+    /// The topics subscribed to, or none if subscribed to a pattern.
+    pub fn topics(&self) -> impl Iterator<Item = &T> {
+        let topics = match &self.subscription {
+            Subscription::Topics(topics) => topics.as_slice(),
+            Subscription::Pattern(_) => &[],
+        };
+        topics.iter().map(|(_, topic)| topic)
+    }
+    /// The typed topic a message received from `name` belongs to.
+    pub(crate) fn topic_for(&self, name: &str) -> Result<T, RdkafkaExtError> {
+        let original = self
+            .retry_schedule
+            .as_ref()
+            .and_then(|schedule| schedule.original_topic(name));
+        match &self.subscription {
+            Subscription::Topics(topics) => topics
+                .iter()
+                .find(|(topic_name, _)| topic_name == original.unwrap_or(name))
+                .map(|(_, topic)| topic.clone())
+                .ok_or_else(|| RdkafkaExtError::UnknownTopic(name.into())),
+            Subscription::Pattern(_) => {
+                T::from_topic_string(name).ok_or_else(|| RdkafkaExtError::UnknownTopic(name.into()))
+            }
+        }
+    }
+    /// Decodes the message's payload, letting the interceptors know how it went.
+    fn decode(&self, message: &TypedMessage<'_, T>) -> PayloadOutcome<T::Payload> {
+        let outcome = message.payload_outcome();
+        let result = match &outcome {
+            PayloadOutcome::Missing => return outcome,
+            PayloadOutcome::Decoded(_) => Ok(()),
+            PayloadOutcome::Invalid(err) => Err(err),
+        };
+        self.inner.context().intercept(|interceptor| {
+            interceptor.on_decode(&message.message, result);
+            true
+        });
+        outcome
+    }
+    pub(crate) fn typed<'a>(
+        &self,
+        message: BorrowedMessage<'a>,
+    ) -> Result<TypedMessage<'a, T>, RdkafkaExtError> {
+        let topic = self.topic_for(message.topic())?;
+        Ok(TypedMessage { message, topic })
+    }
+    /// The topics that partition numbers refer to: the ones subscribed to, or every assigned
+    /// topic for pattern subscriptions.
+    fn topic_names(&self) -> Result<Vec<String>, RdkafkaExtError> {
+        match &self.subscription {
+            Subscription::Topics(topics) => {
+                Ok(topics.iter().map(|(name, _)| name.clone()).collect())
+            }
+            Subscription::Pattern(_) => {
+                let mut names: Vec<_> = self
+                    .inner
+                    .assignment()?
+                    .elements()
+                    .iter()
+                    .map(|element| element.topic().to_string())
+                    .collect();
+                names.sort();
+                names.dedup();
+                Ok(names)
+            }
+        }
+    }
+    /// Commits the offset after `message`, so the group resumes just past it.
+    ///
+    /// Only needed with `enable.auto.commit` off; otherwise offsets are committed periodically
+    /// for every message received.
+    pub fn commit_message(
+        &self,
+        message: &TypedMessage<'_, T>,
+        mode: CommitMode,
+    ) -> Result<(), RdkafkaExtError> {
+        Ok(self.inner.commit_message(&message.message, mode)?)
+    }
+    /// Commits explicit offsets. Each offset is the next one to be read, not the last one
+    /// processed.
+    pub fn commit(
+        &self,
+        offsets: &TopicPartitionList,
+        mode: CommitMode,
+    ) -> Result<(), RdkafkaExtError> {
+        Ok(self.inner.commit(offsets, mode)?)
+    }
+    /// Marks `message` as processed, so the next automatic commit includes it. Use it with
+    /// `enable.auto.offset.store` off to commit periodically while only counting messages that
+    /// were actually handled.
+    pub fn store_offset(&self, message: &TypedMessage<'_, T>) -> Result<(), RdkafkaExtError> {
+        Ok(self.inner.store_offset_from_message(&message.message)?)
+    }
+    /// Stops fetching from `partitions` of every topic subscribed to, or of every assigned topic
+    /// for pattern subscriptions, without leaving the group. Messages already fetched are still delivered.
+    pub fn pause(&self, partitions: &[i32]) -> Result<(), RdkafkaExtError> {
+        Ok(self.inner.pause(&self.partition_list(partitions)?)?)
+    }
+    pub fn resume(&self, partitions: &[i32]) -> Result<(), RdkafkaExtError> {
+        Ok(self.inner.resume(&self.partition_list(partitions)?)?)
+    }
+    /// Pauses every partition currently assigned. Partitions assigned later by a rebalance
+    /// aren't paused.
+    pub fn pause_all(&self) -> Result<(), RdkafkaExtError> {
+        Ok(self.inner.pause(&self.inner.assignment()?)?)
+    }
+    pub fn resume_all(&self) -> Result<(), RdkafkaExtError> {
+        Ok(self.inner.resume(&self.inner.assignment()?)?)
+    }
+    /// Moves an assigned partition so the next message read from it is at `offset`, e.g. to
+    /// replay it. Blocks until the seek is done or `timeout` runs out.
+    ///
+    /// With several topics, the partition is sought in each of them, or in every assigned topic
+    /// for pattern subscriptions.
+    pub fn seek(
+        &self,
+        partition: i32,
+        offset: Offset,
+        timeout: impl Into<Timeout>,
+    ) -> Result<(), RdkafkaExtError> {
+        let timeout = timeout.into();
+        for topic in self.topic_names()? {
+            self.inner.seek(&topic, partition, offset, timeout)?;
+        }
+        Ok(())
+    }
+    /// Seeks every assigned partition back to its oldest retained message.
+    pub fn seek_to_beginning(&self, timeout: impl Into<Timeout>) -> Result<(), RdkafkaExtError> {
+        self.seek_all(Offset::Beginning, timeout.into())
+    }
+    /// Seeks every assigned partition past its latest message, skipping the backlog.
+    pub fn seek_to_end(&self, timeout: impl Into<Timeout>) -> Result<(), RdkafkaExtError> {
+        self.seek_all(Offset::End, timeout.into())
+    }
+    /// Seeks every assigned partition to its first message with a timestamp at or after
+    /// `timestamp`, e.g. to reprocess everything since an incident started. Partitions with
+    /// nothing that recent are sought past their latest message.
+    pub fn seek_to_timestamp(
+        &self,
+        timestamp: SystemTime,
+        timeout: impl Into<Timeout>,
+    ) -> Result<(), RdkafkaExtError> {
+        let timeout = timeout.into();
+        let millis = timestamp
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_millis() as i64);
+
+        let offsets = self.inner.offsets_for_timestamp(millis, timeout)?;
+        for element in offsets.elements() {
+            element.error()?;
+            self.inner.seek(
+                element.topic(),
+                element.partition(),
+                element.offset(),
+                timeout,
+            )?;
+        }
+        Ok(())
+    }
+    fn seek_all(&self, offset: Offset, timeout: Timeout) -> Result<(), RdkafkaExtError> {
+        for element in self.inner.assignment()?.elements() {
+            self.inner
+                .seek(element.topic(), element.partition(), offset, timeout)?;
+        }
+        Ok(())
+    }
+    fn partition_list(&self, partitions: &[i32]) -> Result<TopicPartitionList, RdkafkaExtError> {
+        let mut list = TopicPartitionList::new();
+        for topic in self.topic_names()? {
+            for &partition in partitions {
+                list.add_partition(&topic, partition);
+            }
+        }
+        Ok(list)
+    }
+    /// Receives the next message, holding back retry tier messages that aren't due yet.
+    async fn next_message(&self) -> Result<BorrowedMessage<'_>, KafkaError> {
+        self.next_filtered(|| self.inner.recv()).await
+    }
+    /// Receives messages with `recv` until one gets past the consumer's filters. Shared by the
+    /// consumer's own queue and its split-off partition queues.
+    pub(crate) async fn next_filtered<'m, F, Fut>(
+        &self,
+        mut recv: F,
+    ) -> Result<BorrowedMessage<'m>, KafkaError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = KafkaResult<BorrowedMessage<'m>>>,
+    {
+        loop {
+            let received = match &self.retry_schedule {
+                Some(schedule) => {
+                    schedule.resume_due(&self.inner);
+                    match schedule.next_due(&self.inner) {
+                        Some(due) => match tokio::time::timeout_at(due.into(), recv()).await {
+                            Ok(received) => received,
+                            Err(_) => continue,
+                        },
+                        None => recv().await,
+                    }
+                }
+                None => recv().await,
+            };
+            let message = received?;
+            if self.admit(&message)? {
+                return Ok(message);
+            }
+        }
+    }
+    /// Whether a received message gets past retry tier hold-back,
+    /// [`max_age`](Self::with_max_age) and the interceptors. Taps see it if it does.
+    pub(crate) fn admit(&self, message: &BorrowedMessage<'_>) -> Result<bool, KafkaError> {
+        if let Some(schedule) = &self.retry_schedule {
+            if schedule.hold_back(&self.inner, message)? {
+                return Ok(false);
+            }
+        }
+        if self.is_stale(message) {
+            self.stale_skipped.fetch_add(1, Ordering::Relaxed);
+            return Ok(false);
+        }
+        let context = self.inner.context();
+        if !context.intercept(|interceptor| interceptor.on_receive(message)) {
+            return Ok(false);
+        }
+        self.tap_message(message);
+        Ok(true)
+    }
+    fn is_stale(&self, message: &BorrowedMessage<'_>) -> bool {
+        let (Some(max_age), Some(timestamp)) = (self.max_age, message.timestamp().to_millis())
+        else {
+            return false;
+        };
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_millis() as i64);
+        now.saturating_sub(timestamp) > max_age.as_millis().try_into().unwrap_or(i64::MAX)
+    }
+    pub async fn recv(&self) -> Result<TypedMessage<'_, T>, RdkafkaExtError> {
+        self.typed(self.next_message().await?)
+    }
+    /// Like [`recv`](Self::recv), but gives up after `timeout`, returning `None`, so polling
+    /// loops can check for shutdown or do periodic work in between messages.
+    pub async fn recv_timeout(
+        &self,
+        timeout: Duration,
+    ) -> Result<Option<TypedMessage<'_, T>>, RdkafkaExtError> {
+        match tokio::time::timeout(timeout, self.next_message()).await {
+            Ok(message) => self.typed(message?).map(Some),
+            Err(_) => Ok(None),
+        }
+    }
+    /// Receives up to `max_messages` messages, waiting at most `max_wait` in total, so bulk
+    /// consumers can handle several at once. The batch is empty if nothing arrived in time.
+    ///
+    /// An error ends the batch early. It's returned if nothing had been received yet; otherwise
+    /// the messages are returned and the error is dropped.
+    pub async fn recv_batch(
+        &self,
+        max_messages: usize,
+        max_wait: Duration,
+    ) -> Result<Vec<TypedMessage<'_, T>>, RdkafkaExtError> {
+        let deadline = tokio::time::Instant::now() + max_wait;
+        let mut batch = Vec::with_capacity(max_messages.min(1024));
+        while batch.len() < max_messages {
+            let received = tokio::time::timeout_at(deadline, self.next_message()).await;
+            match received.map(|message| self.typed(message?)) {
+                Ok(Ok(message)) => batch.push(message),
+                Ok(Err(err)) if batch.is_empty() => return Err(err),
+                Ok(Err(_)) | Err(_) => break,
+            }
+        }
+        Ok(batch)
+    }
+    /// Like [`recv`](Self::recv), but waits out retryable errors (broker transport failures,
+    /// rebalances, ...) with exponential backoff, only returning errors that aren't retryable.
+    pub async fn recv_resilient(&self) -> Result<TypedMessage<'_, T>, RdkafkaExtError> {
+        const INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+        const MAX_BACKOFF: Duration = Duration::from_secs(10);
+
+        let mut backoff = INITIAL_BACKOFF;
+        loop {
+            match self.next_message().await {
+                Ok(message) => return self.typed(message),
+                Err(err) if ErrorClass::of_kafka_error(&err) == ErrorClass::Retryable => {
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(MAX_BACKOFF);
+                }
+                Err(err) => return Err(err.into()),
+            }
+        }
+    }
+    pub async fn stream(
+        &self,
+    ) -> impl Stream<Item = Result<TypedMessage<'_, T>, RdkafkaExtError>> + '_ {
+        Box::pin(stream::unfold((), move |()| async move {
+            let message = self.next_message().await;
+            Some((message.map_err(RdkafkaExtError::from), ()))
+        }))
+        .map(|message| self.typed(message?))
+    }
+    /// Splits consumption into a stream of successfully decoded payloads and a side channel of
+    /// everything that went wrong along the way, transport and decode errors alike. Records
+    /// without a payload are skipped.
+    pub async fn stream_with_errors(
+        &self,
+    ) -> (
+        impl Stream<Item = T::Payload> + '_,
+        UnboundedReceiver<RdkafkaExtError>,
+    ) {
+        let (errors, receiver) = mpsc::unbounded();
+
+        let payloads = self.stream().await.filter_map(move |message| {
+            let payload = match message.map(|message| self.decode(&message)) {
+                Ok(PayloadOutcome::Decoded(payload)) => Some(payload),
+                Ok(PayloadOutcome::Missing) => None,
+                Ok(PayloadOutcome::Invalid(err)) => {
+                    let _ = errors.unbounded_send(err.into());
+                    None
+                }
+                Err(err) => {
+                    let _ = errors.unbounded_send(err);
+                    None
+                }
+            };
+            future::ready(payload)
+        });
+
+        (payloads, receiver)
+    }
+    /// Deserializes the message and passes the payload to `handler`, applying the configured
+    /// [`PoisonPolicy`] if either step fails. Messages without a payload are skipped.
+    pub async fn handle<F, Fut, E>(
+        &self,
+        message: &TypedMessage<'_, T>,
+        mut handler: F,
+    ) -> Result<(), RdkafkaExtError>
+    where
+        F: FnMut(T::Payload) -> Fut,
+        Fut: Future<Output = Result<(), E>>,
+        E: Into<Box<dyn Error + Send + Sync>>,
+    {
+        let mut payload = match self.decode(message) {
+            PayloadOutcome::Decoded(payload) => Some(payload),
+            PayloadOutcome::Missing => return Ok(()),
+            PayloadOutcome::Invalid(err) => {
+                return self.poison_policy.apply(&message.message, err.into()).await;
+            }
+        };
+
+        // Each retry needs its own payload, so it's decoded again after the first attempt.
+        self.poison_policy
+            .attempt(&message.message, async || {
+                let payload = match payload.take() {
+                    Some(payload) => payload,
+                    None => match self.decode(message) {
+                        PayloadOutcome::Decoded(payload) => payload,
+                        PayloadOutcome::Missing => return Ok(()),
+                        PayloadOutcome::Invalid(err) => return Err(err.into()),
+                    },
+                };
+                handler(payload)
+                    .await
+                    .map_err(Into::<Box<dyn Error + Send + Sync>>::into)
             })
-        })
+            .await
+    }
+    /// Receives messages forever, passing each payload to `handler` as in
+    /// [`handle`](Self::handle). Returns on the first error the poison policy doesn't absorb.
+    pub async fn run<F, Fut, E>(&self, mut handler: F) -> Result<(), RdkafkaExtError>
+    where
+        F: FnMut(T::Payload) -> Fut,
+        Fut: Future<Output = Result<(), E>>,
+        E: Into<Box<dyn Error + Send + Sync>>,
+    {
+        loop {
+            let message = self.recv().await?;
+            self.handle(&message, &mut handler).await?;
+        }
+    }
+    /// Receives messages forever, passing each one to `handler` and committing its offset once
+    /// the handler succeeds, for at-least-once processing. Failures go through the configured
+    /// [`PoisonPolicy`]: skipped and dead-lettered messages are committed too, and the first
+    /// error the policy doesn't absorb is returned with the message left uncommitted.
+    ///
+    /// Commits are asynchronous, and only make sense with `enable.auto.commit` off.
+    pub async fn process_each<F, E>(&self, mut handler: F) -> Result<(), RdkafkaExtError>
+    where
+        F: AsyncFnMut(&TypedMessage<'_, T>) -> Result<(), E>,
+        E: Into<Box<dyn Error + Send + Sync>>,
+    {
+        loop {
+            let message = self.recv().await?;
+            self.poison_policy
+                .attempt(&message.message, async || handler(&message).await)
+                .await?;
+            self.commit_message(&message, CommitMode::Async)?;
+        }
     }
 }
+impl<T, C: ConsumerContext + 'static> Drop for TypedConsumer<T, C> {
+    fn drop(&mut self) {
+        if self.commit_on_drop {
+            let _ = self.inner.commit_consumer_state(CommitMode::Async);
+        }
+        if self.unsubscribe_on_drop {
+            self.inner.unsubscribe();
+        }
+    }
+}
+
+/// The non-error outcomes of [`TypedAdmin::create_topic`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CreateTopicOutcome {
+    Created,
+    AlreadyExists,
+}
 
 pub struct TypedAdmin {
     inner: AdminClient<DefaultClientContext>,
 }
 impl TypedAdmin {
-    pub fn new(client_config: ClientConfig) -> Self {
-        let inner = client_config.create().unwrap();
+    pub fn new(client_config: ClientConfig) -> Result<Self, RdkafkaExtError> {
+        let inner = client_config.create()?;
 
-        Self { inner }
+        Ok(Self { inner })
     }
     pub async fn create_topic(
         &self,
         topic: impl Topic,
         num_partitions: i32,
         replication: TopicReplication<'_>,
-    ) {
+    ) -> Result<CreateTopicOutcome, RdkafkaExtError> {
         let topic_string = topic.topic_string();
 
         let new_topic = NewTopic::new(&topic_string, num_partitions, replication);
-        let _create = self
+        let result = self
             .inner
             .create_topics(&[new_topic], &AdminOptions::new())
-            .await
-            .unwrap()
-            .first()
-            .unwrap()
-            .as_ref()
-            .unwrap();
+            .await?
+            .into_iter()
+            .next()
+            .unwrap_or(Ok(topic_string));
+
+        match result {
+            Ok(_) => Ok(CreateTopicOutcome::Created),
+            Err((_, RDKafkaErrorCode::TopicAlreadyExists)) => Ok(CreateTopicOutcome::AlreadyExists),
+            Err((resource, code)) => Err(RdkafkaExtError::Admin { resource, code }),
+        }
     }
 }
 
+#[allow(dead_code)]
 mod example {
     use serde::{Deserialize, Serialize};
 
@@ -159,13 +1773,15 @@ mod example {
     }
     impl Topic for SessionTopic {
         type Payload = Update;
+        type Key = String;
+        type Codec = JsonCodec;
 
         fn topic_string(&self) -> String {
             format!("session:{}", self.id)
         }
     }
 
-    async fn _consumer_example() {
+    async fn _consumer_example() -> Result<(), RdkafkaExtError> {
         let config = ConsumerConfigBuilder::new()
             .bootstrap_servers(&["localhost:9092"])
             .allow_auto_create_topics(true)
@@ -177,31 +1793,35 @@ mod example {
             SessionTopic {
                 id: "asdflkj".to_string(),
             },
-        );
+        )?;
 
         let mut stream = consumer.stream().await;
         while let Some(Ok(message)) = stream.next().await {
-            if let Some(payload) = message.payload() {
+            if let Some(payload) = message.payload()? {
                 match payload {
                     Update::Thing1 => println!("Do thing 1"),
                     Update::Thing2 => println!("Do thing 2"),
                 }
             }
         }
+
+        Ok(())
     }
 
-    async fn _producer_example() {
+    async fn _producer_example() -> Result<(), RdkafkaExtError> {
         let config = ProducerConfigBuilder::new()
             .bootstrap_servers(&["localhost:9092"])
             .client_id("client")
             .build();
 
-        let producer = TypedProducer::new(config);
+        let producer = TypedProducer::new(config)?;
 
         let topic = SessionTopic {
             id: "s2d54f".to_string(),
         };
 
-        producer.send(&topic, &Update::Thing1, None, None).await;
+        producer.send(&topic, &Update::Thing1, None).await?;
+
+        Ok(())
     }
 }
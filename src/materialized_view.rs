@@ -0,0 +1,172 @@
+//! A table of the latest payload for every key in a topic, kept up to date as the topic
+//! changes, like a Kafka Streams `KTable`.
+//!
+//! Every instance should see every partition, so give each consumer a group of its own, and
+//! read the topic from the beginning with `auto.offset.reset=earliest`.
+
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    sync::{Arc, Mutex, RwLock},
+};
+
+use futures::{
+    channel::mpsc::{self, UnboundedReceiver, UnboundedSender},
+    StreamExt,
+};
+use rdkafka::{
+    consumer::{ConsumerContext, DefaultConsumerContext},
+    util::Timeout,
+};
+
+use crate::{error::RdkafkaExtError, Topic, TypedConsumer, TypedMessage};
+
+/// A change applied to a [`MaterializedView`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ViewChange<K, V> {
+    Upserted {
+        key: K,
+        value: V,
+    },
+    /// The key's tombstone was read.
+    Deleted {
+        key: K,
+    },
+}
+
+/// Reads a [`MaterializedView`]'s table from anywhere, while it's being updated.
+pub struct ViewReader<K, V> {
+    table: Arc<RwLock<HashMap<K, V>>>,
+}
+impl<K, V> Clone for ViewReader<K, V> {
+    fn clone(&self) -> Self {
+        Self {
+            table: self.table.clone(),
+        }
+    }
+}
+impl<K: Eq + Hash, V: Clone> ViewReader<K, V> {
+    pub fn get(&self, key: &K) -> Option<V> {
+        self.read(|table| table.get(key).cloned())
+    }
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.read(|table| table.contains_key(key))
+    }
+    pub fn len(&self) -> usize {
+        self.read(HashMap::len)
+    }
+    pub fn is_empty(&self) -> bool {
+        self.read(HashMap::is_empty)
+    }
+    /// Runs `f` on the table, holding off updates until it returns.
+    pub fn read<R>(&self, f: impl FnOnce(&HashMap<K, V>) -> R) -> R {
+        f(&self.table.read().unwrap_or_else(|err| err.into_inner()))
+    }
+    /// Copies out the whole table.
+    pub fn snapshot(&self) -> HashMap<K, V>
+    where
+        K: Clone,
+    {
+        self.read(HashMap::clone)
+    }
+}
+
+type Listeners<K, V> = Mutex<Vec<UnboundedSender<ViewChange<K, V>>>>;
+
+/// Keeps a table of the latest payload for every key of `T`, removing keys when their
+/// tombstones are read. Load it with [`load`](Self::load), then keep it up to date with
+/// [`run`](Self::run), reading it meanwhile through [`reader`](Self::reader).
+///
+/// Records without a key are ignored. Records that can't be decoded go through the consumer's
+/// [`PoisonPolicy`](crate::PoisonPolicy).
+pub struct MaterializedView<T: Topic, C: ConsumerContext + 'static = DefaultConsumerContext> {
+    consumer: TypedConsumer<T, C>,
+    table: Arc<RwLock<HashMap<T::Key, T::Payload>>>,
+    listeners: Listeners<T::Key, T::Payload>,
+}
+impl<T, C> MaterializedView<T, C>
+where
+    T: Topic,
+    T::Key: Eq + Hash + Clone,
+    T::Payload: Clone,
+    C: ConsumerContext + 'static,
+{
+    pub fn new(consumer: TypedConsumer<T, C>) -> Self {
+        Self {
+            consumer,
+            table: Arc::default(),
+            listeners: Mutex::default(),
+        }
+    }
+    pub fn consumer(&self) -> &TypedConsumer<T, C> {
+        &self.consumer
+    }
+    pub fn reader(&self) -> ViewReader<T::Key, T::Payload> {
+        ViewReader {
+            table: self.table.clone(),
+        }
+    }
+    /// Receives every change applied from now on.
+    pub fn changes(&self) -> UnboundedReceiver<ViewChange<T::Key, T::Payload>> {
+        let (sender, receiver) = mpsc::unbounded();
+        self.listeners
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .push(sender);
+        receiver
+    }
+    /// Reads the topic up to its current end with
+    /// [`read_to_end`](TypedConsumer::read_to_end), returning how many records were applied.
+    pub async fn load(&self, timeout: impl Into<Timeout>) -> Result<usize, RdkafkaExtError> {
+        let mut messages = self.consumer.read_to_end(timeout);
+        let mut applied = 0;
+        while let Some(message) = messages.next().await {
+            self.apply(&message?).await?;
+            applied += 1;
+        }
+        Ok(applied)
+    }
+    /// Applies records as they arrive, forever. Returns on the first error the poison policy
+    /// doesn't absorb.
+    pub async fn run(&self) -> Result<(), RdkafkaExtError> {
+        loop {
+            let message = self.consumer.recv().await?;
+            self.apply(&message).await?;
+        }
+    }
+
+    async fn apply(&self, message: &TypedMessage<'_, T>) -> Result<(), RdkafkaExtError> {
+        let decoded = message
+            .typed_key()
+            .and_then(|key| Ok((key, message.payload()?)));
+        let change = match decoded {
+            Ok((None, _)) => return Ok(()),
+            Ok((Some(key), Some(value))) => ViewChange::Upserted { key, value },
+            Ok((Some(key), None)) => ViewChange::Deleted { key },
+            Err(err) => {
+                return self
+                    .consumer
+                    .poison_policy
+                    .apply(&message.message, err.into())
+                    .await
+            }
+        };
+
+        {
+            let mut table = self.table.write().unwrap_or_else(|err| err.into_inner());
+            match &change {
+                ViewChange::Upserted { key, value } => {
+                    table.insert(key.clone(), value.clone());
+                }
+                ViewChange::Deleted { key } => {
+                    table.remove(key);
+                }
+            }
+        }
+        self.listeners
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .retain(|listener| listener.unbounded_send(change.clone()).is_ok());
+        Ok(())
+    }
+}
@@ -0,0 +1,192 @@
+//! Keeping offsets outside Kafka, e.g. in the database a handler writes to, so the offset and
+//! the handler's side effects can be saved in one transaction and neither can get ahead of the
+//! other.
+
+use std::{collections::HashMap, error::Error, future::Future, sync::Mutex, time::Duration};
+
+use futures::channel::mpsc::UnboundedReceiver;
+use rdkafka::{
+    consumer::{Consumer, ConsumerContext, DefaultConsumerContext},
+    Message, Offset,
+};
+
+use crate::{
+    error::RdkafkaExtError, offsets::AssignedPartition, rebalance::RebalanceEvent, Topic,
+    TypedConsumer, TypedMessage,
+};
+
+/// Where a consumer's offsets are kept instead of Kafka. Offsets are the next one to be read,
+/// as Kafka counts them.
+pub trait OffsetStore: Send + Sync {
+    /// The stored offset of a partition, or `None` to start wherever Kafka would, e.g. with
+    /// `auto.offset.reset`.
+    fn load(
+        &self,
+        partition: &AssignedPartition,
+    ) -> impl Future<Output = Result<Option<i64>, Box<dyn Error + Send + Sync>>> + Send;
+    /// Stores a partition's offset. Handlers that save it along with their own writes can leave
+    /// this to do nothing.
+    fn save(
+        &self,
+        partition: &AssignedPartition,
+        offset: i64,
+    ) -> impl Future<Output = Result<(), Box<dyn Error + Send + Sync>>> + Send;
+}
+
+/// How far a partition has been positioned from the store since it was assigned.
+enum Positioned {
+    /// Sought to the stored offset; messages outside `first..=last` were fetched before the
+    /// seek and are dropped. The stored offset itself may never arrive, e.g. if it was
+    /// compacted away or is a transaction marker, so any offset in range counts.
+    Seeking {
+        first: i64,
+        last: i64,
+    },
+    Done,
+}
+
+/// A [`TypedConsumer`] that starts every partition it's assigned from the offset in an
+/// [`OffsetStore`], rather than the group's committed offset.
+///
+/// Give the consumer `enable.auto.commit=false`, so Kafka's offsets don't get mistaken for the
+/// real ones by tools, and don't commit through it.
+pub struct ExternalOffsetConsumer<T, S, C: ConsumerContext + 'static = DefaultConsumerContext> {
+    consumer: TypedConsumer<T, C>,
+    store: S,
+    rebalances: Mutex<UnboundedReceiver<RebalanceEvent>>,
+    positioned: Mutex<HashMap<AssignedPartition, Positioned>>,
+}
+impl<T: Topic, S: OffsetStore, C: ConsumerContext + 'static> ExternalOffsetConsumer<T, S, C> {
+    pub fn new(consumer: TypedConsumer<T, C>, store: S) -> Self {
+        let rebalances = Mutex::new(consumer.rebalance_events());
+        Self {
+            consumer,
+            store,
+            rebalances,
+            positioned: Mutex::default(),
+        }
+    }
+    pub fn consumer(&self) -> &TypedConsumer<T, C> {
+        &self.consumer
+    }
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+    /// Receives the next message, seeking each newly assigned partition to its stored offset
+    /// first.
+    pub async fn recv(&self) -> Result<TypedMessage<'_, T>, RdkafkaExtError> {
+        loop {
+            let message = self.consumer.recv().await?;
+            self.forget_revoked();
+            let partition = AssignedPartition {
+                topic: message.message.topic().to_string(),
+                partition: message.partition(),
+            };
+            let offset = message.offset();
+
+            let seeking = {
+                let positioned = self
+                    .positioned
+                    .lock()
+                    .unwrap_or_else(|err| err.into_inner());
+                match positioned.get(&partition) {
+                    Some(Positioned::Done) => return Ok(message),
+                    Some(Positioned::Seeking { first, last }) => Some((*first, *last, false)),
+                    None => None,
+                }
+            };
+            let (first, last, sought) = match seeking {
+                Some(range) => range,
+                None => {
+                    let stored = self
+                        .store
+                        .load(&partition)
+                        .await
+                        .map_err(RdkafkaExtError::OffsetStore)?;
+                    match stored {
+                        Some(next) if next != offset => {
+                            self.consumer.inner.seek(
+                                &partition.topic,
+                                partition.partition,
+                                Offset::Offset(next),
+                                Duration::ZERO,
+                            )?;
+                            // Seeking back, everything from the stored offset up to this
+                            // message is fetched again; messages after it were fetched
+                            // before the seek. Seeking ahead, anything before it was.
+                            match next < offset {
+                                true => (next, offset, true),
+                                false => (next, i64::MAX, true),
+                            }
+                        }
+                        _ => (offset, offset, false),
+                    }
+                }
+            };
+
+            // The message that prompted a seek is fetched again after it, if it's wanted.
+            let done = !sought && (first..=last).contains(&offset);
+            self.positioned
+                .lock()
+                .unwrap_or_else(|err| err.into_inner())
+                .insert(
+                    partition,
+                    match done {
+                        true => Positioned::Done,
+                        false => Positioned::Seeking { first, last },
+                    },
+                );
+            if done {
+                return Ok(message);
+            }
+        }
+    }
+    /// Stores the offset after `message`, so it isn't received again after a restart or
+    /// rebalance.
+    pub async fn save(&self, message: &TypedMessage<'_, T>) -> Result<(), RdkafkaExtError> {
+        let partition = AssignedPartition {
+            topic: message.message.topic().to_string(),
+            partition: message.partition(),
+        };
+        self.store
+            .save(&partition, message.offset() + 1)
+            .await
+            .map_err(RdkafkaExtError::OffsetStore)
+    }
+    /// Receives messages forever, passing each one to `handler` and [`save`](Self::save)-ing
+    /// its offset once the handler succeeds. Failures go through the consumer's
+    /// [`PoisonPolicy`](crate::PoisonPolicy), as in [`TypedConsumer::process_each`].
+    pub async fn process_each<F, E>(&self, mut handler: F) -> Result<(), RdkafkaExtError>
+    where
+        F: AsyncFnMut(&TypedMessage<'_, T>) -> Result<(), E>,
+        E: Into<Box<dyn Error + Send + Sync>>,
+    {
+        loop {
+            let message = self.recv().await?;
+            self.consumer
+                .poison_policy
+                .attempt(&message.message, async || handler(&message).await)
+                .await?;
+            self.save(&message).await?;
+        }
+    }
+
+    /// Revoked partitions are positioned from the store again if they come back.
+    fn forget_revoked(&self) {
+        let mut rebalances = self
+            .rebalances
+            .lock()
+            .unwrap_or_else(|err| err.into_inner());
+        let mut positioned = self
+            .positioned
+            .lock()
+            .unwrap_or_else(|err| err.into_inner());
+        while let Ok(event) = rebalances.try_recv() {
+            if let RebalanceEvent::Revoked(partitions) = event {
+                for partition in partitions {
+                    positioned.remove(&partition);
+                }
+            }
+        }
+    }
+}
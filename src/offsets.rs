@@ -0,0 +1,146 @@
+//! Where a [`TypedConsumer`] is in each of its partitions, for progress reports and lag checks,
+//! and for saving and restoring it during migrations or to replay an incident.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rdkafka::{
+    consumer::{CommitMode, Consumer, ConsumerContext},
+    util::Timeout,
+    Offset, TopicPartitionList,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{codec::CodecError, error::RdkafkaExtError, Topic, TypedConsumer};
+
+/// A partition assigned to a consumer.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct AssignedPartition {
+    pub topic: String,
+    pub partition: i32,
+}
+impl AssignedPartition {
+    pub(crate) fn from_list(list: &TopicPartitionList) -> Vec<Self> {
+        list.elements()
+            .iter()
+            .map(|element| AssignedPartition {
+                topic: element.topic().to_string(),
+                partition: element.partition(),
+            })
+            .collect()
+    }
+}
+
+/// An offset in a partition: the next one to be read, as Kafka counts them.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct PartitionOffset {
+    pub topic: String,
+    pub partition: i32,
+    /// `None` if there isn't one yet, e.g. nothing has been read or committed.
+    pub offset: Option<i64>,
+}
+impl PartitionOffset {
+    pub(crate) fn from_list(list: &TopicPartitionList) -> Vec<Self> {
+        list.elements()
+            .iter()
+            .map(|element| PartitionOffset {
+                topic: element.topic().to_string(),
+                partition: element.partition(),
+                offset: match element.offset() {
+                    Offset::Offset(offset) => Some(offset),
+                    _ => None,
+                },
+            })
+            .collect()
+    }
+}
+
+/// A consumer's offsets at one point in time, from [`TypedConsumer::export_offsets`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OffsetSnapshot {
+    /// When the snapshot was taken, in milliseconds since the Unix epoch.
+    pub exported_at: i64,
+    /// The group's committed offset for each assigned partition.
+    pub committed: Vec<PartitionOffset>,
+    /// How far the consumer had read each assigned partition, which may be ahead of what's
+    /// committed.
+    pub processed: Vec<PartitionOffset>,
+}
+impl OffsetSnapshot {
+    pub fn to_json(&self) -> Result<String, RdkafkaExtError> {
+        Ok(serde_json::to_string_pretty(self).map_err(CodecError::from)?)
+    }
+    pub fn from_json(json: &str) -> Result<Self, RdkafkaExtError> {
+        Ok(serde_json::from_str(json).map_err(CodecError::from)?)
+    }
+}
+
+impl<T: Topic, C: ConsumerContext + 'static> TypedConsumer<T, C> {
+    pub fn assignment(&self) -> Result<Vec<AssignedPartition>, RdkafkaExtError> {
+        Ok(AssignedPartition::from_list(&self.inner.assignment()?))
+    }
+    /// The next offset to be read from each assigned partition.
+    pub fn position(&self) -> Result<Vec<PartitionOffset>, RdkafkaExtError> {
+        Ok(PartitionOffset::from_list(&self.inner.position()?))
+    }
+    /// The group's committed offset for each assigned partition, fetched from the broker.
+    /// Blocks until it answers or `timeout` runs out.
+    pub fn committed(
+        &self,
+        timeout: impl Into<Timeout>,
+    ) -> Result<Vec<PartitionOffset>, RdkafkaExtError> {
+        Ok(PartitionOffset::from_list(&self.inner.committed(timeout)?))
+    }
+    /// Takes a snapshot of the committed and processed offsets of every assigned partition,
+    /// e.g. to save as JSON with [`OffsetSnapshot::to_json`]. Blocks until the broker answers
+    /// or `timeout` runs out.
+    pub fn export_offsets(
+        &self,
+        timeout: impl Into<Timeout>,
+    ) -> Result<OffsetSnapshot, RdkafkaExtError> {
+        let exported_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_millis() as i64);
+        Ok(OffsetSnapshot {
+            exported_at,
+            committed: self.committed(timeout)?,
+            processed: self.position()?,
+        })
+    }
+    /// Commits `offsets` for the group, e.g. the `committed` or `processed` offsets of an
+    /// [`OffsetSnapshot`], and seeks the partitions among them that are assigned, so reading
+    /// carries on from there. Partitions without an offset are left alone. Blocks until the
+    /// commit is done, waiting up to `timeout` for each seek.
+    pub fn import_offsets(
+        &self,
+        offsets: &[PartitionOffset],
+        timeout: impl Into<Timeout>,
+    ) -> Result<(), RdkafkaExtError> {
+        let timeout = timeout.into();
+        let mut list = TopicPartitionList::new();
+        for offset in offsets {
+            if let Some(next) = offset.offset {
+                list.add_partition_offset(&offset.topic, offset.partition, Offset::Offset(next))?;
+            }
+        }
+        if list.count() == 0 {
+            return Ok(());
+        }
+        self.inner.commit(&list, CommitMode::Sync)?;
+
+        let assignment = self.inner.assignment()?;
+        for element in list.elements() {
+            if assignment
+                .find_partition(element.topic(), element.partition())
+                .is_some()
+            {
+                self.inner.seek(
+                    element.topic(),
+                    element.partition(),
+                    element.offset(),
+                    timeout,
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
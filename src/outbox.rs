@@ -0,0 +1,172 @@
+//! The transactional outbox pattern: records are written to the application's own store in the
+//! same transaction as the change they describe, then relayed to Kafka by an [`OutboxRelay`].
+//!
+//! Records are only marked sent once the broker has acknowledged them, so a crash between the
+//! two sends them again. Consumers should expect the odd duplicate.
+
+use std::{error::Error, future::Future, time::Duration};
+
+use futures::future;
+use rdkafka::{client::DefaultClientContext, ClientContext};
+
+use crate::{
+    error::{ErrorClass, RdkafkaExtError, SerializeError},
+    headers::HeaderMap,
+    EncodedRecord, RawRecord, Topic, TypedProducer,
+};
+
+/// A serialized record waiting in an outbox, along with whatever identifies it there.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutboxRecord<Id> {
+    pub id: Id,
+    pub topic: String,
+    pub key: Option<Vec<u8>>,
+    pub payload: Vec<u8>,
+    pub headers: HeaderMap,
+}
+impl<Id> OutboxRecord<Id> {
+    /// Serializes a record for `topic` the same way [`TypedProducer::send`] would, ready to be
+    /// written to the outbox.
+    pub fn encode<T: Topic>(
+        id: Id,
+        topic: &T,
+        payload: &T::Payload,
+        key: Option<&T::Key>,
+    ) -> Result<Self, SerializeError> {
+        let encoded = EncodedRecord::new(topic, payload, key)?;
+        let mut headers = HeaderMap::new();
+        for (name, value) in encoded.payload_headers {
+            headers.insert_raw(name, Some(value));
+        }
+
+        Ok(Self {
+            id,
+            topic: encoded.topic,
+            key: encoded.key,
+            payload: encoded.payload,
+            headers,
+        })
+    }
+}
+
+/// Somewhere records wait to be relayed, such as a database table.
+pub trait OutboxSource: Send + Sync {
+    type Id: Send + Sync;
+
+    /// Up to `limit` records not yet marked sent, oldest first.
+    fn fetch_pending(
+        &self,
+        limit: usize,
+    ) -> impl Future<Output = Result<Vec<OutboxRecord<Self::Id>>, Box<dyn Error + Send + Sync>>> + Send;
+    /// Marks records as sent, so they aren't fetched again.
+    fn mark_sent(
+        &self,
+        ids: Vec<Self::Id>,
+    ) -> impl Future<Output = Result<(), Box<dyn Error + Send + Sync>>> + Send;
+}
+
+/// Relays records from an [`OutboxSource`] to Kafka.
+pub struct OutboxRelay<S, C: ClientContext + 'static = DefaultClientContext> {
+    source: S,
+    producer: TypedProducer<C>,
+    batch_size: usize,
+    poll_interval: Duration,
+}
+impl<S: OutboxSource, C: ClientContext + 'static> OutboxRelay<S, C> {
+    /// Relays up to 100 records at a time, checking for more every second once the outbox is
+    /// empty.
+    pub fn new(source: S, producer: TypedProducer<C>) -> Self {
+        Self {
+            source,
+            producer,
+            batch_size: 100,
+            poll_interval: Duration::from_secs(1),
+        }
+    }
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+    /// How long to wait before checking an empty outbox again.
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+    pub fn source(&self) -> &S {
+        &self.source
+    }
+    /// Relays one batch of pending records, returning how many were fetched.
+    ///
+    /// Every record the broker acknowledges is marked sent, even if others in the batch failed.
+    /// Failed records are retried with the next batch, so they may land after records that were
+    /// behind them in the outbox. Sends wait for room in the producer queue for up to the
+    /// producer's [`default_timeout`](TypedProducer::default_timeout), so batches bigger than
+    /// the queue still go through.
+    pub async fn relay_once(&self) -> Result<usize, RdkafkaExtError> {
+        let records = self
+            .source
+            .fetch_pending(self.batch_size)
+            .await
+            .map_err(RdkafkaExtError::Outbox)?;
+        let fetched = records.len();
+
+        let timeout = self.producer.default_timeout();
+        let deliveries = records.iter().map(|record| {
+            let mut raw =
+                RawRecord::new(&record.topic, record.key.as_deref(), Some(&record.payload));
+            if !record.headers.is_empty() {
+                raw = raw.headers(record.headers.to_owned_headers());
+            }
+            self.producer.send_raw(raw, timeout)
+        });
+        let results = future::join_all(deliveries).await;
+        let mut sent = Vec::new();
+        let mut failure = None;
+        for (record, result) in records.into_iter().zip(results) {
+            match result {
+                Ok(_) => sent.push(record.id),
+                Err(err) => failure = failure.or(Some(err)),
+            }
+        }
+
+        if !sent.is_empty() {
+            self.source
+                .mark_sent(sent)
+                .await
+                .map_err(RdkafkaExtError::Outbox)?;
+        }
+        match failure {
+            Some(err) => Err(err.into()),
+            None => Ok(fetched),
+        }
+    }
+    /// Relays batches forever, waiting the poll interval whenever the outbox is empty.
+    ///
+    /// Retryable failures, such as a broker or the outbox being briefly unreachable, are passed
+    /// to `on_error` and retried with exponential backoff; only other errors are returned.
+    pub async fn run(
+        &self,
+        mut on_error: impl FnMut(&RdkafkaExtError),
+    ) -> Result<(), RdkafkaExtError> {
+        const INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+        const MAX_BACKOFF: Duration = Duration::from_secs(10);
+
+        let mut backoff = INITIAL_BACKOFF;
+        loop {
+            match self.relay_once().await {
+                Ok(fetched) => {
+                    backoff = INITIAL_BACKOFF;
+                    if fetched == 0 {
+                        tokio::time::sleep(self.poll_interval).await;
+                    }
+                }
+                Err(err) if err.class() == ErrorClass::Retryable => {
+                    on_error(&err);
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(MAX_BACKOFF);
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
@@ -0,0 +1,305 @@
+//! Handling several messages from a [`TypedConsumer`] at once while still committing safely:
+//! a partition's offset only moves past a message once it and every message before it in the
+//! partition are done.
+
+use std::{
+    collections::{BTreeSet, HashMap, VecDeque},
+    error::Error,
+    future::Future,
+    pin::pin,
+    task::Poll,
+    time::Duration,
+};
+
+use futures::{
+    future::{self, LocalBoxFuture},
+    stream::FuturesUnordered,
+    StreamExt,
+};
+use rdkafka::{
+    consumer::{CommitMode, Consumer, ConsumerContext},
+    Message, Offset, TopicPartitionList,
+};
+
+use crate::{error::RdkafkaExtError, Topic, TypedConsumer, TypedMessage};
+
+/// The messages of one partition that have been received but aren't done yet.
+#[derive(Default)]
+struct PartitionProgress {
+    pending: BTreeSet<i64>,
+    highest_done: Option<i64>,
+}
+impl PartitionProgress {
+    /// The offset to commit: just before the oldest message not done yet, or past the newest
+    /// one done if they're all done.
+    fn committable(&self) -> Option<i64> {
+        match self.pending.first() {
+            Some(&oldest) => self.highest_done.map(|_| oldest),
+            None => self.highest_done.map(|done| done + 1),
+        }
+    }
+}
+
+/// Tracks which received messages are done, per partition.
+#[derive(Default)]
+struct InFlightOffsets {
+    partitions: HashMap<(String, i32), PartitionProgress>,
+}
+impl InFlightOffsets {
+    fn start(&mut self, topic: &str, partition: i32, offset: i64) {
+        let progress = self
+            .partitions
+            .entry((topic.to_string(), partition))
+            .or_default();
+        progress.pending.insert(offset);
+    }
+    /// Marks a message done, returning the partition's new offset to commit if it moved.
+    fn finish(&mut self, topic: &str, partition: i32, offset: i64) -> Option<i64> {
+        let progress = self.partitions.get_mut(&(topic.to_string(), partition))?;
+        let before = progress.committable();
+        progress.pending.remove(&offset);
+        progress.highest_done = progress.highest_done.max(Some(offset));
+        let after = progress.committable();
+        (after != before).then_some(after).flatten()
+    }
+}
+
+type Handled<'a, T> = (TypedMessage<'a, T>, Result<(), RdkafkaExtError>);
+
+enum Event<'a, T> {
+    Received(Result<TypedMessage<'a, T>, RdkafkaExtError>),
+    Handled(Handled<'a, T>),
+    Shutdown,
+}
+
+/// The state of a parallel run: messages being handled, messages waiting for an earlier one
+/// with the same key, and each partition's progress.
+struct ParallelRun<'a, T, C: ConsumerContext + 'static, F> {
+    consumer: &'a TypedConsumer<T, C>,
+    handler: &'a F,
+    by_key: bool,
+    in_flight: FuturesUnordered<LocalBoxFuture<'a, Handled<'a, T>>>,
+    /// Waiting messages by topic and key. A key has an entry while one of its messages is in
+    /// flight.
+    queued: HashMap<(String, Vec<u8>), VecDeque<TypedMessage<'a, T>>>,
+    held: usize,
+    offsets: InFlightOffsets,
+    /// The latest offset committed for each partition, to commit synchronously on shutdown.
+    committed: HashMap<(String, i32), i64>,
+}
+impl<'a, T: Topic, C: ConsumerContext + 'static, F, E> ParallelRun<'a, T, C, F>
+where
+    F: AsyncFn(&TypedMessage<'_, T>) -> Result<(), E>,
+    E: Into<Box<dyn Error + Send + Sync>>,
+{
+    fn handle(&self, message: TypedMessage<'a, T>) -> LocalBoxFuture<'a, Handled<'a, T>> {
+        let (consumer, handler) = (self.consumer, self.handler);
+        Box::pin(async move {
+            let result = consumer
+                .poison_policy
+                .attempt(&message.message, async || handler(&message).await)
+                .await;
+            (message, result)
+        })
+    }
+    fn received(&mut self, message: TypedMessage<'a, T>) {
+        self.held += 1;
+        let topic = message.message.topic();
+        self.offsets
+            .start(topic, message.partition(), message.offset());
+        let lane = match (self.by_key, message.key()) {
+            (true, Some(key)) => (topic.to_string(), key.to_vec()),
+            _ => return self.in_flight.push(self.handle(message)),
+        };
+        match self.queued.get_mut(&lane) {
+            Some(waiting) => waiting.push_back(message),
+            None => {
+                self.queued.insert(lane, VecDeque::new());
+                self.in_flight.push(self.handle(message));
+            }
+        }
+    }
+    /// Commits as far as the message's partition is done, and starts the next message with
+    /// the same key.
+    fn handled(&mut self, (message, result): Handled<'a, T>) -> Result<(), RdkafkaExtError> {
+        result?;
+        self.held -= 1;
+        let topic = message.message.topic();
+        let partition = message.partition();
+        if let Some(next) = self.offsets.finish(topic, partition, message.offset()) {
+            let mut list = TopicPartitionList::new();
+            list.add_partition_offset(topic, partition, Offset::Offset(next))?;
+            self.consumer.inner.commit(&list, CommitMode::Async)?;
+            self.committed.insert((topic.to_string(), partition), next);
+        }
+
+        let Some(key) = message.key().filter(|_| self.by_key) else {
+            return Ok(());
+        };
+        let lane = (topic.to_string(), key.to_vec());
+        match self.queued.get_mut(&lane).and_then(VecDeque::pop_front) {
+            Some(next) => self.in_flight.push(self.handle(next)),
+            None => {
+                self.queued.remove(&lane);
+            }
+        }
+        Ok(())
+    }
+    /// Receives and handles messages up to `max_in_flight` at a time, until something fails
+    /// or `shutdown` completes.
+    async fn process(
+        &mut self,
+        max_in_flight: usize,
+        shutdown: impl Future<Output = ()>,
+    ) -> Result<(), RdkafkaExtError> {
+        let max_in_flight = max_in_flight.max(1);
+        let consumer = self.consumer;
+        let mut shutdown = pin!(shutdown);
+        loop {
+            let mut received = pin!(consumer.recv());
+            let receiving = self.held < max_in_flight;
+            let event = future::poll_fn(|cx| {
+                if shutdown.as_mut().poll(cx).is_ready() {
+                    return Poll::Ready(Event::Shutdown);
+                }
+                if let Poll::Ready(Some(handled)) = self.in_flight.poll_next_unpin(cx) {
+                    return Poll::Ready(Event::Handled(handled));
+                }
+                match receiving {
+                    true => received.as_mut().poll(cx).map(Event::Received),
+                    false => Poll::Pending,
+                }
+            })
+            .await;
+
+            match event {
+                Event::Received(message) => self.received(message?),
+                Event::Handled(handled) => self.handled(handled)?,
+                Event::Shutdown => return Ok(()),
+            }
+        }
+    }
+    /// Lets messages already received finish for up to `timeout`, then commits how far each
+    /// partition got and leaves the group.
+    async fn drain(&mut self, timeout: Duration) -> Result<(), RdkafkaExtError> {
+        let consumer = &self.consumer.inner;
+        consumer.pause(&consumer.assignment()?)?;
+        let drained = tokio::time::timeout(timeout, async {
+            while let Some(handled) = self.in_flight.next().await {
+                self.handled(handled)?;
+            }
+            Ok(())
+        })
+        .await;
+
+        let mut list = TopicPartitionList::new();
+        for ((topic, partition), next) in &self.committed {
+            list.add_partition_offset(topic, *partition, Offset::Offset(*next))?;
+        }
+        if list.count() > 0 {
+            consumer.commit(&list, CommitMode::Sync)?;
+        }
+        consumer.unsubscribe();
+        drained.unwrap_or(Ok(()))
+    }
+}
+
+impl<T: Topic, C: ConsumerContext + 'static> TypedConsumer<T, C> {
+    /// Like [`process_each`](Self::process_each), but handles up to `max_in_flight` messages
+    /// at once. Messages with the same key are still handled one at a time, in the order they
+    /// arrived; messages without a key aren't ordered at all.
+    ///
+    /// Offsets are committed asynchronously as soon as every earlier message in the partition
+    /// is done, so a crash reprocesses only messages that weren't. The first error the poison
+    /// policy doesn't absorb is returned, abandoning messages still in flight. Handlers all run
+    /// on the calling task, so they should await rather than block.
+    pub async fn process_parallel_by_key<F, E>(
+        &self,
+        max_in_flight: usize,
+        handler: F,
+    ) -> Result<(), RdkafkaExtError>
+    where
+        F: AsyncFn(&TypedMessage<'_, T>) -> Result<(), E>,
+        E: Into<Box<dyn Error + Send + Sync>>,
+    {
+        let mut run = self.parallel_run(true, &handler);
+        run.process(max_in_flight, future::pending()).await
+    }
+    /// Like [`process_parallel_by_key`](Self::process_parallel_by_key), but with no ordering
+    /// at all: up to `max_in_flight` messages are handled at once, whatever their keys.
+    pub async fn run_concurrent<F, E>(
+        &self,
+        max_in_flight: usize,
+        handler: F,
+    ) -> Result<(), RdkafkaExtError>
+    where
+        F: AsyncFn(&TypedMessage<'_, T>) -> Result<(), E>,
+        E: Into<Box<dyn Error + Send + Sync>>,
+    {
+        let mut run = self.parallel_run(false, &handler);
+        run.process(max_in_flight, future::pending()).await
+    }
+    /// Like [`run_concurrent`](Self::run_concurrent), but shuts down gracefully once `shutdown`
+    /// completes, e.g. on a signal or a cancellation token: fetching stops, messages already
+    /// received get up to `drain_timeout` to finish, the offsets of those that did are
+    /// committed, and the consumer leaves its group. Returns `Ok` after a clean shutdown.
+    pub async fn run_until<S, F, E>(
+        &self,
+        shutdown: S,
+        max_in_flight: usize,
+        drain_timeout: Duration,
+        handler: F,
+    ) -> Result<(), RdkafkaExtError>
+    where
+        S: Future<Output = ()>,
+        F: AsyncFn(&TypedMessage<'_, T>) -> Result<(), E>,
+        E: Into<Box<dyn Error + Send + Sync>>,
+    {
+        let mut run = self.parallel_run(false, &handler);
+        run.process(max_in_flight, shutdown).await?;
+        run.drain(drain_timeout).await
+    }
+
+    fn parallel_run<'a, F>(&'a self, by_key: bool, handler: &'a F) -> ParallelRun<'a, T, C, F> {
+        ParallelRun {
+            consumer: self,
+            handler,
+            by_key,
+            in_flight: FuturesUnordered::new(),
+            queued: HashMap::new(),
+            held: 0,
+            offsets: InFlightOffsets::default(),
+            committed: HashMap::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commits_up_to_the_oldest_pending_message() {
+        let mut offsets = InFlightOffsets::default();
+        for offset in 10..13 {
+            offsets.start("t", 0, offset);
+        }
+
+        assert_eq!(offsets.finish("t", 0, 11), Some(10));
+        assert_eq!(offsets.finish("t", 0, 12), None);
+        assert_eq!(offsets.finish("t", 0, 10), Some(13));
+        assert_eq!(offsets.finish("t", 1, 10), None);
+    }
+
+    #[test]
+    fn has_nothing_to_commit_until_a_message_is_done() {
+        let mut progress = PartitionProgress::default();
+        assert_eq!(progress.committable(), None);
+
+        progress.pending.insert(5);
+        assert_eq!(progress.committable(), None);
+
+        progress.highest_done = Some(6);
+        assert_eq!(progress.committable(), Some(5));
+    }
+}
@@ -0,0 +1,207 @@
+//! Rust-side partitioners, for placement librdkafka's built-in
+//! [`Partitioner`](crate::builders::types::Partitioner)s can't express.
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex, MutexGuard},
+    thread,
+    time::{Duration, Instant},
+};
+
+use rdkafka::{
+    producer::{FutureProducer, Producer},
+    ClientContext,
+};
+
+/// How long a topic's partition count is trusted before it's looked up again, so partitions
+/// added to a topic are picked up.
+const PARTITION_COUNT_TTL: Duration = Duration::from_secs(300);
+const METADATA_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long to wait before looking up a partition count again after a lookup failed, doubling
+/// with every failure in a row.
+const INITIAL_LOOKUP_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_LOOKUP_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Picks the partition for records sent without an explicit one.
+///
+/// Implemented for closures taking the topic name, the encoded key and the topic's partition
+/// count. Results outside `0..partition_count` wrap around.
+pub trait KeyPartitioner: Send + Sync {
+    fn partition(&self, topic: &str, key: Option<&[u8]>, partition_count: i32) -> i32;
+}
+impl<F> KeyPartitioner for F
+where
+    F: Fn(&str, Option<&[u8]>, i32) -> i32 + Send + Sync,
+{
+    fn partition(&self, topic: &str, key: Option<&[u8]>, partition_count: i32) -> i32 {
+        self(topic, key, partition_count)
+    }
+}
+
+/// What's known about one topic's partition count.
+struct PartitionCount {
+    /// The latest count looked up, kept through failed lookups.
+    count: Option<i32>,
+    next_lookup: Instant,
+    backoff: Duration,
+}
+
+#[derive(Default)]
+struct PartitionCounts {
+    topics: HashMap<String, PartitionCount>,
+    /// Topics with a lookup running.
+    looking_up: HashSet<String>,
+}
+
+/// A [`KeyPartitioner`] along with the partition counts it has looked up.
+pub(crate) struct PartitionerState {
+    partitioner: Box<dyn KeyPartitioner>,
+    counts: Arc<Mutex<PartitionCounts>>,
+}
+impl PartitionerState {
+    pub(crate) fn new(partitioner: impl KeyPartitioner + 'static) -> Self {
+        Self {
+            partitioner: Box::new(partitioner),
+            counts: Arc::default(),
+        }
+    }
+    /// The partition for a record, or `None` to leave it to librdkafka when the topic's
+    /// partition count isn't known.
+    ///
+    /// Partition counts are looked up on a background thread, so sends never wait on a
+    /// metadata request: the first records to a topic are left to librdkafka while the lookup
+    /// runs, and stale counts are used while they're refreshed every few minutes. Failed
+    /// lookups are retried with exponential backoff.
+    pub(crate) fn partition<C: ClientContext + 'static>(
+        &self,
+        producer: &FutureProducer<C>,
+        topic: &str,
+        key: Option<&[u8]>,
+    ) -> Option<i32> {
+        let count = self.partition_count(producer, topic)?;
+        Some(
+            self.partitioner
+                .partition(topic, key, count)
+                .rem_euclid(count),
+        )
+    }
+    fn partition_count<C: ClientContext + 'static>(
+        &self,
+        producer: &FutureProducer<C>,
+        topic: &str,
+    ) -> Option<i32> {
+        let mut counts = lock(&self.counts);
+        let (count, due) = match counts.topics.get(topic) {
+            Some(known) => (known.count, known.next_lookup <= Instant::now()),
+            None => (None, true),
+        };
+        if due && counts.looking_up.insert(topic.to_string()) {
+            drop(counts);
+            self.look_up(producer.clone(), topic.to_string());
+        }
+        count
+    }
+    /// Looks up a topic's partition count on a thread of its own.
+    fn look_up<C: ClientContext + 'static>(&self, producer: FutureProducer<C>, topic: String) {
+        let counts = self.counts.clone();
+        let spawned = thread::Builder::new()
+            .name("rdkafka-ext-partitions".into())
+            .spawn({
+                let counts = counts.clone();
+                let topic = topic.clone();
+                move || {
+                    let count = fetch_partition_count(&producer, &topic);
+                    record_lookup(&counts, topic, count);
+                }
+            });
+        if spawned.is_err() {
+            record_lookup(&counts, topic, None);
+        }
+    }
+}
+
+fn fetch_partition_count<C: ClientContext + 'static>(
+    producer: &FutureProducer<C>,
+    topic: &str,
+) -> Option<i32> {
+    let metadata = producer
+        .client()
+        .fetch_metadata(Some(topic), METADATA_TIMEOUT)
+        .ok()?;
+    metadata
+        .topics()
+        .iter()
+        .find(|metadata| metadata.name() == topic && metadata.error().is_none())
+        .map(|metadata| metadata.partitions().len() as i32)
+        .filter(|count| *count > 0)
+}
+
+fn record_lookup(counts: &Mutex<PartitionCounts>, topic: String, count: Option<i32>) {
+    let mut counts = lock(counts);
+    counts.looking_up.remove(&topic);
+    let known = counts
+        .topics
+        .entry(topic)
+        .or_insert_with(|| PartitionCount {
+            count: None,
+            next_lookup: Instant::now(),
+            backoff: INITIAL_LOOKUP_BACKOFF,
+        });
+    match count {
+        Some(count) => {
+            known.count = Some(count);
+            known.next_lookup = Instant::now() + PARTITION_COUNT_TTL;
+            known.backoff = INITIAL_LOOKUP_BACKOFF;
+        }
+        None => {
+            known.next_lookup = Instant::now() + known.backoff;
+            known.backoff = (known.backoff * 2).min(MAX_LOOKUP_BACKOFF);
+        }
+    }
+}
+
+fn lock(counts: &Mutex<PartitionCounts>) -> MutexGuard<'_, PartitionCounts> {
+    counts.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use rdkafka::ClientConfig;
+
+    use super::*;
+
+    fn producer() -> FutureProducer {
+        ClientConfig::new()
+            .set("bootstrap.servers", "localhost:9092")
+            .create()
+            .unwrap()
+    }
+
+    #[test]
+    fn wraps_partitions_into_range() {
+        let state = PartitionerState::new(|_: &str, key: Option<&[u8]>, _: i32| {
+            i32::from(key.map_or(0, |key| key[0])) - 2
+        });
+        record_lookup(&state.counts, "orders".to_string(), Some(3));
+        let producer = producer();
+
+        let partitions: Vec<_> = [0, 1, 2, 3, 4, 5]
+            .map(|key| state.partition(&producer, "orders", Some(&[key])))
+            .into();
+        assert_eq!(partitions, [1, 2, 0, 1, 2, 0].map(Some));
+    }
+
+    #[test]
+    fn backs_off_failed_lookups_and_keeps_the_last_count() {
+        let counts = Mutex::default();
+        record_lookup(&counts, "orders".to_string(), Some(3));
+        record_lookup(&counts, "orders".to_string(), None);
+        record_lookup(&counts, "orders".to_string(), None);
+
+        let counts = lock(&counts);
+        let known = &counts.topics["orders"];
+        assert_eq!(known.count, Some(3));
+        assert_eq!(known.backoff, INITIAL_LOOKUP_BACKOFF * 4);
+        assert!(counts.looking_up.is_empty());
+    }
+}
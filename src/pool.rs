@@ -0,0 +1,33 @@
+use std::sync::Mutex;
+
+/// How many idle buffers are kept around for reuse.
+const MAX_IDLE_BUFFERS: usize = 64;
+/// Buffers that grew past this are dropped instead of pooled, so one huge payload doesn't pin
+/// its memory forever.
+const MAX_BUFFER_CAPACITY: usize = 1024 * 1024;
+
+/// A pool of serialization buffers shared by clones of a producer.
+#[derive(Default)]
+pub(crate) struct BufferPool {
+    idle: Mutex<Vec<Vec<u8>>>,
+}
+impl BufferPool {
+    pub(crate) fn take(&self) -> Vec<u8> {
+        self.idle
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .pop()
+            .unwrap_or_default()
+    }
+    pub(crate) fn give(&self, mut buffer: Vec<u8>) {
+        if buffer.capacity() > MAX_BUFFER_CAPACITY {
+            return;
+        }
+        buffer.clear();
+
+        let mut idle = self.idle.lock().unwrap_or_else(|err| err.into_inner());
+        if idle.len() < MAX_IDLE_BUFFERS {
+            idle.push(buffer);
+        }
+    }
+}
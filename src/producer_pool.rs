@@ -0,0 +1,117 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
+
+use rdkafka::{util::Timeout, ClientConfig};
+
+use crate::{
+    encode_record_key,
+    error::{RdkafkaExtError, SendError},
+    Delivery, Topic, TypedProducer,
+};
+
+/// How a [`TypedProducerPool`] picks a producer for each send.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PoolStrategy {
+    /// Cycle through the producers.
+    #[default]
+    RoundRobin,
+    /// Always use the same producer for the same key, so records with one key stay in order.
+    /// Keys come from [`Topic::partition_key`] when none is given. Records without a key are
+    /// sent round robin.
+    KeyHash,
+}
+
+/// Spreads sends across several producers, each with its own librdkafka instance and
+/// connections, for when one producer can't keep up.
+pub struct TypedProducerPool {
+    producers: Vec<TypedProducer>,
+    strategy: PoolStrategy,
+    next: AtomicUsize,
+}
+impl TypedProducerPool {
+    /// Creates `size` producers from `config`. A size of zero is treated as one.
+    pub fn new(config: ClientConfig, size: usize) -> Result<Self, RdkafkaExtError> {
+        let producers = (0..size.max(1))
+            .map(|_| TypedProducer::new(config.clone()))
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            producers,
+            strategy: PoolStrategy::default(),
+            next: AtomicUsize::new(0),
+        })
+    }
+    pub fn with_strategy(mut self, strategy: PoolStrategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+    pub fn producers(&self) -> &[TypedProducer] {
+        &self.producers
+    }
+    /// The producer the pool's strategy picks for a record, for sends other than
+    /// [`send`](Self::send).
+    pub fn producer_for<T: Topic>(
+        &self,
+        topic: &T,
+        payload: &T::Payload,
+        key: Option<&T::Key>,
+    ) -> &TypedProducer {
+        let key = match self.strategy {
+            PoolStrategy::KeyHash => encode_record_key(topic, payload, key).ok().flatten(),
+            PoolStrategy::RoundRobin => None,
+        };
+        let key_hash = key.map(|key| {
+            let mut hasher = DefaultHasher::new();
+            key.hash(&mut hasher);
+            hasher.finish() as usize
+        });
+
+        let index = key_hash.unwrap_or_else(|| self.next.fetch_add(1, Ordering::Relaxed));
+        &self.producers[index % self.producers.len()]
+    }
+    /// Sends with the picked producer's [`default_timeout`](TypedProducer::default_timeout).
+    pub async fn send<T: Topic>(
+        &self,
+        topic: &T,
+        payload: &T::Payload,
+        key: Option<&T::Key>,
+    ) -> Result<Delivery, SendError> {
+        self.producer_for(topic, payload, key)
+            .send(topic, payload, key)
+            .await
+    }
+    /// Like [`send`](Self::send), but waits up to `timeout` instead of the default.
+    pub async fn send_with_timeout<T: Topic>(
+        &self,
+        topic: &T,
+        payload: &T::Payload,
+        key: Option<&T::Key>,
+        timeout: impl Into<Timeout>,
+    ) -> Result<Delivery, SendError> {
+        self.producer_for(topic, payload, key)
+            .send_with_timeout(topic, payload, key, timeout)
+            .await
+    }
+    /// Flushes every producer in turn, each for up to `timeout`.
+    pub fn flush(&self, timeout: impl Into<Timeout>) -> Result<(), RdkafkaExtError> {
+        let timeout = timeout.into();
+        self.producers
+            .iter()
+            .try_for_each(|producer| producer.flush(timeout))
+    }
+    /// Shuts every producer down as in [`TypedProducer::shutdown`], returning how many records
+    /// were still outstanding across all of them.
+    pub async fn shutdown(&self, timeout: Duration) -> usize {
+        let outstanding = futures::future::join_all(
+            self.producers
+                .iter()
+                .map(|producer| producer.shutdown(timeout)),
+        )
+        .await;
+        outstanding.into_iter().sum()
+    }
+}
@@ -0,0 +1,255 @@
+//! Following a [`TypedConsumer`]'s partition assignment as the group rebalances, e.g. to give
+//! each partition its own stream or to flush state at rebalance boundaries.
+
+use std::{
+    collections::VecDeque,
+    pin::Pin,
+    sync::Weak,
+    task::{Context, Poll},
+};
+
+use futures::{
+    channel::mpsc::{self, UnboundedReceiver},
+    stream, Stream, StreamExt,
+};
+use rdkafka::{
+    consumer::{
+        stream_consumer::StreamPartitionQueue, Consumer, ConsumerContext, DefaultConsumerContext,
+        MessageStream, Rebalance,
+    },
+    TopicPartitionList,
+};
+
+use crate::{
+    consumer_context::ConsumerExtContext, error::RdkafkaExtError, offsets::AssignedPartition,
+    Topic, TypedConsumer, TypedMessage,
+};
+
+/// The rebalance side of the consumer's context: telling listeners about assignment changes
+/// and splitting off partition queues.
+impl<C: ConsumerContext + 'static> ConsumerExtContext<C> {
+    pub(crate) fn notify(&self, event: RebalanceEvent) {
+        self.listeners
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .retain(|listener| listener.unbounded_send(event.clone()).is_ok());
+    }
+    pub(crate) fn split_queues(&self, rebalance: &Rebalance<'_>) {
+        let sender = self
+            .partition_queues
+            .lock()
+            .unwrap_or_else(|err| err.into_inner());
+        let Some(sender) = sender.as_ref() else {
+            return;
+        };
+
+        // Assigning partitions deactivates their old queues, so they're split off again here,
+        // before any of their messages can reach the consumer's own queue.
+        let change = match rebalance {
+            Rebalance::Assign(partitions) => SplitChange::Assigned(self.split(partitions)),
+            Rebalance::Revoke(partitions) => SplitChange::Revoked(
+                partitions
+                    .elements()
+                    .iter()
+                    .map(|element| (element.topic().to_string(), element.partition()))
+                    .collect(),
+            ),
+            Rebalance::Error(_) => return,
+        };
+        let _ = sender.unbounded_send(change);
+    }
+    pub(crate) fn split(&self, partitions: &TopicPartitionList) -> Vec<SplitQueue<C>> {
+        let Some(consumer) = self.consumer.get().and_then(Weak::upgrade) else {
+            return Vec::new();
+        };
+        partitions
+            .elements()
+            .iter()
+            .filter_map(|element| {
+                let queue = consumer.split_partition_queue(element.topic(), element.partition())?;
+                Some((element.topic().to_string(), element.partition(), queue))
+            })
+            .collect()
+    }
+}
+/// A change to a consumer's assignment, from [`TypedConsumer::rebalance_events`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RebalanceEvent {
+    /// Partitions were assigned, and the consumer has started fetching from them.
+    Assigned(Vec<AssignedPartition>),
+    /// Partitions are about to be taken away.
+    Revoked(Vec<AssignedPartition>),
+    /// The rebalance failed, for this reason.
+    Failed(String),
+}
+
+pub(crate) type SplitQueue<C> = (String, i32, StreamPartitionQueue<ConsumerExtContext<C>>);
+
+pub(crate) enum SplitChange<C: ConsumerContext + 'static> {
+    Assigned(Vec<SplitQueue<C>>),
+    Revoked(Vec<(String, i32)>),
+}
+
+/// The messages of a single assigned partition, from
+/// [`TypedConsumer::split_partition_queues`]. They go through the same retry tier hold-back,
+/// [`max_age`](TypedConsumer::with_max_age), interceptors and taps as the consumer's own.
+pub struct PartitionQueue<'a, T, C: ConsumerContext + 'static = DefaultConsumerContext> {
+    consumer: &'a TypedConsumer<T, C>,
+    queue: StreamPartitionQueue<ConsumerExtContext<C>>,
+    topic: T,
+    partition: i32,
+}
+impl<T: Topic, C: ConsumerContext + 'static> PartitionQueue<'_, T, C> {
+    pub fn partition(&self) -> i32 {
+        self.partition
+    }
+    pub async fn recv(&self) -> Result<TypedMessage<'_, T>, RdkafkaExtError> {
+        Ok(TypedMessage {
+            message: self.consumer.next_filtered(|| self.queue.recv()).await?,
+            topic: self.topic.clone(),
+        })
+    }
+    pub fn stream(&self) -> impl Stream<Item = Result<TypedMessage<'_, T>, RdkafkaExtError>> + '_ {
+        stream::unfold((), move |()| async move { Some((self.recv().await, ())) })
+    }
+}
+
+/// A change to a consumer's split-off partitions, from [`AssignmentChanges`].
+pub enum AssignmentChange<'a, T, C: ConsumerContext + 'static = DefaultConsumerContext> {
+    /// A partition was assigned, or reassigned, and now has this queue. Any older queue for the
+    /// same partition no longer receives messages.
+    Assigned(PartitionQueue<'a, T, C>),
+    /// A partition was revoked. Its queue won't receive any more messages and can be dropped.
+    Revoked { topic: String, partition: i32 },
+    /// A message fetched before its partition's queue was split off. Handle it before any
+    /// messages from that partition's queue.
+    Unsplit(TypedMessage<'a, T>),
+}
+
+/// Assignment changes for a consumer whose partitions have been split off with
+/// [`TypedConsumer::split_partition_queues`].
+///
+/// It has to be polled for as long as the partition queues are, since polling it is what
+/// serves rebalances and the consumer's other callbacks.
+pub struct AssignmentChanges<'a, T, C: ConsumerContext + 'static = DefaultConsumerContext> {
+    consumer: &'a TypedConsumer<T, C>,
+    messages: MessageStream<'a>,
+    changes: UnboundedReceiver<SplitChange<C>>,
+    pending: VecDeque<Result<AssignmentChange<'a, T, C>, RdkafkaExtError>>,
+}
+impl<'a, T: Topic, C: ConsumerContext + 'static> AssignmentChanges<'a, T, C> {
+    fn queue(
+        &self,
+        (topic, partition, queue): SplitQueue<C>,
+    ) -> Result<PartitionQueue<'a, T, C>, RdkafkaExtError> {
+        Ok(PartitionQueue {
+            consumer: self.consumer,
+            queue,
+            topic: self.consumer.topic_for(&topic)?,
+            partition,
+        })
+    }
+}
+impl<T, C: ConsumerContext + 'static> Unpin for AssignmentChanges<'_, T, C> {}
+impl<'a, T: Topic, C: ConsumerContext + 'static> Stream for AssignmentChanges<'a, T, C> {
+    type Item = Result<AssignmentChange<'a, T, C>, RdkafkaExtError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut polled_consumer = false;
+        loop {
+            if let Some(change) = self.pending.pop_front() {
+                return Poll::Ready(Some(change));
+            }
+            match self.changes.poll_next_unpin(cx) {
+                Poll::Ready(Some(SplitChange::Assigned(queues))) => {
+                    let changes: Vec<_> = queues
+                        .into_iter()
+                        .map(|queue| self.queue(queue).map(AssignmentChange::Assigned))
+                        .collect();
+                    self.pending.extend(changes);
+                    continue;
+                }
+                Poll::Ready(Some(SplitChange::Revoked(partitions))) => {
+                    let changes = partitions.into_iter().map(|(topic, partition)| {
+                        Ok(AssignmentChange::Revoked { topic, partition })
+                    });
+                    self.pending.extend(changes);
+                    continue;
+                }
+                _ if polled_consumer => return Poll::Pending,
+                _ => {}
+            }
+
+            // Polling the consumer's own queue is what runs the rebalance callback, so check for
+            // changes once more afterwards.
+            polled_consumer = true;
+            match self.messages.poll_next_unpin(cx) {
+                Poll::Ready(Some(Ok(message))) => match self.consumer.admit(&message) {
+                    Ok(true) => {
+                        let message = self.consumer.typed(message);
+                        return Poll::Ready(Some(message.map(AssignmentChange::Unsplit)));
+                    }
+                    Ok(false) => polled_consumer = false,
+                    Err(err) => return Poll::Ready(Some(Err(err.into()))),
+                },
+                Poll::Ready(Some(Err(err))) => return Poll::Ready(Some(Err(err.into()))),
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => {}
+            }
+        }
+    }
+}
+
+type SplitQueues<'a, T, C> = (Vec<PartitionQueue<'a, T, C>>, AssignmentChanges<'a, T, C>);
+
+impl<T: Topic, C: ConsumerContext + 'static> TypedConsumer<T, C> {
+    /// Reports every rebalance from now on, so state can be flushed or caches warmed as
+    /// partitions come and go. Rebalances only happen while the consumer is being polled.
+    ///
+    /// Revocations are sent before the partitions are taken away, but will usually be read
+    /// after, so offsets for them can no longer be committed by then. Commit as messages are
+    /// handled to keep what's reprocessed after a rebalance small.
+    pub fn rebalance_events(&self) -> UnboundedReceiver<RebalanceEvent> {
+        let (sender, receiver) = mpsc::unbounded();
+        self.inner
+            .context()
+            .listeners
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .push(sender);
+        receiver
+    }
+    /// Gives every assigned partition its own stream of messages, so partitions can be handled
+    /// in parallel while each stays in order. Returns the queues of the partitions assigned so
+    /// far, and a stream of the partitions assigned and revoked from then on.
+    ///
+    /// Don't use the consumer's own [`recv`](Self::recv) or [`stream`](Self::stream) after
+    /// splitting. Messages fetched before the split come through the assignment changes
+    /// instead.
+    ///
+    /// Fails if the current assignment can't be read, or includes a topic the typed topic
+    /// can't be rebuilt from.
+    pub fn split_partition_queues(&self) -> Result<SplitQueues<'_, T, C>, RdkafkaExtError> {
+        let (sender, receiver) = mpsc::unbounded();
+        let context = self.inner.context();
+        // Held while splitting the current assignment, so a rebalance can't slip in between.
+        let mut partition_queues = context
+            .partition_queues
+            .lock()
+            .unwrap_or_else(|err| err.into_inner());
+        *partition_queues = Some(sender);
+
+        let changes = AssignmentChanges {
+            consumer: self,
+            messages: self.inner.stream(),
+            changes: receiver,
+            pending: VecDeque::new(),
+        };
+        let queues = context
+            .split(&self.inner.assignment()?)
+            .into_iter()
+            .map(|queue| changes.queue(queue))
+            .collect::<Result<_, _>>()?;
+        Ok((queues, changes))
+    }
+}
@@ -0,0 +1,112 @@
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    time::Duration,
+};
+
+/// How many times to retry, and how long to wait between attempts.
+///
+/// Waits start at the base backoff and double after every attempt up to the max backoff. Jitter
+/// shortens each wait by a random fraction of up to `jitter`, so clients that failed together
+/// don't all retry together.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    max_attempts: usize,
+    base_backoff: Duration,
+    max_backoff: Duration,
+    jitter: f64,
+}
+impl RetryPolicy {
+    /// Five attempts, backing off from 100ms to 10s, with up to 20% jitter.
+    pub fn new() -> Self {
+        Self {
+            max_attempts: 5,
+            base_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(10),
+            jitter: 0.2,
+        }
+    }
+    /// The total number of attempts, including the first. Zero is treated as one.
+    pub fn max_attempts(mut self, max_attempts: usize) -> Self {
+        self.max_attempts = max_attempts;
+        self
+    }
+    pub fn backoff(mut self, base: Duration, max: Duration) -> Self {
+        self.base_backoff = base;
+        self.max_backoff = max;
+        self
+    }
+    /// The largest fraction of a wait to randomly cut off, from 0 to 1.
+    pub fn jitter(mut self, jitter: f64) -> Self {
+        self.jitter = jitter.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Whether another attempt should follow the `attempt`th one, counting from zero.
+    pub(crate) fn should_retry(&self, attempt: usize) -> bool {
+        attempt + 1 < self.max_attempts
+    }
+    /// How long to wait after the `attempt`th attempt, counting from zero.
+    pub(crate) fn backoff_after(&self, attempt: usize) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.try_into().unwrap_or(u32::MAX));
+        let backoff = self
+            .base_backoff
+            .saturating_mul(factor)
+            .min(self.max_backoff);
+
+        backoff.mul_f64(1.0 - self.jitter * random_fraction())
+    }
+}
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A random number in `[0, 1)`, good enough for jitter without pulling in a RNG crate.
+fn random_fraction() -> f64 {
+    let bits = RandomState::new().build_hasher().finish();
+    (bits >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn doubles_the_backoff_up_to_the_max() {
+        let policy = RetryPolicy::new()
+            .backoff(Duration::from_millis(100), Duration::from_secs(1))
+            .jitter(0.0);
+
+        let waits: Vec<_> = (0..6)
+            .map(|attempt| policy.backoff_after(attempt))
+            .collect();
+        assert_eq!(
+            waits,
+            [100, 200, 400, 800, 1000, 1000].map(Duration::from_millis)
+        );
+        assert_eq!(policy.backoff_after(usize::MAX), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn jitter_only_shortens_waits() {
+        let policy = RetryPolicy::new()
+            .backoff(Duration::from_millis(100), Duration::from_secs(10))
+            .jitter(0.5);
+
+        for _ in 0..100 {
+            let wait = policy.backoff_after(1);
+            assert!(wait > Duration::from_millis(100) && wait <= Duration::from_millis(200));
+        }
+    }
+
+    #[test]
+    fn stops_after_max_attempts() {
+        let policy = RetryPolicy::new().max_attempts(3);
+        assert!(policy.should_retry(0));
+        assert!(policy.should_retry(1));
+        assert!(!policy.should_retry(2));
+        assert!(!RetryPolicy::new().max_attempts(0).should_retry(0));
+    }
+}
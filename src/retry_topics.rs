@@ -0,0 +1,357 @@
+//! Tiered retry topics: messages that fail are republished to `<topic>.retry.5s`,
+//! `<topic>.retry.1m` and so on, each consumed again once its delay has passed, and finally to a
+//! dead-letter topic once every tier has been tried. Each republished message carries how often
+//! it has been retried in its [`RETRY_COUNT`] header, read back with
+//! [`TypedMessage::retry_count`](crate::TypedMessage::retry_count).
+//!
+//! Consume with [`TypedConsumer::new_with_retry_tiers`], which subscribes to the tier topics too
+//! and holds their messages back until they're due. The tier topics have to exist, like any
+//! other.
+
+use std::{
+    str,
+    sync::{Mutex, MutexGuard},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use rdkafka::{
+    consumer::{Consumer, ConsumerContext, DefaultConsumerContext, StreamConsumer},
+    error::KafkaError,
+    message::BorrowedMessage,
+    util::Timeout,
+    ClientConfig, Message, Offset, TopicPartitionList,
+};
+
+use crate::{
+    consumer_context::ConsumerExtContext, error::RdkafkaExtError, headers::HeaderMap, PoisonPolicy,
+    RawRecord, Subscription, Topic, TypedConsumer, TypedProducer,
+};
+
+/// How many times the message has been republished for retry.
+pub const RETRY_COUNT: &str = "x-retry-count";
+/// Why the message was last sent to a retry tier.
+pub const RETRY_REASON: &str = "x-retry-reason";
+pub const ORIGINAL_TOPIC: &str = "x-original-topic";
+pub const ORIGINAL_PARTITION: &str = "x-original-partition";
+pub const ORIGINAL_OFFSET: &str = "x-original-offset";
+pub const DEAD_LETTER_REASON: &str = "x-dead-letter-reason";
+
+/// The delays of each retry tier, and where messages go once they've all been tried.
+#[derive(Clone)]
+pub struct RetryTiers {
+    delays: Vec<Duration>,
+    max_retries: Option<u32>,
+    dead_letter_topic: Option<String>,
+    producer: TypedProducer,
+}
+impl RetryTiers {
+    /// Tiers of 5 seconds, 1 minute and 10 minutes, then `<topic>.dlq`. Failed messages are
+    /// republished with `producer`.
+    pub fn new(producer: TypedProducer) -> Self {
+        Self {
+            delays: vec![
+                Duration::from_secs(5),
+                Duration::from_secs(60),
+                Duration::from_secs(600),
+            ],
+            max_retries: None,
+            dead_letter_topic: None,
+            producer,
+        }
+    }
+    /// Replaces the tiers' delays, in the order they're tried. With none, failed messages go
+    /// straight to the dead-letter topic.
+    pub fn with_tiers(mut self, delays: impl IntoIterator<Item = Duration>) -> Self {
+        self.delays = delays.into_iter().collect();
+        self
+    }
+    /// How many times a message is retried before it's dead-lettered. Retries past the last
+    /// tier go through the last tier again.
+    ///
+    /// Default: one retry per tier
+    pub fn with_max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = Some(max_retries);
+        self
+    }
+    pub fn max_retries(&self) -> u32 {
+        self.max_retries
+            .unwrap_or(self.delays.len().try_into().unwrap_or(u32::MAX))
+    }
+    /// Default: `<topic>.dlq`
+    pub fn with_dead_letter_topic(mut self, topic: impl Into<String>) -> Self {
+        self.dead_letter_topic = Some(topic.into());
+        self
+    }
+    pub fn delays(&self) -> &[Duration] {
+        &self.delays
+    }
+    /// The topics retries of `topic` are republished to, with their delays, in the order
+    /// they're tried.
+    pub fn tier_topics(&self, topic: &str) -> Vec<(String, Duration)> {
+        self.delays
+            .iter()
+            .map(|&delay| (format!("{topic}.retry.{}", delay_label(delay)), delay))
+            .collect()
+    }
+    pub fn dead_letter_topic(&self, topic: &str) -> String {
+        self.dead_letter_topic
+            .clone()
+            .unwrap_or_else(|| format!("{topic}.dlq"))
+    }
+
+    /// Republishes a message that couldn't be handled to the next tier, or to the dead-letter
+    /// topic once it has been retried as many times as allowed.
+    pub(crate) async fn republish(
+        &self,
+        message: &BorrowedMessage<'_>,
+        err: RdkafkaExtError,
+    ) -> Result<(), RdkafkaExtError> {
+        let received = message.headers().map(HeaderMap::from_headers);
+        let received = received.unwrap_or_default();
+        let header = |name| {
+            received
+                .get_raw(name)
+                .and_then(|value| str::from_utf8(value).ok())
+        };
+
+        let original_topic = header(ORIGINAL_TOPIC)
+            .unwrap_or(message.topic())
+            .to_string();
+        let partition = message.partition().to_string();
+        let offset = message.offset().to_string();
+        let retries: u32 = header(RETRY_COUNT)
+            .and_then(|count| count.parse().ok())
+            .unwrap_or(0);
+
+        let mut headers = HeaderMap::new();
+        for (name, value) in received.iter() {
+            if ![
+                RETRY_COUNT,
+                RETRY_REASON,
+                ORIGINAL_TOPIC,
+                ORIGINAL_PARTITION,
+                ORIGINAL_OFFSET,
+            ]
+            .contains(&name)
+            {
+                headers.insert_raw(name, value.map(<[u8]>::to_vec));
+            }
+        }
+        let origin = [
+            (ORIGINAL_TOPIC, original_topic.as_str()),
+            (
+                ORIGINAL_PARTITION,
+                header(ORIGINAL_PARTITION).unwrap_or(&partition),
+            ),
+            (ORIGINAL_OFFSET, header(ORIGINAL_OFFSET).unwrap_or(&offset)),
+        ];
+        for (name, value) in origin {
+            headers.insert_raw(name, Some(value.as_bytes().to_vec()));
+        }
+
+        let tier = (retries as usize).min(self.delays.len().saturating_sub(1));
+        let next_tier = self.tier_topics(&original_topic).into_iter().nth(tier);
+        let topic = match next_tier {
+            Some((topic, _)) if retries < self.max_retries() => {
+                let count = (retries + 1).to_string();
+                headers.insert_raw(RETRY_COUNT, Some(count.into_bytes()));
+                headers.insert_raw(RETRY_REASON, Some(err.to_string().into_bytes()));
+                topic
+            }
+            _ => {
+                headers.insert_raw(RETRY_COUNT, Some(retries.to_string().into_bytes()));
+                headers.insert_raw(DEAD_LETTER_REASON, Some(err.to_string().into_bytes()));
+                self.dead_letter_topic(&original_topic)
+            }
+        };
+        let record = RawRecord::new(&topic, message.key(), message.payload())
+            .headers(headers.to_owned_headers());
+        self.producer.send_raw(record, Timeout::Never).await?;
+        Ok(())
+    }
+}
+
+/// Names a delay by its largest whole unit, e.g. `5s`, `1m` or `250ms`.
+fn delay_label(delay: Duration) -> String {
+    let millis = delay.as_millis();
+    match millis {
+        0 => "0s".to_string(),
+        _ if millis.is_multiple_of(3_600_000) => format!("{}h", millis / 3_600_000),
+        _ if millis.is_multiple_of(60_000) => format!("{}m", millis / 60_000),
+        _ if millis.is_multiple_of(1_000) => format!("{}s", millis / 1_000),
+        _ => format!("{millis}ms"),
+    }
+}
+
+/// A partition paused until its next message is due.
+struct ParkedPartition {
+    due: Instant,
+    topic: String,
+    partition: i32,
+    /// Where the partition was rewound to.
+    offset: i64,
+}
+
+/// The partitions a [`TypedConsumer`] has paused until their next retry is due. Kept on the
+/// consumer's context, so partitions are forgotten as soon as they're revoked: a partition
+/// assigned again later starts out unpaused.
+#[derive(Default)]
+pub(crate) struct ParkedPartitions {
+    parked: Mutex<Vec<ParkedPartition>>,
+}
+impl ParkedPartitions {
+    pub(crate) fn forget(&self, revoked: &TopicPartitionList) {
+        self.parked
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .retain(|parked| {
+                revoked
+                    .find_partition(&parked.topic, parked.partition)
+                    .is_none()
+            });
+    }
+}
+
+/// Which topics a [`TypedConsumer`] holds messages back on.
+pub(crate) struct RetrySchedule {
+    topic: String,
+    tiers: Vec<(String, Duration)>,
+}
+impl RetrySchedule {
+    /// The topic messages on a tier topic were first published to.
+    pub(crate) fn original_topic(&self, name: &str) -> Option<&str> {
+        self.tiers
+            .iter()
+            .any(|(tier, _)| tier == name)
+            .then_some(self.topic.as_str())
+    }
+    pub(crate) fn tier_topics(&self) -> impl Iterator<Item = &str> {
+        self.tiers.iter().map(|(topic, _)| topic.as_str())
+    }
+    /// When the earliest parked partition is due to be resumed.
+    pub(crate) fn next_due<C: ConsumerContext>(
+        &self,
+        consumer: &StreamConsumer<ConsumerExtContext<C>>,
+    ) -> Option<Instant> {
+        let parked = parked(consumer);
+        parked.iter().map(|parked| parked.due).min()
+    }
+    /// Resumes every parked partition that has come due.
+    pub(crate) fn resume_due<C: ConsumerContext>(
+        &self,
+        consumer: &StreamConsumer<ConsumerExtContext<C>>,
+    ) {
+        let now = Instant::now();
+        let mut due = TopicPartitionList::new();
+        parked(consumer).retain(|parked| match parked.due <= now {
+            true => {
+                due.add_partition(&parked.topic, parked.partition);
+                false
+            }
+            false => true,
+        });
+        if due.count() > 0 {
+            let _ = consumer.resume(&due);
+        }
+    }
+    /// Whether `message` has to wait for its tier's delay. If so, its partition is paused and
+    /// rewound to it, to be resumed once it's due.
+    pub(crate) fn hold_back<C: ConsumerContext>(
+        &self,
+        consumer: &StreamConsumer<ConsumerExtContext<C>>,
+        message: &BorrowedMessage<'_>,
+    ) -> Result<bool, KafkaError> {
+        let Some((_, delay)) = self.tiers.iter().find(|(tier, _)| tier == message.topic()) else {
+            return Ok(false);
+        };
+        let mut parked = parked(consumer);
+        // Messages fetched before the partition was paused are fetched again once it's resumed.
+        // Pausing and rewinding again makes sure that holds even if the pause was lost.
+        if let Some(existing) = parked.iter().find(|parked| {
+            parked.topic == message.topic() && parked.partition == message.partition()
+        }) {
+            pause_at(
+                consumer,
+                message.topic(),
+                message.partition(),
+                existing.offset,
+            )?;
+            return Ok(true);
+        }
+
+        let Some(published) = message.timestamp().to_millis() else {
+            return Ok(false);
+        };
+        let due = UNIX_EPOCH + Duration::from_millis(published.max(0) as u64) + *delay;
+        let Ok(wait) = due.duration_since(SystemTime::now()) else {
+            return Ok(false);
+        };
+
+        pause_at(
+            consumer,
+            message.topic(),
+            message.partition(),
+            message.offset(),
+        )?;
+        parked.push(ParkedPartition {
+            due: Instant::now() + wait,
+            topic: message.topic().to_string(),
+            partition: message.partition(),
+            offset: message.offset(),
+        });
+        Ok(true)
+    }
+}
+
+fn parked<C: ConsumerContext>(
+    consumer: &StreamConsumer<ConsumerExtContext<C>>,
+) -> MutexGuard<'_, Vec<ParkedPartition>> {
+    consumer
+        .context()
+        .parked
+        .parked
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
+}
+
+/// Pauses a partition and rewinds it to `offset`.
+fn pause_at<C: ConsumerContext>(
+    consumer: &StreamConsumer<C>,
+    topic: &str,
+    partition: i32,
+    offset: i64,
+) -> Result<(), KafkaError> {
+    let mut list = TopicPartitionList::new();
+    list.add_partition(topic, partition);
+    consumer.pause(&list)?;
+    consumer.seek(topic, partition, Offset::Offset(offset), Duration::ZERO)
+}
+
+impl<T: Topic> TypedConsumer<T> {
+    /// Consumes `topic` along with its retry tiers, sending messages that fail to the next tier
+    /// as [`PoisonPolicy::RetryTopics`] does. Messages on a tier topic are only received once
+    /// their tier's delay has passed since they were republished, and carry `topic` as their
+    /// typed topic.
+    ///
+    /// Waiting partitions are paused rather than held in memory, so other partitions keep
+    /// flowing. Use it with [`process_each`](Self::process_each) and `enable.auto.commit` off,
+    /// so a message is only committed once it has been handled or republished.
+    pub fn new_with_retry_tiers(
+        client_config: ClientConfig,
+        topic: T,
+        tiers: RetryTiers,
+    ) -> Result<Self, RdkafkaExtError> {
+        let name = topic.topic_string();
+        let schedule = RetrySchedule {
+            tiers: tiers.tier_topics(&name),
+            topic: name.clone(),
+        };
+        let consumer = Self::subscribe(
+            client_config,
+            Subscription::Topics(vec![(name, topic)]),
+            Some(schedule),
+            DefaultConsumerContext,
+        )?;
+        Ok(consumer.with_poison_policy(PoisonPolicy::RetryTopics(tiers)))
+    }
+}
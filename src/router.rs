@@ -0,0 +1,128 @@
+//! Consuming topics of different types with one consumer, handing each message to the handler
+//! registered for its topic.
+
+use std::{error::Error, marker::PhantomData};
+
+use futures::future::LocalBoxFuture;
+use rdkafka::{
+    consumer::{CommitMode, Consumer, StreamConsumer},
+    message::BorrowedMessage,
+    ClientConfig, Message,
+};
+
+use crate::{error::RdkafkaExtError, PoisonPolicy, Topic, TypedMessage};
+
+/// A handler for one topic, with its payload type erased.
+trait Route: Send + Sync {
+    fn topic_string(&self) -> &str;
+    /// Handles `message`, handing it back to be committed if the policy lets the router carry
+    /// on.
+    fn dispatch<'a>(
+        &'a self,
+        message: BorrowedMessage<'a>,
+        policy: &'a PoisonPolicy,
+    ) -> LocalBoxFuture<'a, Result<BorrowedMessage<'a>, RdkafkaExtError>>;
+}
+
+struct TypedRoute<T, F, E> {
+    topic_string: String,
+    topic: T,
+    handler: F,
+    error: PhantomData<fn() -> E>,
+}
+impl<T, F, E> Route for TypedRoute<T, F, E>
+where
+    T: Topic + Send + Sync,
+    F: AsyncFn(&TypedMessage<'_, T>) -> Result<(), E> + Send + Sync,
+    E: Into<Box<dyn Error + Send + Sync>>,
+{
+    fn topic_string(&self) -> &str {
+        &self.topic_string
+    }
+    fn dispatch<'a>(
+        &'a self,
+        message: BorrowedMessage<'a>,
+        policy: &'a PoisonPolicy,
+    ) -> LocalBoxFuture<'a, Result<BorrowedMessage<'a>, RdkafkaExtError>> {
+        Box::pin(async move {
+            let message = TypedMessage {
+                message,
+                topic: self.topic.clone(),
+            };
+            policy
+                .attempt(&message.message, async || (self.handler)(&message).await)
+                .await?;
+            Ok(message.message)
+        })
+    }
+}
+
+/// A small consumer framework: register a handler per topic with [`route`](Self::route), then
+/// [`run`](Self::run) subscribes to all of them and dispatches each message to its handler.
+pub struct TopicRouter {
+    client_config: ClientConfig,
+    routes: Vec<Box<dyn Route>>,
+    poison_policy: PoisonPolicy,
+}
+impl TopicRouter {
+    pub fn new(client_config: ClientConfig) -> Self {
+        Self {
+            client_config,
+            routes: Vec::new(),
+            poison_policy: PoisonPolicy::Fail,
+        }
+    }
+    /// Hands every message from `topic` to `handler`. Routing the same topic twice replaces the
+    /// first handler.
+    pub fn route<T, F, E>(mut self, topic: T, handler: F) -> Self
+    where
+        T: Topic + Send + Sync + 'static,
+        F: AsyncFn(&TypedMessage<'_, T>) -> Result<(), E> + Send + Sync + 'static,
+        E: Into<Box<dyn Error + Send + Sync>> + 'static,
+    {
+        let topic_string = topic.topic_string();
+        self.routes
+            .retain(|route| route.topic_string() != topic_string);
+        self.routes.push(Box::new(TypedRoute {
+            topic_string,
+            topic,
+            handler,
+            error: PhantomData,
+        }));
+        self
+    }
+    /// Sets what happens when a handler fails, as for
+    /// [`TypedConsumer::with_poison_policy`](crate::TypedConsumer::with_poison_policy).
+    ///
+    /// Default: [`PoisonPolicy::Fail`]
+    pub fn with_poison_policy(mut self, policy: PoisonPolicy) -> Self {
+        self.poison_policy = policy;
+        self
+    }
+    /// Subscribes to every routed topic and handles messages forever, committing each one once
+    /// its handler succeeds or the poison policy absorbs its failure. Returns on the first
+    /// error the policy doesn't absorb, with that message left uncommitted.
+    ///
+    /// Commits are asynchronous, and only make sense with `enable.auto.commit` off.
+    pub async fn run(self) -> Result<(), RdkafkaExtError> {
+        let consumer: StreamConsumer = self.client_config.create()?;
+        let topics: Vec<_> = self
+            .routes
+            .iter()
+            .map(|route| route.topic_string())
+            .collect();
+        consumer.subscribe(&topics)?;
+
+        loop {
+            let message = consumer.recv().await?;
+            let route = self
+                .routes
+                .iter()
+                .find(|route| route.topic_string() == message.topic())
+                .ok_or_else(|| RdkafkaExtError::UnknownTopic(message.topic().to_string()))?;
+
+            let message = route.dispatch(message, &self.poison_policy).await?;
+            consumer.commit_message(&message, CommitMode::Async)?;
+        }
+    }
+}
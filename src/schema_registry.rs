@@ -0,0 +1,370 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt,
+    sync::{Arc, RwLock},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::ErrorClass;
+
+/// The formats the Confluent Schema Registry knows how to store.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum SchemaType {
+    #[default]
+    Avro,
+    Protobuf,
+    Json,
+}
+
+/// How the registry subject for a topic's schema is named, mirroring the Java serializers'
+/// `*.subject.name.strategy` settings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SubjectNameStrategy {
+    /// `<topic>-key` or `<topic>-value`.
+    #[default]
+    TopicName,
+    /// The fully qualified record name.
+    RecordName,
+    /// `<topic>-<fully qualified record name>`.
+    TopicRecordName,
+}
+impl SubjectNameStrategy {
+    /// The subject to use, or `None` if the strategy needs a record name and none was given.
+    pub fn subject(&self, topic: &str, record_name: Option<&str>, is_key: bool) -> Option<String> {
+        match (self, record_name) {
+            (SubjectNameStrategy::TopicName, _) => {
+                let suffix = if is_key { "key" } else { "value" };
+                Some(format!("{topic}-{suffix}"))
+            }
+            (SubjectNameStrategy::RecordName, Some(record_name)) => Some(record_name.to_string()),
+            (SubjectNameStrategy::TopicRecordName, Some(record_name)) => {
+                Some(format!("{topic}-{record_name}"))
+            }
+            (_, None) => None,
+        }
+    }
+}
+
+/// A schema as stored in the registry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegisteredSchema {
+    pub id: u32,
+    pub schema: String,
+    pub schema_type: SchemaType,
+}
+
+/// One version of a schema under a subject.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SubjectVersion {
+    pub subject: String,
+    pub version: i32,
+    pub id: u32,
+    pub schema: String,
+    #[serde(default)]
+    pub schema_type: SchemaType,
+}
+
+#[derive(Debug)]
+pub enum SchemaRegistryError {
+    /// The registry couldn't be reached, or sent back something unreadable.
+    Http(reqwest::Error),
+    /// The registry answered with an error.
+    Registry {
+        status: u16,
+        error_code: i64,
+        message: String,
+    },
+    /// The subject name strategy needs a record name, but the schema doesn't have one.
+    NoSubject(SubjectNameStrategy),
+}
+impl fmt::Display for SchemaRegistryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SchemaRegistryError::Http(err) => err.fmt(f),
+            SchemaRegistryError::Registry {
+                status,
+                error_code,
+                message,
+            } => write!(
+                f,
+                "schema registry returned {status} (error code {error_code}): {message}"
+            ),
+            SchemaRegistryError::NoSubject(strategy) => {
+                write!(f, "{strategy:?} subject naming needs a named schema")
+            }
+        }
+    }
+}
+impl Error for SchemaRegistryError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SchemaRegistryError::Http(err) => err.source(),
+            SchemaRegistryError::Registry { .. } | SchemaRegistryError::NoSubject(_) => None,
+        }
+    }
+}
+impl SchemaRegistryError {
+    pub fn class(&self) -> ErrorClass {
+        match self {
+            SchemaRegistryError::Http(err) if err.is_decode() => ErrorClass::Serialization,
+            SchemaRegistryError::Http(_) => ErrorClass::Retryable,
+            SchemaRegistryError::Registry {
+                status: 401 | 403, ..
+            } => ErrorClass::Authorization,
+            SchemaRegistryError::Registry { status, .. } if *status >= 500 => ErrorClass::Retryable,
+            SchemaRegistryError::Registry { .. } | SchemaRegistryError::NoSubject(_) => {
+                ErrorClass::Fatal
+            }
+        }
+    }
+}
+impl From<reqwest::Error> for SchemaRegistryError {
+    fn from(err: reqwest::Error) -> Self {
+        SchemaRegistryError::Http(err)
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SchemaRequest<'a> {
+    schema: &'a str,
+    schema_type: SchemaType,
+}
+
+#[derive(Deserialize)]
+struct IdResponse {
+    id: u32,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SchemaResponse {
+    schema: String,
+    #[serde(default)]
+    schema_type: SchemaType,
+}
+
+#[derive(Deserialize)]
+struct CompatibilityResponse {
+    is_compatible: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ErrorResponse {
+    error_code: i64,
+    message: String,
+}
+
+/// A client for the Confluent Schema Registry REST API. Schemas are cached by id as they're
+/// fetched or registered, so codecs can look them up without going back to the registry.
+pub struct SchemaRegistryClient {
+    http: reqwest::Client,
+    base_url: String,
+    cache: RwLock<HashMap<u32, Arc<RegisteredSchema>>>,
+}
+impl SchemaRegistryClient {
+    pub fn new(base_url: impl Into<String>) -> Self {
+        Self::with_http_client(base_url, reqwest::Client::new())
+    }
+    /// Uses a preconfigured HTTP client, e.g. one with auth headers or custom TLS roots.
+    pub fn with_http_client(base_url: impl Into<String>, http: reqwest::Client) -> Self {
+        Self {
+            http,
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            cache: RwLock::default(),
+        }
+    }
+    /// Registers `schema` under `subject`, returning its id. Registering a schema that is
+    /// already registered returns the existing id.
+    pub async fn register(
+        &self,
+        subject: &str,
+        schema: &str,
+        schema_type: SchemaType,
+    ) -> Result<u32, SchemaRegistryError> {
+        let subject = encode_path_segment(subject);
+        let response = self
+            .http
+            .post(format!("{}/subjects/{subject}/versions", self.base_url))
+            .json(&SchemaRequest {
+                schema,
+                schema_type,
+            })
+            .send()
+            .await?;
+        let IdResponse { id } = Self::parse(response).await?;
+
+        self.insert(RegisteredSchema {
+            id,
+            schema: schema.to_string(),
+            schema_type,
+        });
+        Ok(id)
+    }
+    /// Fetches the schema with the given id, from the cache if possible.
+    pub async fn schema_by_id(
+        &self,
+        id: u32,
+    ) -> Result<Arc<RegisteredSchema>, SchemaRegistryError> {
+        if let Some(schema) = self.cached_schema(id) {
+            return Ok(schema);
+        }
+
+        let response = self
+            .http
+            .get(format!("{}/schemas/ids/{id}", self.base_url))
+            .send()
+            .await?;
+        let SchemaResponse {
+            schema,
+            schema_type,
+        } = Self::parse(response).await?;
+
+        Ok(self.insert(RegisteredSchema {
+            id,
+            schema,
+            schema_type,
+        }))
+    }
+    /// Fetches every version registered under `subject` into the cache, so records written with
+    /// older schemas can be decoded without a registry round trip.
+    pub async fn prefetch_subject(&self, subject: &str) -> Result<(), SchemaRegistryError> {
+        let subject = encode_path_segment(subject);
+        let response = self
+            .http
+            .get(format!("{}/subjects/{subject}/versions", self.base_url))
+            .send()
+            .await?;
+        let versions: Vec<i32> = Self::parse(response).await?;
+
+        for version in versions {
+            let response = self
+                .http
+                .get(format!(
+                    "{}/subjects/{subject}/versions/{version}",
+                    self.base_url
+                ))
+                .send()
+                .await?;
+            self.insert_version(Self::parse(response).await?);
+        }
+        Ok(())
+    }
+    /// Fetches the latest version registered under `subject`.
+    pub async fn latest(&self, subject: &str) -> Result<SubjectVersion, SchemaRegistryError> {
+        let subject = encode_path_segment(subject);
+        let response = self
+            .http
+            .get(format!(
+                "{}/subjects/{subject}/versions/latest",
+                self.base_url
+            ))
+            .send()
+            .await?;
+        let version: SubjectVersion = Self::parse(response).await?;
+
+        self.insert_version(version.clone());
+        Ok(version)
+    }
+    /// Checks whether `schema` could be registered under `subject` given the subject's
+    /// compatibility level.
+    pub async fn is_compatible(
+        &self,
+        subject: &str,
+        schema: &str,
+        schema_type: SchemaType,
+    ) -> Result<bool, SchemaRegistryError> {
+        let subject = encode_path_segment(subject);
+        let response = self
+            .http
+            .post(format!(
+                "{}/compatibility/subjects/{subject}/versions/latest",
+                self.base_url
+            ))
+            .json(&SchemaRequest {
+                schema,
+                schema_type,
+            })
+            .send()
+            .await?;
+        let CompatibilityResponse { is_compatible } = Self::parse(response).await?;
+
+        Ok(is_compatible)
+    }
+    /// Looks up a schema that has already been fetched or registered through this client.
+    pub fn cached_schema(&self, id: u32) -> Option<Arc<RegisteredSchema>> {
+        self.cache
+            .read()
+            .unwrap_or_else(|err| err.into_inner())
+            .get(&id)
+            .cloned()
+    }
+
+    fn insert(&self, schema: RegisteredSchema) -> Arc<RegisteredSchema> {
+        let schema = Arc::new(schema);
+        self.cache
+            .write()
+            .unwrap_or_else(|err| err.into_inner())
+            .insert(schema.id, schema.clone());
+        schema
+    }
+    fn insert_version(&self, version: SubjectVersion) {
+        self.insert(RegisteredSchema {
+            id: version.id,
+            schema: version.schema,
+            schema_type: version.schema_type,
+        });
+    }
+    async fn parse<R: serde::de::DeserializeOwned>(
+        response: reqwest::Response,
+    ) -> Result<R, SchemaRegistryError> {
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response.json().await?);
+        }
+
+        let (error_code, message) = match response.json::<ErrorResponse>().await {
+            Ok(ErrorResponse {
+                error_code,
+                message,
+            }) => (error_code, message),
+            Err(_) => (status.as_u16().into(), status.to_string()),
+        };
+        Err(SchemaRegistryError::Registry {
+            status: status.as_u16(),
+            error_code,
+            message,
+        })
+    }
+}
+
+/// Percent-encodes a subject for use as one URL path segment, since subjects are free-form and
+/// may contain `/`, `?` or spaces.
+fn encode_path_segment(segment: &str) -> String {
+    let mut encoded = String::with_capacity(segment.len());
+    for byte in segment.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(byte as char)
+            }
+            byte => encoded.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_subjects_as_one_path_segment() {
+        assert_eq!(encode_path_segment("orders-value"), "orders-value");
+        assert_eq!(encode_path_segment("a/b c?d%é"), "a%2Fb%20c%3Fd%25%C3%A9");
+    }
+}
@@ -0,0 +1,275 @@
+//! Typed snapshots of the statistics librdkafka reports every `statistics.interval.ms`.
+
+use std::{
+    error::Error,
+    sync::{Arc, Mutex, MutexGuard},
+    time::Duration,
+};
+
+use futures::channel::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use rdkafka::{
+    client::OAuthToken,
+    config::RDKafkaLogLevel,
+    error::KafkaError,
+    statistics::{Broker, Partition, Statistics},
+    ClientContext,
+};
+use tokio::sync::Notify;
+
+/// How one broker connection is doing.
+#[derive(Debug, Clone)]
+pub struct BrokerStats {
+    pub name: String,
+    /// The connection state, e.g. `UP` or `DOWN`.
+    pub state: String,
+    /// Requests sent to the broker and awaiting a response.
+    pub awaiting_response: i64,
+    /// Average round-trip time over the last window.
+    pub rtt_avg: Duration,
+    /// 99th percentile round-trip time over the last window.
+    pub rtt_p99: Duration,
+}
+impl BrokerStats {
+    fn new(broker: &Broker) -> Self {
+        let micros = |value: i64| Duration::from_micros(value.max(0) as u64);
+        let rtt = broker.rtt.as_ref();
+
+        Self {
+            name: broker.name.clone(),
+            state: broker.state.clone(),
+            awaiting_response: broker.waitresp_cnt,
+            rtt_avg: micros(rtt.map_or(0, |rtt| rtt.avg)),
+            rtt_p99: micros(rtt.map_or(0, |rtt| rtt.p99)),
+        }
+    }
+}
+
+/// One statistics report from a producer.
+#[derive(Debug, Clone)]
+pub struct ProducerStats {
+    /// Records waiting in the producer queue, including those in flight.
+    pub queued_records: u64,
+    pub queued_bytes: u64,
+    /// Records sent to a broker and not yet acknowledged.
+    pub in_flight_records: i64,
+    /// Records sent to brokers since the producer was created.
+    pub sent_records: i64,
+    /// Records sent per second since the previous report, or zero for the first one.
+    pub send_rate: f64,
+    pub brokers: Vec<BrokerStats>,
+    /// Everything librdkafka reported.
+    pub raw: Statistics,
+}
+impl Report for ProducerStats {
+    fn new(raw: Statistics, previous: Option<&ProducerStats>) -> Self {
+        let send_rate = previous.map_or(0.0, |previous| {
+            let elapsed = (raw.ts - previous.raw.ts) as f64 / 1_000_000.0;
+            if elapsed > 0.0 {
+                (raw.txmsgs - previous.raw.txmsgs) as f64 / elapsed
+            } else {
+                0.0
+            }
+        });
+        let mut brokers: Vec<_> = raw.brokers.values().map(BrokerStats::new).collect();
+        brokers.sort_unstable_by(|a, b| a.name.cmp(&b.name));
+
+        Self {
+            queued_records: raw.msg_cnt,
+            queued_bytes: raw.msg_size,
+            in_flight_records: raw
+                .brokers
+                .values()
+                .map(|broker| broker.waitresp_msg_cnt)
+                .sum(),
+            sent_records: raw.txmsgs,
+            send_rate,
+            brokers,
+            raw,
+        }
+    }
+}
+
+/// How one assigned partition is doing.
+#[derive(Debug, Clone)]
+pub struct PartitionStats {
+    pub topic: String,
+    pub partition: i32,
+    /// How far the consumer is behind the end of the partition, if known yet.
+    pub lag: Option<i64>,
+    /// Records fetched and waiting to be received.
+    pub queued_records: i64,
+    pub queued_bytes: u64,
+    /// Where fetching is at, e.g. `active` or `stopped`.
+    pub fetch_state: String,
+    /// The group's committed offset, if there is one.
+    pub committed_offset: Option<i64>,
+}
+impl PartitionStats {
+    fn new(topic: &str, partition: &Partition) -> Self {
+        let known = |value: i64| (value >= 0).then_some(value);
+
+        Self {
+            topic: topic.to_string(),
+            partition: partition.partition,
+            lag: known(partition.consumer_lag),
+            queued_records: partition.fetchq_cnt,
+            queued_bytes: partition.fetchq_size,
+            fetch_state: partition.fetch_state.clone(),
+            committed_offset: known(partition.committed_offset),
+        }
+    }
+}
+
+/// One statistics report from a consumer. Fetch latency shows up as the brokers' round-trip
+/// times.
+#[derive(Debug, Clone)]
+pub struct ConsumerStats {
+    /// Lag summed over every partition it's known for.
+    pub lag: i64,
+    /// Records fetched and waiting to be received, over every partition.
+    pub queued_records: i64,
+    pub queued_bytes: u64,
+    /// Records received from brokers since the consumer was created.
+    pub received_records: i64,
+    /// Records received per second since the previous report, or zero for the first one.
+    pub receive_rate: f64,
+    pub partitions: Vec<PartitionStats>,
+    pub brokers: Vec<BrokerStats>,
+    /// Everything librdkafka reported.
+    pub raw: Statistics,
+}
+impl Report for ConsumerStats {
+    fn new(raw: Statistics, previous: Option<&ConsumerStats>) -> Self {
+        let receive_rate = previous.map_or(0.0, |previous| {
+            let elapsed = (raw.ts - previous.raw.ts) as f64 / 1_000_000.0;
+            if elapsed > 0.0 {
+                (raw.rxmsgs - previous.raw.rxmsgs) as f64 / elapsed
+            } else {
+                0.0
+            }
+        });
+        // Partition -1 holds records not yet assigned a partition, and partitions that aren't
+        // desired are only known from metadata.
+        let mut partitions: Vec<_> = raw
+            .topics
+            .values()
+            .flat_map(|topic| {
+                topic
+                    .partitions
+                    .values()
+                    .filter(|partition| partition.partition >= 0 && partition.desired)
+                    .map(|partition| PartitionStats::new(&topic.topic, partition))
+            })
+            .collect();
+        partitions.sort_unstable_by(|a, b| (&a.topic, a.partition).cmp(&(&b.topic, b.partition)));
+        let mut brokers: Vec<_> = raw.brokers.values().map(BrokerStats::new).collect();
+        brokers.sort_unstable_by(|a, b| a.name.cmp(&b.name));
+
+        Self {
+            lag: partitions
+                .iter()
+                .filter_map(|partition| partition.lag)
+                .sum(),
+            queued_records: partitions
+                .iter()
+                .map(|partition| partition.queued_records)
+                .sum(),
+            queued_bytes: partitions
+                .iter()
+                .map(|partition| partition.queued_bytes)
+                .sum(),
+            received_records: raw.rxmsgs,
+            receive_rate,
+            partitions,
+            brokers,
+            raw,
+        }
+    }
+}
+
+/// A typed report built from librdkafka's statistics, and the one before it.
+pub(crate) trait Report: Clone {
+    fn new(raw: Statistics, previous: Option<&Self>) -> Self;
+}
+
+struct Reports<S> {
+    latest: Option<S>,
+    listeners: Vec<UnboundedSender<S>>,
+}
+
+/// The latest statistics report and everyone waiting for the next one. Clones share reports.
+#[derive(Clone)]
+pub(crate) struct StatsReports<S = ProducerStats> {
+    reports: Arc<Mutex<Reports<S>>>,
+}
+impl<S> Default for StatsReports<S> {
+    fn default() -> Self {
+        Self {
+            reports: Arc::new(Mutex::new(Reports {
+                latest: None,
+                listeners: Vec::new(),
+            })),
+        }
+    }
+}
+impl<S: Report> StatsReports<S> {
+    pub(crate) fn latest(&self) -> Option<S> {
+        self.lock().latest.clone()
+    }
+    pub(crate) fn subscribe(&self) -> UnboundedReceiver<S> {
+        let (sender, receiver) = mpsc::unbounded();
+        self.lock().listeners.push(sender);
+        receiver
+    }
+    pub(crate) fn record(&self, statistics: Statistics) {
+        let mut reports = self.lock();
+        let stats = S::new(statistics, reports.latest.as_ref());
+
+        reports
+            .listeners
+            .retain(|listener| listener.unbounded_send(stats.clone()).is_ok());
+        reports.latest = Some(stats);
+    }
+    fn lock(&self) -> MutexGuard<'_, Reports<S>> {
+        self.reports.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+/// The producer's client context: records statistics reports, then hands every callback on to
+/// the caller's context.
+pub(crate) struct StatsContext<C> {
+    reports: StatsReports,
+    context: Arc<C>,
+    /// Notified with every report, since it updates the producer's queued bytes.
+    reported: Arc<Notify>,
+}
+impl<C> StatsContext<C> {
+    pub(crate) fn new(reports: StatsReports, context: Arc<C>, reported: Arc<Notify>) -> Self {
+        Self {
+            reports,
+            context,
+            reported,
+        }
+    }
+}
+impl<C: ClientContext> ClientContext for StatsContext<C> {
+    const ENABLE_REFRESH_OAUTH_TOKEN: bool = C::ENABLE_REFRESH_OAUTH_TOKEN;
+
+    fn log(&self, level: RDKafkaLogLevel, fac: &str, log_message: &str) {
+        self.context.log(level, fac, log_message);
+    }
+    fn stats(&self, statistics: Statistics) {
+        self.reports.record(statistics.clone());
+        self.reported.notify_waiters();
+        self.context.stats(statistics);
+    }
+    fn error(&self, error: KafkaError, reason: &str) {
+        self.context.error(error, reason);
+    }
+    fn generate_oauth_token(
+        &self,
+        oauthbearer_config: Option<&str>,
+    ) -> Result<OAuthToken, Box<dyn Error>> {
+        self.context.generate_oauth_token(oauthbearer_config)
+    }
+}
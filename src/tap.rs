@@ -0,0 +1,90 @@
+//! Watching a sample of what a [`TypedConsumer`] receives, e.g. to debug a live pipeline,
+//! without changing what it consumes or commits.
+
+use std::sync::Mutex;
+
+use futures::channel::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use rdkafka::{
+    consumer::ConsumerContext,
+    message::{BorrowedMessage, OwnedMessage},
+    Message,
+};
+
+use crate::{GenericTypedMessage, OwnedTypedMessage, Topic, TypedConsumer};
+
+struct Tap<T> {
+    sender: UnboundedSender<OwnedTypedMessage<T>>,
+    sample_rate: f64,
+    /// How far along to the next sampled message; one is sent each time this reaches 1.
+    credit: f64,
+}
+
+/// The taps attached to a consumer.
+pub(crate) struct Taps<T> {
+    taps: Mutex<Vec<Tap<T>>>,
+}
+impl<T> Default for Taps<T> {
+    fn default() -> Self {
+        Self {
+            taps: Mutex::default(),
+        }
+    }
+}
+impl<T: Clone> Taps<T> {
+    /// Sends a copy of the message to every tap whose turn it is, dropping taps nobody is
+    /// listening to any more.
+    pub(crate) fn offer(&self, message: &BorrowedMessage<'_>, topic: impl FnOnce() -> Option<T>) {
+        let mut taps = self.taps.lock().unwrap_or_else(|err| err.into_inner());
+        if taps.is_empty() {
+            return;
+        }
+        let mut copy: Option<Option<(OwnedMessage, T)>> = None;
+        let mut topic = Some(topic);
+        taps.retain_mut(|tap| {
+            tap.credit += tap.sample_rate;
+            if tap.credit < 1.0 {
+                return !tap.sender.is_closed();
+            }
+            tap.credit -= 1.0;
+            let copy = copy.get_or_insert_with(|| {
+                let topic = topic.take().and_then(|topic| topic())?;
+                Some((message.detach(), topic))
+            });
+            let Some((message, topic)) = copy else {
+                return true;
+            };
+            let sampled = GenericTypedMessage {
+                message: message.clone(),
+                topic: topic.clone(),
+            };
+            tap.sender.unbounded_send(sampled).is_ok()
+        });
+    }
+}
+
+impl<T: Topic, C: ConsumerContext + 'static> TypedConsumer<T, C> {
+    /// Receives a copy of a `sample_rate` share of the messages received from now on, from 0
+    /// for none to 1 for all, spread evenly: 0.01 copies every hundredth message. Messages the
+    /// consumer skips aren't copied.
+    ///
+    /// Copies are owned, so they can be held and decoded at leisure, and what happens to them
+    /// doesn't affect the consumer's offsets. The tap is dropped along with its receiver.
+    pub fn tap(&self, sample_rate: f64) -> UnboundedReceiver<OwnedTypedMessage<T>> {
+        let (sender, receiver) = mpsc::unbounded();
+        self.taps
+            .taps
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .push(Tap {
+                sender,
+                sample_rate: sample_rate.clamp(0.0, 1.0),
+                credit: 0.0,
+            });
+        receiver
+    }
+    /// Offers a received message to the taps.
+    pub(crate) fn tap_message(&self, message: &BorrowedMessage<'_>) {
+        self.taps
+            .offer(message, || self.topic_for(message.topic()).ok());
+    }
+}
@@ -0,0 +1,78 @@
+//! W3C trace context propagation, so distributed traces carry on across Kafka hops.
+//!
+//! With the `otel` feature, every [`TypedProducer`](crate::TypedProducer) writes the current
+//! OpenTelemetry span as `traceparent` and `tracestate` headers, and
+//! [`TypedMessage::trace_context`](crate::TypedMessage::trace_context) reads them back as the
+//! remote parent of whatever span handles the message.
+
+use std::str::FromStr;
+
+use opentelemetry::{
+    trace::{SpanContext, SpanId, TraceContextExt, TraceFlags, TraceId, TraceState},
+    Context,
+};
+use rdkafka::types::RDKafkaErrorCode;
+
+use crate::interceptor::{OutgoingRecord, ProducerInterceptor};
+
+pub const TRACEPARENT: &str = "traceparent";
+pub const TRACESTATE: &str = "tracestate";
+
+/// Writes the current span's context on records that don't already carry one. Added to every
+/// producer by default.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TraceContextInterceptor;
+impl ProducerInterceptor for TraceContextInterceptor {
+    fn on_send(&self, record: &mut OutgoingRecord<'_>) -> Result<(), RDKafkaErrorCode> {
+        let context = Context::current();
+        let span = context.span();
+        let span_context = span.span_context();
+        if !span_context.is_valid() || record.headers.get_raw(TRACEPARENT).is_some() {
+            return Ok(());
+        }
+
+        let traceparent = format!(
+            "00-{:032x}-{:016x}-{:02x}",
+            span_context.trace_id(),
+            span_context.span_id(),
+            span_context.trace_flags()
+        );
+        record
+            .headers
+            .insert_raw(TRACEPARENT, Some(traceparent.into_bytes()));
+        let tracestate = span_context.trace_state().header();
+        if !tracestate.is_empty() {
+            record
+                .headers
+                .insert_raw(TRACESTATE, Some(tracestate.into_bytes()));
+        }
+        Ok(())
+    }
+}
+
+/// Parses `traceparent` and `tracestate` header values into a remote span context, or returns
+/// `None` if `traceparent` is malformed.
+pub(crate) fn extract(traceparent: &str, tracestate: Option<&str>) -> Option<Context> {
+    let mut parts = traceparent.trim().split('-');
+    let version = parts.next().filter(|version| version.len() == 2)?;
+    let trace_id = parts.next().filter(|id| id.len() == 32)?;
+    let span_id = parts.next().filter(|id| id.len() == 16)?;
+    let flags = parts.next().filter(|flags| flags.len() == 2)?;
+    // Later versions may add fields, but version 00 has exactly four.
+    if version == "ff" || (version == "00" && parts.next().is_some()) {
+        return None;
+    }
+
+    let span_context = SpanContext::new(
+        TraceId::from_hex(trace_id).ok()?,
+        SpanId::from_hex(span_id).ok()?,
+        TraceFlags::new(u8::from_str_radix(flags, 16).ok()? & TraceFlags::SAMPLED.to_u8()),
+        true,
+        tracestate
+            .and_then(|state| TraceState::from_str(state).ok())
+            .unwrap_or_default(),
+    );
+    span_context
+        .is_valid()
+        .then(|| Context::new().with_remote_span_context(span_context))
+}
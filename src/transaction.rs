@@ -0,0 +1,210 @@
+use std::future::Future;
+
+use futures::future;
+use rdkafka::{
+    client::DefaultClientContext, consumer::ConsumerGroupMetadata, error::KafkaError,
+    producer::Producer, util::Timeout, ClientConfig, ClientContext, TopicPartitionList,
+};
+
+use crate::{
+    error::{RdkafkaExtError, SendError, SerializeError},
+    Delivery, EncodedRecord, Topic, TypedProducer,
+};
+
+/// How many times a commit that failed with a retriable error is tried again before the
+/// transaction is aborted.
+const COMMIT_RETRIES: usize = 2;
+
+/// A producer whose sends, and optionally consumer offset commits, happen in transactions that
+/// become visible all at once or not at all.
+///
+/// The transaction calls (`begin`, `commit`, `abort` and `send_offsets`) are synchronous in
+/// librdkafka: they block the calling thread, async task included, until the broker answers or
+/// `timeout` runs out. On a shared runtime keep `timeout` short, or drive transactions from a
+/// blocking thread, e.g. with tokio's `spawn_blocking`.
+pub struct TypedTransactionalProducer<C: ClientContext + 'static = DefaultClientContext> {
+    producer: TypedProducer<C>,
+    timeout: Timeout,
+}
+impl TypedTransactionalProducer {
+    /// Creates the producer and registers its `transactional.id` with the broker, fencing off
+    /// older producers with the same id.
+    pub fn new(config: ClientConfig, timeout: impl Into<Timeout>) -> Result<Self, RdkafkaExtError> {
+        Self::new_with_context(config, DefaultClientContext, timeout)
+    }
+}
+impl<C: ClientContext + 'static> TypedTransactionalProducer<C> {
+    /// Like [`new`](TypedTransactionalProducer::new), but with a client context of your own, as
+    /// in [`TypedProducer::new_with_context`].
+    pub fn new_with_context(
+        config: ClientConfig,
+        context: C,
+        timeout: impl Into<Timeout>,
+    ) -> Result<Self, RdkafkaExtError> {
+        let producer = TypedProducer::new_with_context(config, context)?;
+        let timeout = timeout.into();
+        producer.inner.init_transactions(timeout)?;
+
+        Ok(Self { producer, timeout })
+    }
+    pub fn context(&self) -> &C {
+        self.producer.context()
+    }
+    /// Starts a transaction. Sends made through the returned handle are part of it until it is
+    /// committed or aborted.
+    pub fn begin(&self) -> Result<Transaction<C>, RdkafkaExtError> {
+        self.producer.inner.begin_transaction()?;
+
+        Ok(Transaction {
+            producer: self.producer.clone(),
+            timeout: self.timeout,
+        })
+    }
+    pub(crate) fn timeout(&self) -> Timeout {
+        self.timeout
+    }
+    /// Runs `f` in a transaction, committing it if `f` succeeds and aborting it if `f` or the
+    /// commit fails. Commits that fail with a retriable error are retried a couple of times
+    /// first. If aborting fails too, the error from `f` or the commit is the one returned.
+    pub async fn transaction<F, Fut, R>(&self, f: F) -> Result<R, RdkafkaExtError>
+    where
+        F: FnOnce(Transaction<C>) -> Fut,
+        Fut: Future<Output = Result<R, RdkafkaExtError>>,
+    {
+        let transaction = self.begin()?;
+        let result = match f(transaction.clone()).await {
+            Ok(result) => result,
+            Err(err) => {
+                let _ = transaction.abort();
+                return Err(err);
+            }
+        };
+        transaction.commit()?;
+        Ok(result)
+    }
+}
+
+/// A handle to an open transaction.
+pub struct Transaction<C: ClientContext + 'static = DefaultClientContext> {
+    producer: TypedProducer<C>,
+    timeout: Timeout,
+}
+impl<C: ClientContext + 'static> Clone for Transaction<C> {
+    fn clone(&self) -> Self {
+        Self {
+            producer: self.producer.clone(),
+            timeout: self.timeout,
+        }
+    }
+}
+impl<C: ClientContext + 'static> Transaction<C> {
+    pub async fn send<T: Topic>(
+        &self,
+        topic: &T,
+        payload: &T::Payload,
+        key: Option<&T::Key>,
+    ) -> Result<Delivery, SendError> {
+        self.producer
+            .send_with_timeout(topic, payload, key, self.timeout)
+            .await
+    }
+    /// Commits consumer offsets as part of the transaction, so they only advance if it commits.
+    pub fn send_offsets(
+        &self,
+        offsets: &TopicPartitionList,
+        group: &ConsumerGroupMetadata,
+    ) -> Result<(), RdkafkaExtError> {
+        Ok(self
+            .producer
+            .inner
+            .send_offsets_to_transaction(offsets, group, self.timeout)?)
+    }
+    /// Commits the transaction. Retriable failures are retried a couple of times; if the
+    /// commit still fails, the transaction is aborted, so a new one can be begun, and the
+    /// commit's error is returned.
+    pub fn commit(&self) -> Result<(), RdkafkaExtError> {
+        Ok(self.producer.commit_or_abort(self.timeout)?)
+    }
+    pub fn abort(&self) -> Result<(), RdkafkaExtError> {
+        Ok(self.producer.inner.abort_transaction(self.timeout)?)
+    }
+}
+
+/// A record serialized for its topic, so records for topics with different payload types can be
+/// sent together by [`TypedProducer::send_all_transactional`].
+pub struct TransactionRecord(EncodedRecord);
+impl TransactionRecord {
+    pub fn new<T: Topic>(
+        topic: &T,
+        payload: &T::Payload,
+        key: Option<&T::Key>,
+    ) -> Result<Self, SerializeError> {
+        EncodedRecord::new(topic, payload, key).map(Self)
+    }
+    pub fn topic(&self) -> &str {
+        &self.0.topic
+    }
+}
+
+impl<C: ClientContext + 'static> TypedProducer<C> {
+    /// Sends `records`, which may be for any number of topics, in a single transaction, so
+    /// either all of them become visible to `read_committed` consumers or none do. Deliveries
+    /// come back in the order the records were given.
+    ///
+    /// The producer needs a `transactional.id`. Transactions are initialized on the first call,
+    /// and calls on clones of the same producer wait for each other, since only one transaction
+    /// can be open at a time. The transaction calls block the current thread for up to the
+    /// [`default_timeout`](Self::default_timeout) each, as in [`TypedTransactionalProducer`].
+    /// If the sends or the commit fail, the transaction is aborted and their error returned.
+    pub async fn send_all_transactional(
+        &self,
+        records: impl IntoIterator<Item = TransactionRecord>,
+    ) -> Result<Vec<Delivery>, RdkafkaExtError> {
+        let mut initialized = self.transactions.lock().await;
+        let timeout = self.default_timeout;
+        if !*initialized {
+            self.inner.init_transactions(timeout)?;
+            *initialized = true;
+        }
+        self.inner.begin_transaction()?;
+
+        let records: Vec<_> = records.into_iter().collect();
+        let pending: Result<Vec<_>, _> = records
+            .iter()
+            .map(|record| self.enqueue_raw(record.0.record()))
+            .collect();
+        let deliveries = match pending {
+            Ok(pending) => future::try_join_all(pending).await,
+            Err(err) => Err(err),
+        };
+        let deliveries = match deliveries {
+            Ok(deliveries) => deliveries,
+            Err(err) => {
+                let _ = self.inner.abort_transaction(timeout);
+                return Err(err.into());
+            }
+        };
+
+        self.commit_or_abort(timeout)?;
+        Ok(deliveries)
+    }
+    /// Commits the open transaction, retrying retriable failures a couple of times, and aborts
+    /// it if the commit still fails. The commit's error is returned even if aborting fails too.
+    pub(crate) fn commit_or_abort(&self, timeout: Timeout) -> Result<(), KafkaError> {
+        let mut retries = COMMIT_RETRIES;
+        loop {
+            match self.inner.commit_transaction(timeout) {
+                Ok(()) => return Ok(()),
+                Err(KafkaError::Transaction(err))
+                    if retries > 0 && err.is_retriable() && !err.txn_requires_abort() =>
+                {
+                    retries -= 1;
+                }
+                Err(err) => {
+                    let _ = self.inner.abort_transaction(timeout);
+                    return Err(err);
+                }
+            }
+        }
+    }
+}